@@ -0,0 +1,142 @@
+use cgmath::Vector3;
+
+use std::f32;
+
+use ray::Ray;
+
+/// Axis aligned bounding box, stored as its two opposite corners
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> Aabb {
+        Aabb { min, max }
+    }
+
+    /// Box containing nothing, growing it by anything gives a box around just that
+    pub fn empty() -> Aabb {
+        Aabb {
+            min: Vector3::new(f32::MAX, f32::MAX, f32::MAX),
+            max: Vector3::new(-f32::MAX, -f32::MAX, -f32::MAX),
+        }
+    }
+
+    /// Box of the space inside both boxes, it's inside out if they don't overlap
+    pub fn intersection(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Vector3::new(
+                self.min.x.max(other.min.x),
+                self.min.y.max(other.min.y),
+                self.min.z.max(other.min.z),
+            ),
+            max: Vector3::new(
+                self.max.x.min(other.max.x),
+                self.max.y.min(other.max.y),
+                self.max.z.min(other.max.z),
+            ),
+        }
+    }
+
+    /// The same box moved by `offset`
+    pub fn offset(&self, offset: Vector3<f32>) -> Aabb {
+        Aabb {
+            min: self.min + offset,
+            max: self.max + offset,
+        }
+    }
+
+    /// Box grown by `distance` on every side
+    pub fn expand(&self, distance: f32) -> Aabb {
+        let extent = Vector3::new(distance, distance, distance);
+
+        Aabb {
+            min: self.min - extent,
+            max: self.max + extent,
+        }
+    }
+
+    /// Smallest box containing both boxes
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Vector3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: Vector3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    /// Smallest box containing the box and a point
+    pub fn grow(&self, point: Vector3<f32>) -> Aabb {
+        self.union(&Aabb::new(point, point))
+    }
+
+    pub fn centroid(&self) -> Vector3<f32> {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let size = self.max - self.min;
+        if size.x < 0.0 || size.y < 0.0 || size.z < 0.0 {
+            return 0.0;
+        }
+
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+
+    /// Axis the box is longest along
+    pub fn largest_axis(&self) -> usize {
+        let size = self.max - self.min;
+        if size.x >= size.y && size.x >= size.z {
+            0
+        } else if size.y >= size.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Returns the distances along the ray where it enters and leaves the box,
+    /// clipped to the range t_min to t_max. None if the ray misses the box
+    pub fn intersect(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<(f32, f32)> {
+        let mut t_enter = t_min;
+        let mut t_exit = t_max;
+
+        // Slab method, clip the ray against the pair of planes on each axis
+        for axis in 0..3 {
+            let inverse_direction = 1.0 / ray.direction()[axis];
+
+            let mut t0 = (self.min[axis] - ray.origin()[axis]) * inverse_direction;
+            let mut t1 = (self.max[axis] - ray.origin()[axis]) * inverse_direction;
+            if inverse_direction < 0.0 {
+                ::std::mem::swap(&mut t0, &mut t1);
+            }
+
+            t_enter = t_enter.max(t0);
+            t_exit = t_exit.min(t1);
+
+            // Flat boxes, like the bounds of an axis aligned triangle, can still be hit
+            if t_exit < t_enter {
+                return None;
+            }
+        }
+
+        Some((t_enter, t_exit))
+    }
+
+    /// Position relative to the box, where min maps to 0.0 and max maps to 1.0
+    pub fn relative_position(&self, position: Vector3<f32>) -> Vector3<f32> {
+        let size = self.max - self.min;
+        let offset = position - self.min;
+
+        Vector3::new(offset.x / size.x, offset.y / size.y, offset.z / size.z)
+    }
+}
@@ -0,0 +1,110 @@
+use cgmath::Vector3;
+
+// Luminance a pixel's noise is measured relative to, so very dark pixels don't need an
+// impossibly small error to converge
+const MIN_LUMINANCE: f32 = 0.01;
+
+/// Decides how many samples each pixel gets. Every pixel takes at least `min_samples`,
+/// then keeps sampling until the standard error of its mean luminance, relative to the
+/// luminance, drops below the noise threshold or it reaches `max_samples`.
+///
+/// With the same minimum and maximum every pixel gets exactly that many samples
+#[derive(Debug, Clone, Copy)]
+pub struct AdaptiveSampling {
+    pub min_samples: u32,
+    pub max_samples: u32,
+    noise_threshold: f32,
+}
+
+impl AdaptiveSampling {
+    pub fn new(min_samples: u32, max_samples: u32, noise_threshold: f32) -> AdaptiveSampling {
+        assert!(min_samples <= max_samples);
+        assert!(noise_threshold >= 0.0);
+
+        AdaptiveSampling {
+            min_samples,
+            max_samples,
+            noise_threshold,
+        }
+    }
+
+    /// Sampling without any adaptivity, every pixel gets `samples` samples
+    pub fn fixed(samples: u32) -> AdaptiveSampling {
+        AdaptiveSampling::new(samples, samples, 0.0)
+    }
+
+    pub fn is_adaptive(&self) -> bool {
+        self.min_samples < self.max_samples
+    }
+
+    /// Whether a pixel with these statistics needs any more samples
+    pub fn is_done(&self, statistics: &PixelStatistics) -> bool {
+        if statistics.count < self.min_samples.max(2) {
+            return statistics.count >= self.max_samples;
+        }
+
+        if statistics.count >= self.max_samples {
+            return true;
+        }
+
+        statistics.relative_error() < self.noise_threshold
+    }
+}
+
+/// Running mean and variance of the luminance of a pixel's samples, using Welford's
+/// algorithm so it stays accurate over many samples
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PixelStatistics {
+    count: u32,
+    mean: f64,
+    // Sum of squared differences from the mean
+    m2: f64,
+}
+
+impl PixelStatistics {
+    pub fn new() -> PixelStatistics {
+        PixelStatistics {
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+        }
+    }
+
+    /// Rebuild statistics from the values returned by `parts`
+    pub fn from_parts(count: u32, mean: f64, m2: f64) -> PixelStatistics {
+        PixelStatistics { count, mean, m2 }
+    }
+
+    /// The sample count, mean and sum of squared differences from the mean
+    pub fn parts(&self) -> (u32, f64, f64) {
+        (self.count, self.mean, self.m2)
+    }
+
+    /// Number of samples added so far
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub fn add(&mut self, color: Vector3<f32>) {
+        let value = f64::from(luminance(color));
+
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / f64::from(self.count);
+        self.m2 += delta * (value - self.mean);
+    }
+
+    // Standard error of the mean, divided by the mean
+    fn relative_error(&self) -> f32 {
+        let count = f64::from(self.count);
+        let variance = self.m2 / (count - 1.0);
+        let standard_error = (variance / count).sqrt() as f32;
+
+        standard_error / (self.mean as f32).max(MIN_LUMINANCE)
+    }
+}
+
+// Perceived brightness of a linear RGB color, with the Rec. 709 weights
+fn luminance(color: Vector3<f32>) -> f32 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
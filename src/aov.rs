@@ -0,0 +1,364 @@
+use cgmath::prelude::*;
+use cgmath::Vector3;
+
+use std::f32;
+
+use hit::HitRecord;
+use ray::Ray;
+
+/// Names of every AOV that can be written
+pub const AOV_NAMES: &[&str] = &[
+    "albedo",
+    "normal",
+    "depth",
+    "position",
+    "object-id",
+    "material-id",
+    "direct-diffuse",
+    "indirect-diffuse",
+    "direct-specular",
+    "indirect-specular",
+    "emission",
+    "alpha",
+];
+
+/// Arbitrary output variable. An extra image rendered alongside the final one, holding
+/// a single property of the scene or a part of the light
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aov {
+    /// Color of the first surface hit
+    Albedo,
+    /// Shading normal of the first surface hit
+    Normal,
+    /// Distance to the first surface hit, infinite where nothing was hit
+    Depth,
+    /// World space position of the first surface hit
+    Position,
+    /// Index of the object in the scene, 0 where nothing was hit
+    ObjectId,
+    /// Hash of the material parameters, 0 where nothing was hit
+    MaterialId,
+    /// Light reflected by the first diffuse bounce straight from the sky
+    DirectDiffuse,
+    /// Light reaching the first diffuse bounce after more bounces
+    IndirectDiffuse,
+    /// Light reflected or refracted by the first specular bounce straight from the sky
+    DirectSpecular,
+    /// Light reaching the first specular bounce after more bounces
+    IndirectSpecular,
+    /// Light emitted towards the camera without bouncing, including the sky
+    Emission,
+    /// Coverage, 0 where the camera sees the sky and 1 where it sees the scene
+    Alpha,
+}
+
+impl Aov {
+    /// Find an AOV by one of the names in `AOV_NAMES`
+    pub fn from_name(name: &str) -> Option<Aov> {
+        let aov = match name {
+            "albedo" => Aov::Albedo,
+            "normal" => Aov::Normal,
+            "depth" => Aov::Depth,
+            "position" => Aov::Position,
+            "object-id" => Aov::ObjectId,
+            "material-id" => Aov::MaterialId,
+            "direct-diffuse" => Aov::DirectDiffuse,
+            "indirect-diffuse" => Aov::IndirectDiffuse,
+            "direct-specular" => Aov::DirectSpecular,
+            "indirect-specular" => Aov::IndirectSpecular,
+            "emission" => Aov::Emission,
+            "alpha" => Aov::Alpha,
+            _ => return None,
+        };
+
+        Some(aov)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::ObjectId => "object-id",
+            Aov::MaterialId => "material-id",
+            Aov::DirectDiffuse => "direct-diffuse",
+            Aov::IndirectDiffuse => "indirect-diffuse",
+            Aov::DirectSpecular => "direct-specular",
+            Aov::IndirectSpecular => "indirect-specular",
+            Aov::Emission => "emission",
+            Aov::Alpha => "alpha",
+        }
+    }
+
+    /// Names of the channels of the AOV inside of a multi-layer image
+    pub fn channels(self) -> &'static [&'static str] {
+        match self {
+            Aov::Normal | Aov::Position => &["X", "Y", "Z"],
+            Aov::Depth => &["Z"],
+            Aov::ObjectId | Aov::MaterialId => &["id"],
+            Aov::Alpha => &["A"],
+            _ => &["R", "G", "B"],
+        }
+    }
+
+    /// Whether the AOV holds integer ids rather than continuous values
+    pub fn is_id(self) -> bool {
+        self == Aov::ObjectId || self == Aov::MaterialId
+    }
+}
+
+/// Kind of the first bounce along a path, deciding which AOV its light goes to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lobe {
+    /// Diffuse surfaces and scattering inside of media
+    Diffuse,
+    /// Reflection or refraction by metals and glass
+    Specular,
+}
+
+/// AOVs of a single sample, filled in by an integrator
+#[derive(Debug, Clone, Copy)]
+pub struct AovSample {
+    pub hit: bool,
+    pub albedo: Vector3<f32>,
+    pub normal: Vector3<f32>,
+    pub position: Vector3<f32>,
+    pub depth: f32,
+    pub object_id: u32,
+    pub material_id: u32,
+    pub direct_diffuse: Vector3<f32>,
+    pub indirect_diffuse: Vector3<f32>,
+    pub direct_specular: Vector3<f32>,
+    pub indirect_specular: Vector3<f32>,
+    pub emission: Vector3<f32>,
+    pub alpha: f32,
+}
+
+impl AovSample {
+    pub fn new() -> AovSample {
+        AovSample {
+            hit: false,
+            albedo: Vector3::zero(),
+            normal: Vector3::zero(),
+            position: Vector3::zero(),
+            depth: f32::INFINITY,
+            object_id: 0,
+            material_id: 0,
+            direct_diffuse: Vector3::zero(),
+            indirect_diffuse: Vector3::zero(),
+            direct_specular: Vector3::zero(),
+            indirect_specular: Vector3::zero(),
+            emission: Vector3::zero(),
+            alpha: 0.0,
+        }
+    }
+
+    /// Record the properties of the first surface a camera ray hit
+    pub fn record_hit(&mut self, ray: Ray, record: &HitRecord) {
+        self.hit = true;
+        self.albedo = record.material.albedo();
+        self.normal = record.normal;
+        self.position = record.position;
+        self.depth = record.t * ray.direction().magnitude();
+        self.object_id = record.object_id;
+        // Keep zero free for the background
+        self.material_id = (record.material.id() as u32).max(1);
+        self.alpha = 1.0;
+    }
+
+    /// Add light that reached the camera. `lobe` is the kind of the first bounce along the
+    /// path, if there was one, and `direct` is whether that was the only bounce
+    pub fn add_light(&mut self, lobe: Option<Lobe>, direct: bool, light: Vector3<f32>) {
+        match (lobe, direct) {
+            (None, _) => self.emission += light,
+            (Some(Lobe::Diffuse), true) => self.direct_diffuse += light,
+            (Some(Lobe::Diffuse), false) => self.indirect_diffuse += light,
+            (Some(Lobe::Specular), true) => self.direct_specular += light,
+            (Some(Lobe::Specular), false) => self.indirect_specular += light,
+        }
+    }
+}
+
+/// Sum of the AOVs of every sample taken inside of a pixel
+#[derive(Debug, Clone, Copy)]
+pub struct AovPixel {
+    samples: u32,
+    // Samples that hit a surface, geometric AOVs are averaged over these
+    hits: u32,
+    albedo: Vector3<f32>,
+    normal: Vector3<f32>,
+    position: Vector3<f32>,
+    depth: f32,
+    // Ids can't be averaged, so they come from the first sample that hit something
+    object_id: u32,
+    material_id: u32,
+    direct_diffuse: Vector3<f32>,
+    indirect_diffuse: Vector3<f32>,
+    direct_specular: Vector3<f32>,
+    indirect_specular: Vector3<f32>,
+    emission: Vector3<f32>,
+    alpha: f32,
+}
+
+impl AovPixel {
+    /// Number of words returned by `to_words`
+    pub const WORDS: usize = 30;
+
+    pub fn new() -> AovPixel {
+        AovPixel {
+            samples: 0,
+            hits: 0,
+            albedo: Vector3::zero(),
+            normal: Vector3::zero(),
+            position: Vector3::zero(),
+            depth: 0.0,
+            object_id: 0,
+            material_id: 0,
+            direct_diffuse: Vector3::zero(),
+            indirect_diffuse: Vector3::zero(),
+            direct_specular: Vector3::zero(),
+            indirect_specular: Vector3::zero(),
+            emission: Vector3::zero(),
+            alpha: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, sample: &AovSample) {
+        if sample.hit {
+            if self.hits == 0 {
+                self.object_id = sample.object_id;
+                self.material_id = sample.material_id;
+            }
+
+            self.hits += 1;
+            self.normal += sample.normal;
+            self.position += sample.position;
+            self.depth += sample.depth;
+        }
+
+        self.samples += 1;
+        self.albedo += sample.albedo;
+        self.direct_diffuse += sample.direct_diffuse;
+        self.indirect_diffuse += sample.indirect_diffuse;
+        self.direct_specular += sample.direct_specular;
+        self.indirect_specular += sample.indirect_specular;
+        self.emission += sample.emission;
+        self.alpha += sample.alpha;
+    }
+
+    /// Final value of an AOV, with one value per channel
+    pub fn value(&self, aov: Aov) -> [f32; 3] {
+        let samples = self.samples.max(1) as f32;
+        let hits = self.hits.max(1) as f32;
+        let vector = |value: Vector3<f32>| [value.x, value.y, value.z];
+
+        match aov {
+            Aov::Albedo => vector(self.albedo / samples),
+            Aov::Normal if self.hits > 0 => vector(self.normal.normalize()),
+            Aov::Normal => [0.0; 3],
+            Aov::Depth if self.hits > 0 => [self.depth / hits, 0.0, 0.0],
+            Aov::Depth => [f32::INFINITY, 0.0, 0.0],
+            Aov::Position => vector(self.position / hits),
+            Aov::ObjectId => [self.object_id as f32, 0.0, 0.0],
+            Aov::MaterialId => [self.material_id as f32, 0.0, 0.0],
+            Aov::DirectDiffuse => vector(self.direct_diffuse / samples),
+            Aov::IndirectDiffuse => vector(self.indirect_diffuse / samples),
+            Aov::DirectSpecular => vector(self.direct_specular / samples),
+            Aov::IndirectSpecular => vector(self.indirect_specular / samples),
+            Aov::Emission => vector(self.emission / samples),
+            Aov::Alpha => [self.alpha / samples, 0.0, 0.0],
+        }
+    }
+
+    /// Exact integer id, for the id AOVs
+    pub fn id(&self, aov: Aov) -> u32 {
+        match aov {
+            Aov::ObjectId => self.object_id,
+            Aov::MaterialId => self.material_id,
+            _ => 0,
+        }
+    }
+
+    /// Every field as raw 32 bit words, for saving in checkpoints
+    pub fn to_words(self) -> Vec<u32> {
+        let mut words = vec![self.samples, self.hits, self.object_id, self.material_id];
+
+        let vectors = [
+            self.albedo,
+            self.normal,
+            self.position,
+            self.direct_diffuse,
+            self.indirect_diffuse,
+            self.direct_specular,
+            self.indirect_specular,
+            self.emission,
+        ];
+        for vector in &vectors {
+            words.extend_from_slice(&[vector.x.to_bits(), vector.y.to_bits(), vector.z.to_bits()]);
+        }
+
+        words.push(self.depth.to_bits());
+        words.push(self.alpha.to_bits());
+        words
+    }
+
+    /// Rebuild a pixel from the words of `to_words`
+    pub fn from_words(words: &[u32]) -> AovPixel {
+        assert_eq!(words.len(), AovPixel::WORDS);
+
+        let vector = |index: usize| {
+            let start = 4 + index * 3;
+            Vector3::new(
+                f32::from_bits(words[start]),
+                f32::from_bits(words[start + 1]),
+                f32::from_bits(words[start + 2]),
+            )
+        };
+
+        AovPixel {
+            samples: words[0],
+            hits: words[1],
+            object_id: words[2],
+            material_id: words[3],
+            albedo: vector(0),
+            normal: vector(1),
+            position: vector(2),
+            direct_diffuse: vector(3),
+            indirect_diffuse: vector(4),
+            direct_specular: vector(5),
+            indirect_specular: vector(6),
+            emission: vector(7),
+            depth: f32::from_bits(words[28]),
+            alpha: f32::from_bits(words[29]),
+        }
+    }
+}
+
+/// Color showing an AOV in an 8 bit image, for formats that can't hold its real values.
+/// Normals are remapped from -1.0..1.0, depth fades to black with distance and ids get a
+/// random flat color
+pub fn preview_color(aov: Aov, pixel: &AovPixel) -> Vector3<f32> {
+    let [a, b, c] = pixel.value(aov);
+
+    match aov {
+        Aov::Normal => Vector3::new(a, b, c) * 0.5 + Vector3::new(0.5, 0.5, 0.5),
+        Aov::Depth => {
+            let brightness = 1.0 / (1.0 + a);
+            Vector3::new(brightness, brightness, brightness)
+        }
+        Aov::Alpha => Vector3::new(a, a, a),
+        Aov::ObjectId | Aov::MaterialId if pixel.id(aov) == 0 => Vector3::zero(),
+        Aov::ObjectId | Aov::MaterialId => {
+            // Spread consecutive ids over very different colors
+            let hash = pixel.id(aov).wrapping_mul(0x9E37_79B9);
+
+            Vector3::new(
+                (hash >> 24) as f32 / 255.0,
+                ((hash >> 16) & 0xFF) as f32 / 255.0,
+                ((hash >> 8) & 0xFF) as f32 / 255.0,
+            )
+        }
+        _ => Vector3::new(a, b, c),
+    }
+}
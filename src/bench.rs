@@ -0,0 +1,167 @@
+use std::time::{Duration, Instant};
+
+use adaptive::AdaptiveSampling;
+use camera::Camera;
+use film::{self, Film};
+use integrator;
+use rayon;
+use renderer::Renderer;
+use sampler;
+use scene;
+use stats::{self, RenderStats};
+
+/// Scenes rendered when none are picked, the ones that stress tracing rather than
+/// checking the results
+pub const DEFAULT_SCENES: &[&str] = &["spheres", "cover", "sphere-cloud", "cornell", "mesh"];
+
+/// How every scene in a benchmark is rendered. Scenes are rendered with the path tracer,
+/// the random sampler and a box filter so only the cost of tracing rays is measured
+#[derive(Debug, Clone, Copy)]
+pub struct BenchSettings {
+    pub width: u32,
+    pub height: u32,
+    pub samples: u32,
+    pub max_depth: u32,
+    pub seed: u64,
+    /// Times each scene is rendered, the median time is reported
+    pub runs: u32,
+}
+
+/// Timings and ray counts of a single benchmark scene
+#[derive(Debug, Clone)]
+pub struct BenchResult {
+    pub scene: String,
+    /// Time taken to create the scene, including building acceleration structures
+    pub build_seconds: f64,
+    /// Render time of every run
+    pub run_seconds: Vec<f64>,
+    /// Statistics of the run with the median render time
+    pub stats: RenderStats,
+}
+
+impl BenchResult {
+    pub fn median_seconds(&self) -> f64 {
+        self.stats.seconds
+    }
+
+    pub fn fastest_seconds(&self) -> f64 {
+        self.run_seconds.iter().cloned().fold(f64::MAX, f64::min)
+    }
+
+    pub fn slowest_seconds(&self) -> f64 {
+        self.run_seconds.iter().cloned().fold(0.0, f64::max)
+    }
+}
+
+/// Build and render one of the built-in scenes. Returns None if no scene has that name
+pub fn run_scene(name: &str, settings: BenchSettings) -> Option<BenchResult> {
+    let build_start = Instant::now();
+    let scene = scene::create_scene(name, scene::SceneOptions::default())?;
+    let build_seconds = build_start.elapsed().as_secs_f64();
+
+    let camera = Camera::new(settings.width, settings.height, scene.camera);
+    let renderer = Renderer::new(
+        camera,
+        scene,
+        integrator::create_integrator("path", settings.max_depth).unwrap(),
+        sampler::create_sampler("random", settings.seed, settings.samples).unwrap(),
+        AdaptiveSampling::fixed(settings.samples),
+    );
+    let filter = film::create_filter("box", None).unwrap();
+
+    let mut runs: Vec<RenderStats> = (0..settings.runs.max(1))
+        .map(|_| {
+            let mut film = Film::new(settings.width, settings.height, filter);
+
+            stats::reset();
+            let start = Instant::now();
+            renderer.render_pass(&mut film, settings.samples);
+
+            RenderStats::collect(start.elapsed())
+        })
+        .collect();
+
+    let run_seconds = runs.iter().map(|run| run.seconds).collect();
+    runs.sort_by(|a, b| a.seconds.total_cmp(&b.seconds));
+    let median = runs.swap_remove(runs.len() / 2);
+
+    Some(BenchResult {
+        scene: name.to_string(),
+        build_seconds,
+        run_seconds,
+        stats: median,
+    })
+}
+
+/// Table with one line per scene
+pub fn report(results: &[BenchResult]) -> String {
+    let mut report = format!(
+        "{:<10} {:>10} {:>10} {:>10} {:>14} {:>14}\n",
+        "Scene", "Build", "Median", "Fastest", "Rays", "Rays/s"
+    );
+
+    for result in results {
+        report += &format!(
+            "{:<10} {:>9.3}s {:>9.3}s {:>9.3}s {:>14} {:>14.0}\n",
+            result.scene,
+            result.build_seconds,
+            result.median_seconds(),
+            result.fastest_seconds(),
+            result.stats.total_rays(),
+            result.stats.rays_per_second()
+        );
+    }
+
+    report
+}
+
+/// The settings and results as a JSON object, so runs can be tracked over time
+pub fn to_json(settings: BenchSettings, results: &[BenchResult], total: Duration) -> String {
+    let scenes: Vec<String> = results
+        .iter()
+        .map(|result| {
+            let run_seconds: Vec<String> = result
+                .run_seconds
+                .iter()
+                .map(|seconds| seconds.to_string())
+                .collect();
+
+            format!(
+                "    {{\n      \"name\": \"{}\",\n      \"build_seconds\": {},\n      \
+                 \"median_seconds\": {},\n      \"fastest_seconds\": {},\n      \
+                 \"slowest_seconds\": {},\n      \"run_seconds\": [{}],\n      \
+                 \"primary_rays\": {},\n      \"secondary_rays\": {},\n      \
+                 \"shadow_rays\": {},\n      \"rays_per_second\": {},\n      \
+                 \"intersection_tests\": {},\n      \"bvh_nodes_visited\": {}\n    }}",
+                result.scene,
+                result.build_seconds,
+                result.median_seconds(),
+                result.fastest_seconds(),
+                result.slowest_seconds(),
+                run_seconds.join(", "),
+                result.stats.primary_rays,
+                result.stats.secondary_rays,
+                result.stats.shadow_rays,
+                result.stats.rays_per_second(),
+                result.stats.intersection_tests,
+                result.stats.nodes_visited
+            )
+        })
+        .collect();
+
+    format!(
+        "{{\n  \"version\": \"{}\",\n  \"width\": {},\n  \"height\": {},\n  \"samples\": {},\n  \
+         \"max_depth\": {},\n  \"seed\": {},\n  \"runs\": {},\n  \"threads\": {},\n  \
+         \"total_seconds\": {},\n  \"scenes\": [\n{}\n  ]\n}}\n",
+        env!("CARGO_PKG_VERSION"),
+        settings.width,
+        settings.height,
+        settings.samples,
+        settings.max_depth,
+        settings.seed,
+        settings.runs.max(1),
+        rayon::current_num_threads(),
+        total.as_secs_f64(),
+        scenes.join(",\n")
+    )
+}
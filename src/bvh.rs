@@ -0,0 +1,194 @@
+use cgmath::Vector3;
+
+use aabb::Aabb;
+use ray::Ray;
+use stats::{self, Counter};
+
+// Nodes with this many primitives or fewer aren't split any further
+const MAX_LEAF_SIZE: usize = 4;
+
+// Number of candidate split positions tried along the longest axis of each node
+const BINS: usize = 12;
+
+/// Bounding volume hierarchy over a list of primitives. It only knows the bounding box of
+/// each primitive, so anything with bounds can be put in one and the owner does the
+/// actual intersection tests.
+///
+/// Nodes are split with the surface area heuristic, which places splits where the
+/// expected cost of tracing a ray through the children is lowest
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    // Primitive indices, ordered so each leaf covers a contiguous range
+    indices: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BvhNode {
+    bounds: Aabb,
+    // For leaves, the first entry of `indices`. For interior nodes, the index of the
+    // second child, the first child always comes right after its parent
+    offset: u32,
+    // Number of primitives in a leaf, zero for interior nodes
+    count: u32,
+    // Axis an interior node was split along
+    axis: u8,
+}
+
+impl Bvh {
+    /// Build a hierarchy over primitives with the given bounding boxes. Primitives are
+    /// referred to by their index into `bounds`
+    pub fn build(bounds: &[Aabb]) -> Bvh {
+        let mut indices: Vec<u32> = (0..bounds.len() as u32).collect();
+        let centroids: Vec<Vector3<f32>> = bounds.iter().map(Aabb::centroid).collect();
+        let mut nodes = Vec::with_capacity(2 * bounds.len() / MAX_LEAF_SIZE + 1);
+
+        if !bounds.is_empty() {
+            build_node(bounds, &centroids, &mut indices, 0, &mut nodes);
+        }
+
+        Bvh { nodes, indices }
+    }
+
+    /// Box around every primitive
+    pub fn bounds(&self) -> Aabb {
+        self.nodes
+            .first()
+            .map_or_else(Aabb::empty, |node| node.bounds)
+    }
+
+    /// Find the closest primitive along the ray between `t_min` and `t_max`. `hit` is
+    /// called with the index of every primitive whose box the ray passes through and the
+    /// distance to the closest hit so far, and returns the distance to the primitive if
+    /// the ray hits it closer than that
+    pub fn closest_hit<F>(&self, ray: Ray, t_min: f32, t_max: f32, mut hit: F)
+    where
+        F: FnMut(usize, f32) -> Option<f32>,
+    {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let mut closest = t_max;
+        let mut stack = Vec::with_capacity(64);
+        stack.push(0);
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            stats::count(Counter::NodesVisited);
+
+            if node.bounds.intersect(ray, t_min, closest).is_none() {
+                continue;
+            }
+
+            if node.count > 0 {
+                let start = node.offset as usize;
+                for &primitive in &self.indices[start..start + node.count as usize] {
+                    if let Some(t) = hit(primitive as usize, closest) {
+                        closest = t;
+                    }
+                }
+            } else if ray.direction()[node.axis as usize] > 0.0 {
+                // Visit the nearer child first, so hits in it cut the search short
+                stack.push(node.offset as usize);
+                stack.push(index + 1);
+            } else {
+                stack.push(index + 1);
+                stack.push(node.offset as usize);
+            }
+        }
+    }
+}
+
+// Add a node for the primitives in `indices`, which start at `offset` in the full list,
+// then split it recursively. Returns the index of the node
+fn build_node(
+    bounds: &[Aabb],
+    centroids: &[Vector3<f32>],
+    indices: &mut [u32],
+    offset: usize,
+    nodes: &mut Vec<BvhNode>,
+) -> usize {
+    let node_bounds = indices
+        .iter()
+        .fold(Aabb::empty(), |total, &i| total.union(&bounds[i as usize]));
+
+    let node_index = nodes.len();
+    nodes.push(BvhNode {
+        bounds: node_bounds,
+        offset: offset as u32,
+        count: indices.len() as u32,
+        axis: 0,
+    });
+
+    if indices.len() <= MAX_LEAF_SIZE {
+        return node_index;
+    }
+
+    let centroid_bounds = indices
+        .iter()
+        .fold(Aabb::empty(), |total, &i| total.grow(centroids[i as usize]));
+    let axis = centroid_bounds.largest_axis();
+    let axis_min = centroid_bounds.min[axis];
+    let extent = centroid_bounds.max[axis] - axis_min;
+
+    // Every primitive is in the same spot, no split can separate them
+    if extent <= 0.0 {
+        return node_index;
+    }
+
+    let bin_of = |i: u32| {
+        let relative = (centroids[i as usize][axis] - axis_min) / extent;
+        ((relative * BINS as f32) as usize).min(BINS - 1)
+    };
+
+    let mut bin_bounds = [Aabb::empty(); BINS];
+    let mut bin_counts = [0usize; BINS];
+    for &i in indices.iter() {
+        let bin = bin_of(i);
+        bin_bounds[bin] = bin_bounds[bin].union(&bounds[i as usize]);
+        bin_counts[bin] += 1;
+    }
+
+    // Cost of splitting before each bin, the area of each side times the primitives in it
+    let mut costs = [0.0; BINS];
+    let mut left_bounds = Aabb::empty();
+    let mut left_count = 0;
+    for split in 1..BINS {
+        left_bounds = left_bounds.union(&bin_bounds[split - 1]);
+        left_count += bin_counts[split - 1];
+        costs[split] = left_bounds.surface_area() * left_count as f32;
+    }
+
+    let mut right_bounds = Aabb::empty();
+    let mut right_count = 0;
+    for split in (1..BINS).rev() {
+        right_bounds = right_bounds.union(&bin_bounds[split]);
+        right_count += bin_counts[split];
+        costs[split] += right_bounds.surface_area() * right_count as f32;
+    }
+
+    // The first and last bins always hold a centroid, so both sides are never empty
+    let split = (1..BINS)
+        .min_by(|&a, &b| costs[a].total_cmp(&costs[b]))
+        .unwrap();
+
+    // Move everything left of the split to the front
+    let mut middle = 0;
+    for i in 0..indices.len() {
+        if bin_of(indices[i]) < split {
+            indices.swap(i, middle);
+            middle += 1;
+        }
+    }
+
+    let (left, right) = indices.split_at_mut(middle);
+    build_node(bounds, centroids, left, offset, nodes);
+    let second_child = build_node(bounds, centroids, right, offset + middle, nodes);
+
+    let node = &mut nodes[node_index];
+    node.offset = second_child as u32;
+    node.count = 0;
+    node.axis = axis as u8;
+
+    node_index
+}
@@ -1,52 +1,100 @@
 use cgmath::prelude::*;
-use cgmath::Vector3;
+use cgmath::{Vector2, Vector3};
 
 use ray::Ray;
+use sampler::Sampler;
 
-use rand::{thread_rng, Rng};
+/// Where a camera is and what it looks at
+#[derive(Debug, Clone, Copy)]
+pub struct CameraSettings {
+    pub position: Vector3<f32>,
+    pub target: Vector3<f32>,
+    /// Direction that is up in the image
+    pub up: Vector3<f32>,
+    /// Angle covered by the image from bottom to top, in degrees
+    pub vertical_fov: f32,
+}
+
+impl Default for CameraSettings {
+    /// At the origin looking down the negative z-axis, with a 90 degree field of view
+    fn default() -> CameraSettings {
+        CameraSettings {
+            position: Vector3::zero(),
+            target: Vector3::new(0.0, 0.0, -1.0),
+            up: Vector3::unit_y(),
+            vertical_fov: 90.0,
+        }
+    }
+}
 
 /// Camera handles creating new rays and ensuring they are all oriented
 /// correctly.
 pub struct Camera {
     position: Vector3<f32>,
-    focal_point: Vector3<f32>,
     lower_left_corner: Vector3<f32>,
     horizontal_scale: Vector3<f32>,
     vertical_scale: Vector3<f32>,
+    resolution_x: f32,
+    resolution_y: f32,
 }
 
 impl Camera {
     /// res_x: Width of the focal plane in pixels
     /// res_y: Height of the focal plane in pixles
-    /// focal_point: Z component is the distance from the camera. Must be positive
-    ///              X and Y must be between -1.0 and 1.0, where 0.0 is the center of the plane
-    pub fn new(res_x: u32, res_y: u32, focal_point: Vector3<f32>) -> Camera {
+    pub fn new(res_x: u32, res_y: u32, settings: CameraSettings) -> Camera {
         let res_x = res_x as f32;
         let res_y = res_y as f32;
 
-        assert!(focal_point.z > 0f32);
-        assert!(focal_point.x > -1.0 && focal_point.x < 1.0);
-        assert!(focal_point.y > -1.0 && focal_point.y < 1.0);
-
         // Only width is modified to change clipping area. If the height is changed,
         // than the final result is scaled up to match. Dunno if this is correct, but
         // it seems pretty similar to what other programs do.
-        let width = res_x / res_y;
+        let half_height = (settings.vertical_fov.to_radians() / 2.0).tan();
+        let half_width = half_height * res_x / res_y;
+
+        // Camera space, looking down the negative w-axis
+        let w = (settings.position - settings.target).normalize();
+        let u = settings.up.cross(w).normalize();
+        let v = w.cross(u);
 
         Camera {
-            position: Vector3::zero(),
-            focal_point,
-            lower_left_corner: Vector3::new(-width, -1.0, -1.0),
-            horizontal_scale: Vector3::new(width * 2.0, 0.0, 0.0),
-            vertical_scale: Vector3::new(0.0, 2.0, 0.0),
+            position: settings.position,
+            // The focal plane is one unit in front of the camera
+            lower_left_corner: settings.position - half_width * u - half_height * v - w,
+            horizontal_scale: 2.0 * half_width * u,
+            vertical_scale: 2.0 * half_height * v,
+            resolution_x: res_x,
+            resolution_y: res_y,
         }
     }
 
+    // Initialize a ray through a random point inside of a pixel, where pixel (0, 0) is the
+    // bottom left corner of the image. Also returns the point on the film, in pixels, so the
+    // sample can be filtered onto the pixels around it
+    pub fn get_ray(
+        &self,
+        pixel_x: u32,
+        pixel_y: u32,
+        sampler: &mut dyn Sampler,
+    ) -> (Ray, Vector2<f32>) {
+        // Randomly offset each ray by a tiny, random amount to get nice AA
+        let (jitter_x, jitter_y) = sampler.next_2d();
+        let film_position = Vector2::new(pixel_x as f32 + jitter_x, pixel_y as f32 + jitter_y);
+        let horizontal_offset = film_position.x / self.resolution_x;
+        let vertical_offset = film_position.y / self.resolution_y;
+
+        (
+            self.get_ray_at_coords(horizontal_offset, vertical_offset),
+            film_position,
+        )
+    }
+
     // Initialize a ray starting at the camera position and pointing toward a point on a plane 1 unit away
     // from the camera
     pub fn get_ray_at_coords(&self, horizontal_offset: f32, vertical_offset: f32) -> Ray {
-        let destination = self.lower_left_corner + (horizontal_offset * self.horizontal_scale)
-            + (vertical_offset * self.vertical_scale) - self.position;
+        let destination = self.lower_left_corner
+            + (horizontal_offset * self.horizontal_scale)
+            + (vertical_offset * self.vertical_scale)
+            - self.position;
 
         Ray::new(self.position, destination)
     }
@@ -0,0 +1,147 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use film::Film;
+
+// Identifies checkpoint files, and the version of the format
+const MAGIC: &[u8; 8] = b"RTCKPT01";
+
+// Settings are a short line of text, anything longer is a corrupt length that would
+// otherwise be allocated up front
+const MAX_SETTINGS_LENGTH: u32 = 64 * 1024;
+
+/// Save the state of a render so it can be resumed with `load`.
+///
+/// The file starts with a magic number, the film size as little endian u32s, the number of
+/// samples per pixel of the last finished pass, and the length and text of `settings`. The
+/// rest is the state of the film.
+///
+/// Samplers derive every number from the seed, pixel and sample index, so the sample count
+/// of each pixel on the film is all the random number state there is. The seed has to be
+/// part of `settings`.
+///
+/// The checkpoint is written next to `path` first and then moved over it, so a crash while
+/// saving can't destroy the previous checkpoint
+pub fn save(path: &Path, settings: &str, sample_limit: u32, film: &Film) -> io::Result<()> {
+    let temporary_path = path.with_extension("tmp");
+
+    {
+        let mut writer = BufWriter::new(File::create(&temporary_path)?);
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&film.width().to_le_bytes())?;
+        writer.write_all(&film.height().to_le_bytes())?;
+        writer.write_all(&sample_limit.to_le_bytes())?;
+        writer.write_all(&(settings.len() as u32).to_le_bytes())?;
+        writer.write_all(settings.as_bytes())?;
+        film.write_state(&mut writer)?;
+
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+    }
+
+    fs::rename(temporary_path, path)
+}
+
+/// Load a checkpoint written by `save` into `film`, returning the number of samples per
+/// pixel of the last finished pass. Fails if the checkpoint was made for a different film
+/// size or with different settings
+pub fn load(path: &Path, settings: &str, film: &mut Film) -> io::Result<u32> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("file is not a checkpoint"));
+    }
+
+    let width = read_u32(&mut reader)?;
+    let height = read_u32(&mut reader)?;
+    if width != film.width() || height != film.height() {
+        return Err(invalid_data(&format!(
+            "checkpoint is {} by {} but the image is {} by {}",
+            width,
+            height,
+            film.width(),
+            film.height()
+        )));
+    }
+
+    let sample_limit = read_u32(&mut reader)?;
+
+    let settings_length = read_u32(&mut reader)?;
+    if settings_length > MAX_SETTINGS_LENGTH {
+        return Err(invalid_data("checkpoint settings are too long"));
+    }
+
+    let mut saved_settings = vec![0; settings_length as usize];
+    reader.read_exact(&mut saved_settings)?;
+    if saved_settings != settings.as_bytes() {
+        return Err(invalid_data(&format!(
+            "checkpoint was rendered with different settings: {}",
+            String::from_utf8_lossy(&saved_settings)
+        )));
+    }
+
+    film.read_state(&mut reader)?;
+
+    if reader.read(&mut [0])? != 0 {
+        return Err(invalid_data("checkpoint has data past the end of the film"));
+    }
+
+    Ok(sample_limit)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+
+    use film::create_filter;
+
+    fn film() -> Film {
+        Film::new(4, 2, create_filter("box", None).unwrap())
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = env::temp_dir().join("ray-tracer-checkpoint-round-trip.ckpt");
+        save(&path, "scene=spheres seed=1", 16, &film()).unwrap();
+
+        assert_eq!(
+            load(&path, "scene=spheres seed=1", &mut film()).unwrap(),
+            16
+        );
+        assert!(load(&path, "scene=cover seed=1", &mut film()).is_err());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn huge_settings_length_is_rejected() {
+        let path = env::temp_dir().join("ray-tracer-checkpoint-huge-settings.ckpt");
+        let mut bytes = MAGIC.to_vec();
+        for word in &[4, 2, 16, u32::MAX] {
+            bytes.extend_from_slice(&u32::to_le_bytes(*word));
+        }
+        fs::write(&path, bytes).unwrap();
+
+        let error = load(&path, "scene=spheres", &mut film()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,447 @@
+use cgmath::prelude::*;
+use cgmath::Vector3;
+
+use image::{self, ImageBuffer, Pixel, Rgba, RgbaImage};
+
+use std::path::Path;
+
+use exr::ExrImage;
+
+// Added to the reference in relative MSE so black pixels don't divide by zero
+const RELATIVE_MSE_EPSILON: f32 = 0.01;
+
+// Constants of SSIM for values in 0..1
+const SSIM_C1: f32 = 0.01 * 0.01;
+const SSIM_C2: f32 = 0.03 * 0.03;
+
+// Size of the windows SSIM is calculated over
+const SSIM_WINDOW: u32 = 8;
+
+// Standard deviation in pixels of the blur that stands in for how the eye loses detail,
+// and of the edge and point detectors
+const PERCEPTUAL_BLUR: f32 = 1.0;
+
+// Largest HyAB distance between two colors, found between pure green and pure blue
+const MAX_COLOR_DIFFERENCE: f32 = 308.0;
+
+// Color differences are compressed so that the first 40% of the range, where most
+// visible differences are, takes up 95% of the error
+const COLOR_CUTOFF: f32 = 0.4;
+const COLOR_CUTOFF_ERROR: f32 = 0.95;
+
+/// Colors of an image as floats, rows starting at the top
+pub struct ColorBuffer {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Vector3<f32>>,
+}
+
+impl ColorBuffer {
+    /// Load the `R`, `G` and `B` channels of an EXR file, or any 8 bit image as 0..1
+    pub fn load(path: &Path) -> Result<ColorBuffer, String> {
+        let is_exr = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("exr"));
+
+        if is_exr {
+            let image = ExrImage::load(path).map_err(|e| e.to_string())?;
+
+            let mut channels = Vec::new();
+            for name in &["R", "G", "B"] {
+                match image.channel(name) {
+                    Some(channel) => channels.push(channel),
+                    None => return Err(format!("image has no {} channel", name)),
+                }
+            }
+
+            let pixels = (0..(image.width * image.height) as usize)
+                .map(|index| {
+                    Vector3::new(
+                        channels[0].get(index),
+                        channels[1].get(index),
+                        channels[2].get(index),
+                    )
+                })
+                .collect();
+
+            Ok(ColorBuffer {
+                width: image.width,
+                height: image.height,
+                pixels,
+            })
+        } else {
+            let image = image::open(path).map_err(|e| e.to_string())?.to_rgba();
+
+            let pixels = image
+                .pixels()
+                .map(|pixel| {
+                    Vector3::new(
+                        f32::from(pixel.data[0]),
+                        f32::from(pixel.data[1]),
+                        f32::from(pixel.data[2]),
+                    ) / 255.0
+                })
+                .collect();
+
+            Ok(ColorBuffer {
+                width: image.width(),
+                height: image.height(),
+                pixels,
+            })
+        }
+    }
+
+    fn get(&self, x: u32, y: u32) -> Vector3<f32> {
+        self.pixels[(y * self.width + x) as usize]
+    }
+}
+
+/// Error metrics between an image and a reference
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    /// Mean squared error over every channel
+    pub mse: f32,
+    /// Squared error divided by the squared reference, so errors in dark areas count as
+    /// much as errors in bright ones
+    pub relative_mse: f32,
+    /// Peak signal to noise ratio in decibels, for a peak value of 1.0. Infinite for
+    /// identical images
+    pub psnr: f32,
+    /// Mean structural similarity of the luminance, 1.0 for identical images
+    pub ssim: f32,
+    /// Mean of `error_map`, 0.0 for identical images and 1.0 at most
+    pub flip: f32,
+    /// How visible the difference is at each pixel, from 0.0 to 1.0. A simplified version
+    /// of NVIDIA's FLIP
+    pub error_map: Vec<f32>,
+}
+
+/// Compare `image` against `reference`, which must be the same size
+pub fn compare(image: &ColorBuffer, reference: &ColorBuffer) -> Comparison {
+    assert_eq!(
+        (image.width, image.height),
+        (reference.width, reference.height)
+    );
+
+    let count = (image.pixels.len() * 3) as f32;
+    let mut squared_error = 0.0;
+    let mut relative_squared_error = 0.0;
+
+    for (pixel, reference) in image.pixels.iter().zip(&reference.pixels) {
+        for channel in 0..3 {
+            let error = pixel[channel] - reference[channel];
+            squared_error += error * error;
+            relative_squared_error +=
+                error * error / (reference[channel] * reference[channel] + RELATIVE_MSE_EPSILON);
+        }
+    }
+
+    let mse = squared_error / count;
+    let error_map = flip_error_map(image, reference);
+    let flip = error_map.iter().sum::<f32>() / error_map.len() as f32;
+
+    Comparison {
+        mse,
+        relative_mse: relative_squared_error / count,
+        psnr: 10.0 * (1.0 / mse).log10(),
+        ssim: ssim(image, reference),
+        flip,
+        error_map,
+    }
+}
+
+/// Error map as an image, going from black through purple and orange to yellow as the
+/// difference gets more visible
+pub fn false_color(error_map: &[f32], width: u32, height: u32) -> RgbaImage {
+    // Stops of a color map close to magma, evenly spaced from 0.0 to 1.0
+    let stops = [
+        Vector3::new(0.0, 0.0, 0.02),
+        Vector3::new(0.23, 0.06, 0.44),
+        Vector3::new(0.55, 0.16, 0.51),
+        Vector3::new(0.87, 0.29, 0.41),
+        Vector3::new(0.99, 0.62, 0.43),
+        Vector3::new(0.99, 0.99, 0.75),
+    ];
+
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let error = error_map[(y * width + x) as usize].clamp(0.0, 1.0);
+
+        let position = error * (stops.len() - 1) as f32;
+        let index = (position as usize).min(stops.len() - 2);
+        let color = stops[index].lerp(stops[index + 1], position - index as f32);
+
+        let color = (color * 255.99).cast::<u8>().unwrap();
+        Rgba::from_channels(color.x, color.y, color.z, 255)
+    })
+}
+
+// Mean SSIM of the luminance over windows spaced half a window apart
+fn ssim(image: &ColorBuffer, reference: &ColorBuffer) -> f32 {
+    let window = SSIM_WINDOW.min(image.width).min(image.height);
+    let step = (window / 2).max(1) as usize;
+
+    let mut total = 0.0;
+    let mut windows = 0;
+
+    for y in (0..=image.height - window).step_by(step) {
+        for x in (0..=image.width - window).step_by(step) {
+            let mut values = Vec::with_capacity((window * window) as usize);
+            for v in y..y + window {
+                for u in x..x + window {
+                    values.push((luminance(image.get(u, v)), luminance(reference.get(u, v))));
+                }
+            }
+
+            let count = values.len() as f32;
+            let mean_a = values.iter().map(|v| v.0).sum::<f32>() / count;
+            let mean_b = values.iter().map(|v| v.1).sum::<f32>() / count;
+
+            let mut variance_a = 0.0;
+            let mut variance_b = 0.0;
+            let mut covariance = 0.0;
+            for &(a, b) in &values {
+                variance_a += (a - mean_a) * (a - mean_a);
+                variance_b += (b - mean_b) * (b - mean_b);
+                covariance += (a - mean_a) * (b - mean_b);
+            }
+            let samples = (count - 1.0).max(1.0);
+            variance_a /= samples;
+            variance_b /= samples;
+            covariance /= samples;
+
+            total += ((2.0 * mean_a * mean_b + SSIM_C1) * (2.0 * covariance + SSIM_C2))
+                / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1)
+                    * (variance_a + variance_b + SSIM_C2));
+            windows += 1;
+        }
+    }
+
+    total / windows as f32
+}
+
+// Per pixel error in the style of FLIP. Both images are blurred the way the eye blurs
+// fine detail, compared in a perceptual color space, and the color error is then boosted
+// where edges or points differ between the images
+// https://research.nvidia.com/publication/2020-07_FLIP
+fn flip_error_map(image: &ColorBuffer, reference: &ColorBuffer) -> Vec<f32> {
+    let width = image.width;
+    let height = image.height;
+
+    let lab_image = blur(&to_lab(image), width, height);
+    let lab_reference = blur(&to_lab(reference), width, height);
+
+    let features_image = features(image);
+    let features_reference = features(reference);
+
+    lab_image
+        .iter()
+        .zip(&lab_reference)
+        .zip(features_image.iter().zip(&features_reference))
+        .map(|((a, b), (feature_a, feature_b))| {
+            // HyAB distance, city block in lightness and euclidean in color
+            let difference = a - b;
+            let color_difference = difference.x.abs()
+                + (difference.y * difference.y + difference.z * difference.z).sqrt();
+            let color_error = compress_color_error(color_difference);
+
+            let feature_error = ((feature_a.0 - feature_b.0)
+                .abs()
+                .max((feature_a.1 - feature_b.1).abs())
+                / 2f32.sqrt())
+            .min(1.0)
+            .powf(0.5);
+
+            color_error.powf(1.0 - feature_error)
+        })
+        .collect()
+}
+
+// Map a HyAB distance to 0..1, growing quickly for small differences
+fn compress_color_error(difference: f32) -> f32 {
+    let difference = difference.powf(0.7);
+    let cutoff = COLOR_CUTOFF * MAX_COLOR_DIFFERENCE.powf(0.7);
+
+    if difference < cutoff {
+        COLOR_CUTOFF_ERROR * difference / cutoff
+    } else {
+        let rest = MAX_COLOR_DIFFERENCE.powf(0.7) - cutoff;
+        (COLOR_CUTOFF_ERROR + (1.0 - COLOR_CUTOFF_ERROR) * (difference - cutoff) / rest).min(1.0)
+    }
+}
+
+// Gaussian blur, separated into a horizontal and a vertical pass
+fn blur(values: &[Vector3<f32>], width: u32, height: u32) -> Vec<Vector3<f32>> {
+    let kernel = gaussian_kernel(PERCEPTUAL_BLUR);
+    let radius = (kernel.len() / 2) as i32;
+
+    let pass = |values: &[Vector3<f32>], dx: i32, dy: i32| -> Vec<Vector3<f32>> {
+        (0..height as i32)
+            .flat_map(|y| (0..width as i32).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let mut sum = Vector3::zero();
+                for (i, weight) in kernel.iter().enumerate() {
+                    let offset = i as i32 - radius;
+                    let sample_x = (x + offset * dx).clamp(0, width as i32 - 1);
+                    let sample_y = (y + offset * dy).clamp(0, height as i32 - 1);
+
+                    sum += values[(sample_y * width as i32 + sample_x) as usize] * *weight;
+                }
+
+                sum
+            })
+            .collect()
+    };
+
+    let horizontal = pass(values, 1, 0);
+    pass(&horizontal, 0, 1)
+}
+
+// Edge and point strength of every pixel, from the first and second derivatives of the
+// luminance
+fn features(image: &ColorBuffer) -> Vec<(f32, f32)> {
+    let radius = (3.0 * PERCEPTUAL_BLUR).ceil() as i32;
+    let sigma2 = PERCEPTUAL_BLUR * PERCEPTUAL_BLUR;
+
+    let luminance_at = |x: i32, y: i32| {
+        let x = x.clamp(0, image.width as i32 - 1) as u32;
+        let y = y.clamp(0, image.height as i32 - 1) as u32;
+
+        luminance(image.get(x, y))
+    };
+
+    (0..image.height as i32)
+        .flat_map(|y| (0..image.width as i32).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let mut edge_x = 0.0;
+            let mut edge_y = 0.0;
+            let mut point_x = 0.0;
+            let mut point_y = 0.0;
+            let mut normalization = 0.0;
+
+            for v in -radius..=radius {
+                for u in -radius..=radius {
+                    let (u_f, v_f) = (u as f32, v as f32);
+                    let gaussian = (-(u_f * u_f + v_f * v_f) / (2.0 * sigma2)).exp();
+                    let value = luminance_at(x + u, y + v);
+
+                    // Derivatives of the gaussian
+                    edge_x += -u_f / sigma2 * gaussian * value;
+                    edge_y += -v_f / sigma2 * gaussian * value;
+                    point_x += (u_f * u_f / sigma2 - 1.0) / sigma2 * gaussian * value;
+                    point_y += (v_f * v_f / sigma2 - 1.0) / sigma2 * gaussian * value;
+                    normalization += gaussian;
+                }
+            }
+
+            let edge = (edge_x * edge_x + edge_y * edge_y).sqrt() / normalization;
+            let point = (point_x * point_x + point_y * point_y).sqrt() / normalization;
+
+            (edge, point)
+        })
+        .collect()
+}
+
+fn gaussian_kernel(sigma: f32) -> Vec<f32> {
+    let radius = (3.0 * sigma).ceil() as i32;
+    let kernel: Vec<f32> = (-radius..=radius)
+        .map(|x| (-(x * x) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect();
+
+    let total: f32 = kernel.iter().sum();
+    kernel.iter().map(|weight| weight / total).collect()
+}
+
+// Convert colors to CIELAB. Values are what gets shown on screen, so they're treated as
+// sRGB encoded and clamped to the displayable range
+fn to_lab(image: &ColorBuffer) -> Vec<Vector3<f32>> {
+    image
+        .pixels
+        .iter()
+        .map(|color| {
+            let linear = color.map(|channel| {
+                let channel = channel.clamp(0.0, 1.0);
+                if channel <= 0.04045 {
+                    channel / 12.92
+                } else {
+                    ((channel + 0.055) / 1.055).powf(2.4)
+                }
+            });
+
+            // Linear sRGB to XYZ, relative to the D65 white point
+            let x = (0.4124 * linear.x + 0.3576 * linear.y + 0.1805 * linear.z) / 0.9505;
+            let y = 0.2126 * linear.x + 0.7152 * linear.y + 0.0722 * linear.z;
+            let z = (0.0193 * linear.x + 0.1192 * linear.y + 0.9505 * linear.z) / 1.089;
+
+            let f = |t: f32| {
+                if t > 0.008856 {
+                    t.cbrt()
+                } else {
+                    7.787 * t + 16.0 / 116.0
+                }
+            };
+
+            Vector3::new(
+                116.0 * f(y) - 16.0,
+                500.0 * (f(x) - f(y)),
+                200.0 * (f(y) - f(z)),
+            )
+        })
+        .collect()
+}
+
+// Rec. 709 luminance
+fn luminance(color: Vector3<f32>) -> f32 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(value: f32) -> ColorBuffer {
+        ColorBuffer {
+            width: 16,
+            height: 12,
+            pixels: vec![Vector3::new(value, value, value); 16 * 12],
+        }
+    }
+
+    #[test]
+    fn identical_images() {
+        // A gradient, so SSIM has some structure to compare
+        let image = ColorBuffer {
+            width: 16,
+            height: 12,
+            pixels: (0..16 * 12)
+                .map(|i| Vector3::new((i % 16) as f32 / 16.0, (i / 16) as f32 / 12.0, 0.5))
+                .collect(),
+        };
+        let comparison = compare(&image, &image);
+
+        assert_eq!(comparison.mse, 0.0);
+        assert_eq!(comparison.relative_mse, 0.0);
+        assert!(comparison.psnr.is_infinite());
+        assert!((comparison.ssim - 1.0).abs() < 1e-6);
+        assert_eq!(comparison.flip, 0.0);
+    }
+
+    #[test]
+    fn constant_offset() {
+        let comparison = compare(&gray(0.6), &gray(0.5));
+
+        assert!((comparison.mse - 0.01).abs() < 1e-6);
+        assert!((comparison.relative_mse - 0.01 / (0.25 + RELATIVE_MSE_EPSILON)).abs() < 1e-6);
+        assert!((comparison.psnr - 20.0).abs() < 1e-3);
+
+        // Neither image varies, so only the means differ
+        let ssim = (2.0 * 0.6 * 0.5 + SSIM_C1) / (0.6 * 0.6 + 0.5 * 0.5 + SSIM_C1);
+        assert!((comparison.ssim - ssim).abs() < 1e-6);
+
+        // The same small error everywhere
+        assert!(comparison.flip > 0.0 && comparison.flip < 0.5);
+        assert!(comparison
+            .error_map
+            .iter()
+            .all(|&error| (error - comparison.flip).abs() < 1e-5));
+    }
+}
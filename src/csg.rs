@@ -0,0 +1,256 @@
+use std::f32;
+
+use aabb::Aabb;
+use hit::{HitRecord, Hittable, Solid, Span};
+use ray::Ray;
+
+/// How the insides of two solids are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsgOperation {
+    /// Inside either solid
+    Union,
+    /// Inside both solids
+    Intersection,
+    /// Inside the first solid but not the second
+    Difference,
+}
+
+impl CsgOperation {
+    fn contains(self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_left || in_right,
+            CsgOperation::Intersection => in_left && in_right,
+            CsgOperation::Difference => in_left && !in_right,
+        }
+    }
+}
+
+/// Constructive solid geometry, a solid made by combining two others. The result is a
+/// solid too, so combinations can be nested to build up more complicated shapes.
+///
+/// Surfaces keep the material of the solid they came from. Where the second solid is cut
+/// out of the first the cut shows the material of the second
+pub struct Csg {
+    operation: CsgOperation,
+    left: Box<dyn Solid + Sync>,
+    right: Box<dyn Solid + Sync>,
+    bounds: Aabb,
+}
+
+impl Csg {
+    pub fn new(
+        operation: CsgOperation,
+        left: Box<dyn Solid + Sync>,
+        right: Box<dyn Solid + Sync>,
+    ) -> Csg {
+        let bounds = match operation {
+            CsgOperation::Union => left.bounding_box().union(&right.bounding_box()),
+            CsgOperation::Intersection => left.bounding_box().intersection(&right.bounding_box()),
+            CsgOperation::Difference => left.bounding_box(),
+        };
+
+        Csg {
+            operation,
+            left,
+            right,
+            bounds,
+        }
+    }
+
+    pub fn union(left: Box<dyn Solid + Sync>, right: Box<dyn Solid + Sync>) -> Csg {
+        Csg::new(CsgOperation::Union, left, right)
+    }
+
+    pub fn intersection(left: Box<dyn Solid + Sync>, right: Box<dyn Solid + Sync>) -> Csg {
+        Csg::new(CsgOperation::Intersection, left, right)
+    }
+
+    pub fn difference(left: Box<dyn Solid + Sync>, right: Box<dyn Solid + Sync>) -> Csg {
+        Csg::new(CsgOperation::Difference, left, right)
+    }
+}
+
+impl Hittable for Csg {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.bounds.intersect(ray, t_min, t_max)?;
+
+        self.spans(ray)
+            .into_iter()
+            .flat_map(|span| vec![span.enter, span.exit])
+            .find(|record| record.t > t_min && record.t < t_max)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bounds
+    }
+}
+
+impl Solid for Csg {
+    // Walk along the ray through every place it crosses the surface of either solid,
+    // keeping track of which ones it's inside. A span of the result starts wherever the
+    // operation goes from outside to inside and ends where it goes back out
+    fn spans(&self, ray: Ray) -> Vec<Span<'_>> {
+        if self.bounds.intersect(ray, -f32::MAX, f32::MAX).is_none() {
+            return Vec::new();
+        }
+
+        let left = self.left.spans(ray);
+        let right = self.right.spans(ray);
+
+        // Each crossing, whether it's of the left solid and whether it goes inside
+        let mut crossings: Vec<(HitRecord, bool, bool)> =
+            Vec::with_capacity(2 * (left.len() + right.len()));
+        for (spans, is_left) in [(left, true), (right, false)] {
+            for span in spans {
+                crossings.push((span.enter, is_left, true));
+                crossings.push((span.exit, is_left, false));
+            }
+        }
+        crossings.sort_by(|a, b| a.0.t.total_cmp(&b.0.t));
+
+        let mut in_left = false;
+        let mut in_right = false;
+        let mut enter: Option<HitRecord> = None;
+        let mut spans = Vec::new();
+
+        for (mut record, is_left, entering) in crossings {
+            if is_left {
+                in_left = entering;
+            } else {
+                in_right = entering;
+            }
+
+            // The inside of the second solid is the outside of a difference, so its
+            // surface faces the other way
+            if !is_left && self.operation == CsgOperation::Difference {
+                record.normal = -record.normal;
+            }
+
+            let inside = self.operation.contains(in_left, in_right);
+            match enter {
+                None if inside => enter = Some(record),
+                Some(start) if !inside => {
+                    // Solids that only touch leave nothing in between
+                    if start.t < record.t {
+                        spans.push(Span {
+                            enter: start,
+                            exit: record,
+                        });
+                    }
+                    enter = None;
+                }
+                _ => {}
+            }
+        }
+
+        spans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::prelude::*;
+    use cgmath::Vector3;
+
+    use super::*;
+    use material::Material;
+    use sphere::Sphere;
+
+    fn sphere(x: f32, radius: f32) -> Box<dyn Solid + Sync> {
+        Box::new(Sphere::new(
+            Vector3::new(x, 0.0, 0.0),
+            radius,
+            Material::new_lambertian(0.5, 0.5, 0.5),
+        ))
+    }
+
+    // Ray along the x-axis, starting at x = -5
+    fn ray_along_x() -> Ray {
+        Ray::new(Vector3::new(-5.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0))
+    }
+
+    fn ends(spans: &[Span]) -> Vec<(f32, f32)> {
+        spans
+            .iter()
+            .map(|span| (span.enter.t, span.exit.t))
+            .collect()
+    }
+
+    fn assert_close(actual: Vec<(f32, f32)>, expected: &[(f32, f32)]) {
+        assert_eq!(
+            actual.len(),
+            expected.len(),
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert!(
+                (actual.0 - expected.0).abs() < 1e-4 && (actual.1 - expected.1).abs() < 1e-4,
+                "{:?} != {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn union_merges_overlapping_spans() {
+        let csg = Csg::union(sphere(-0.5, 1.0), sphere(0.5, 1.0));
+        assert_close(ends(&csg.spans(ray_along_x())), &[(3.5, 6.5)]);
+    }
+
+    #[test]
+    fn union_keeps_separate_spans() {
+        let csg = Csg::union(sphere(-2.0, 1.0), sphere(2.0, 1.0));
+        assert_close(ends(&csg.spans(ray_along_x())), &[(2.0, 4.0), (6.0, 8.0)]);
+    }
+
+    #[test]
+    fn intersection_makes_a_lens() {
+        let csg = Csg::intersection(sphere(-0.5, 1.0), sphere(0.5, 1.0));
+        let spans = csg.spans(ray_along_x());
+        assert_close(ends(&spans), &[(4.5, 5.5)]);
+
+        // Each side of the lens is the surface of the sphere on the other side
+        assert!((spans[0].enter.normal - Vector3::new(-1.0, 0.0, 0.0)).magnitude() < 1e-4);
+        assert!((spans[0].exit.normal - Vector3::new(1.0, 0.0, 0.0)).magnitude() < 1e-4);
+    }
+
+    #[test]
+    fn intersection_of_separate_solids_is_empty() {
+        let csg = Csg::intersection(sphere(-2.0, 1.0), sphere(2.0, 1.0));
+        assert!(csg.spans(ray_along_x()).is_empty());
+        assert!(csg.hit(ray_along_x(), 0.001, f32::MAX).is_none());
+    }
+
+    #[test]
+    fn difference_makes_a_hollow_sphere() {
+        let csg = Csg::difference(sphere(0.0, 1.0), sphere(0.0, 0.5));
+        let spans = csg.spans(ray_along_x());
+        assert_close(ends(&spans), &[(4.0, 4.5), (5.5, 6.0)]);
+
+        // The inner surface faces into the hollow
+        assert!((spans[0].exit.normal - Vector3::new(1.0, 0.0, 0.0)).magnitude() < 1e-4);
+        assert!((spans[1].enter.normal - Vector3::new(-1.0, 0.0, 0.0)).magnitude() < 1e-4);
+    }
+
+    #[test]
+    fn hit_from_inside_the_hollow() {
+        let csg = Csg::difference(sphere(0.0, 1.0), sphere(0.0, 0.5));
+        let ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+
+        let record = csg.hit(ray, 0.001, f32::MAX).unwrap();
+        assert!((record.t - 0.5).abs() < 1e-4);
+        assert!((record.normal - Vector3::new(-1.0, 0.0, 0.0)).magnitude() < 1e-4);
+    }
+
+    #[test]
+    fn nested_operations() {
+        // A hollow sphere cut in half, leaving a bowl
+        let hollow = Csg::difference(sphere(0.0, 1.0), sphere(0.0, 0.5));
+        let bowl = Csg::difference(Box::new(hollow), sphere(-1.0, 1.0));
+
+        assert_close(ends(&bowl.spans(ray_along_x())), &[(5.5, 6.0)]);
+    }
+}
@@ -0,0 +1,94 @@
+use cgmath::{Vector2, Vector3};
+
+use std::f32;
+
+use aabb::Aabb;
+use hit::{HitRecord, Hittable, Solid, Span};
+use material::Material;
+use ray::Ray;
+use stats::{self, Counter};
+
+/// An axis aligned box
+pub struct Cuboid {
+    bounds: Aabb,
+    material: Material,
+}
+
+impl Cuboid {
+    /// min and max are opposite corners of the box
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>, material: Material) -> Self {
+        Cuboid {
+            bounds: Aabb::new(min, max),
+            material,
+        }
+    }
+
+    fn record(&self, ray: Ray, t: f32) -> HitRecord<'_> {
+        let position = ray.point_at_distance(t);
+        let relative = self.bounds.relative_position(position);
+
+        // The face that was hit is the one the relative position is closest to 0.0 or 1.0 on
+        let mut axis = 0;
+        let mut closest = f32::MAX;
+        for i in 0..3 {
+            let distance = relative[i].min(1.0 - relative[i]).abs();
+            if distance < closest {
+                closest = distance;
+                axis = i;
+            }
+        }
+
+        let mut normal = Vector3::new(0.0, 0.0, 0.0);
+        normal[axis] = if relative[axis] < 0.5 { -1.0 } else { 1.0 };
+
+        // Faces are parameterized by the two axes that lie in them
+        let uv = Vector2::new(relative[(axis + 1) % 3], relative[(axis + 2) % 3]);
+
+        HitRecord {
+            t,
+            position,
+            normal,
+            uv,
+            material: self.material,
+            object_id: 0,
+            medium: None,
+        }
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        stats::count(Counter::IntersectionTests);
+
+        let (t_enter, t_exit) = self.bounds.intersect(ray, -f32::MAX, f32::MAX)?;
+
+        // Rays starting inside the box hit the face they leave through
+        let t = if t_enter > t_min && t_enter < t_max {
+            t_enter
+        } else if t_exit > t_min && t_exit < t_max {
+            t_exit
+        } else {
+            return None;
+        };
+
+        Some(self.record(ray, t))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bounds
+    }
+}
+
+impl Solid for Cuboid {
+    fn spans(&self, ray: Ray) -> Vec<Span<'_>> {
+        stats::count(Counter::IntersectionTests);
+
+        match self.bounds.intersect(ray, -f32::MAX, f32::MAX) {
+            Some((t_enter, t_exit)) => vec![Span {
+                enter: self.record(ray, t_enter),
+                exit: self.record(ray, t_exit),
+            }],
+            None => Vec::new(),
+        }
+    }
+}
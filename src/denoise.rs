@@ -0,0 +1,201 @@
+use cgmath::prelude::*;
+use cgmath::Vector3;
+
+use rayon::prelude::*;
+
+use exr::{ChannelData, ExrImage};
+
+// Weights of the 5 by 5 B3 spline kernel along one axis
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// Number of filter passes, each one doubles the distance between the kernel taps so the
+// last one reaches 2 * (2^5 - 1) = 62 pixels
+const ITERATIONS: u32 = 5;
+
+// Albedo channels below this aren't divided out, nothing is left to recover there
+const MIN_ALBEDO: f32 = 0.01;
+
+/// How strongly differences between pixels stop the filter from blurring across them.
+/// Smaller values keep more detail and remove less noise
+#[derive(Debug, Clone, Copy)]
+pub struct DenoiseSettings {
+    pub color_sigma: f32,
+    pub normal_sigma: f32,
+    pub albedo_sigma: f32,
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> DenoiseSettings {
+        DenoiseSettings {
+            color_sigma: 0.6,
+            normal_sigma: 0.1,
+            albedo_sigma: 0.1,
+        }
+    }
+}
+
+/// Denoise the `R`, `G` and `B` channels of an image, guided by its `albedo` and `normal`
+/// layers like the ones written with `--aovs albedo,normal`. The result only has the
+/// denoised `R`, `G` and `B` channels
+pub fn denoise_image(image: &ExrImage, settings: DenoiseSettings) -> Result<ExrImage, String> {
+    let buffers = DenoiseBuffers::from_exr(image)?;
+    let color = denoise(&buffers, settings);
+
+    let mut denoised = ExrImage::new(image.width, image.height);
+    for (channel, name) in ["R", "G", "B"].iter().enumerate() {
+        let values = color.iter().map(|color| color[channel]).collect();
+        denoised.insert(name, ChannelData::Float(values));
+    }
+
+    Ok(denoised)
+}
+
+// The noisy image along with the feature buffers guiding the denoiser
+struct DenoiseBuffers {
+    width: u32,
+    height: u32,
+    color: Vec<Vector3<f32>>,
+    albedo: Vec<Vector3<f32>>,
+    normal: Vec<Vector3<f32>>,
+}
+
+impl DenoiseBuffers {
+    fn from_exr(image: &ExrImage) -> Result<DenoiseBuffers, String> {
+        let layer = |names: [&str; 3]| -> Result<Vec<Vector3<f32>>, String> {
+            let channels = names
+                .iter()
+                .map(|name| {
+                    image
+                        .channel(name)
+                        .ok_or_else(|| format!("image has no {} channel", name))
+                })
+                .collect::<Result<Vec<&ChannelData>, String>>()?;
+
+            Ok((0..(image.width * image.height) as usize)
+                .map(|index| {
+                    Vector3::new(
+                        channels[0].get(index),
+                        channels[1].get(index),
+                        channels[2].get(index),
+                    )
+                })
+                .collect())
+        };
+
+        Ok(DenoiseBuffers {
+            width: image.width,
+            height: image.height,
+            color: layer(["R", "G", "B"])?,
+            albedo: layer(["albedo.R", "albedo.G", "albedo.B"])?,
+            normal: layer(["normal.X", "normal.Y", "normal.Z"])?,
+        })
+    }
+}
+
+// Remove noise from the color buffer with an edge-avoiding à-trous wavelet filter, a
+// cross-bilateral filter that grows its footprint every pass. Pixels only blur together
+// if their colors, normals and albedos are alike, so edges and textures survive.
+//
+// The albedo is divided out before filtering and multiplied back in afterwards, so
+// texture detail isn't blurred along with the noise in the lighting
+fn denoise(buffers: &DenoiseBuffers, settings: DenoiseSettings) -> Vec<Vector3<f32>> {
+    let albedo: Vec<Vector3<f32>> = buffers
+        .albedo
+        .iter()
+        .map(|albedo| albedo.map(|channel| if channel < MIN_ALBEDO { 1.0 } else { channel }))
+        .collect();
+
+    let mut lighting: Vec<Vector3<f32>> = buffers
+        .color
+        .iter()
+        .zip(&albedo)
+        .map(|(color, albedo)| color.div_element_wise(*albedo))
+        .collect();
+
+    for iteration in 0..ITERATIONS {
+        // Noise left after each pass is smaller, so differences in color have to be too
+        let color_sigma = settings.color_sigma / 2f32.powi(iteration as i32);
+        lighting = filter_pass(buffers, &lighting, 1 << iteration, color_sigma, settings);
+    }
+
+    lighting
+        .iter()
+        .zip(&albedo)
+        .map(|(lighting, albedo)| lighting.mul_element_wise(*albedo))
+        .collect()
+}
+
+// One pass of the filter, with `step` pixels between the taps of the kernel
+fn filter_pass(
+    buffers: &DenoiseBuffers,
+    lighting: &[Vector3<f32>],
+    step: i32,
+    color_sigma: f32,
+    settings: DenoiseSettings,
+) -> Vec<Vector3<f32>> {
+    let width = buffers.width as i32;
+    let height = buffers.height as i32;
+
+    let mut output = vec![Vector3::zero(); lighting.len()];
+    output
+        .par_chunks_mut(width as usize)
+        .enumerate()
+        .for_each(|(y, row)| {
+            let y = y as i32;
+
+            for (x, output) in row.iter_mut().enumerate() {
+                let x = x as i32;
+                let center = (y * width + x) as usize;
+                let center_color = compress(lighting[center]);
+
+                let mut sum = Vector3::zero();
+                let mut total_weight = 0.0;
+
+                for (j, kernel_y) in KERNEL.iter().enumerate() {
+                    let sample_y = y + (j as i32 - 2) * step;
+                    if sample_y < 0 || sample_y >= height {
+                        continue;
+                    }
+
+                    for (i, kernel_x) in KERNEL.iter().enumerate() {
+                        let sample_x = x + (i as i32 - 2) * step;
+                        if sample_x < 0 || sample_x >= width {
+                            continue;
+                        }
+
+                        let sample = (sample_y * width + sample_x) as usize;
+
+                        let color_distance =
+                            (compress(lighting[sample]) - center_color).magnitude2();
+                        let normal_distance =
+                            (buffers.normal[sample] - buffers.normal[center]).magnitude2();
+                        let albedo_distance =
+                            (buffers.albedo[sample] - buffers.albedo[center]).magnitude2();
+
+                        let weight = kernel_x
+                            * kernel_y
+                            * (-color_distance / (color_sigma * color_sigma)
+                                - normal_distance
+                                    / (settings.normal_sigma * settings.normal_sigma)
+                                - albedo_distance
+                                    / (settings.albedo_sigma * settings.albedo_sigma))
+                                .exp();
+
+                        sum += lighting[sample] * weight;
+                        total_weight += weight;
+                    }
+                }
+
+                // The center pixel always has a weight, so this never divides by zero
+                *output = sum / total_weight;
+            }
+        });
+
+    output
+}
+
+// Map colors into 0..1 so very bright pixels don't stop everything around them from
+// blurring together
+fn compress(color: Vector3<f32>) -> Vector3<f32> {
+    color.map(|channel| channel.max(0.0) / (1.0 + channel.max(0.0)))
+}
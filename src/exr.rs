@@ -0,0 +1,494 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use image::{ImageBuffer, Pixel, Rgba, RgbaImage};
+
+// First four bytes of every OpenEXR file
+const MAGIC: [u8; 4] = [0x76, 0x2F, 0x31, 0x01];
+
+// Pixel types of a channel
+const UINT: i32 = 0;
+const HALF: i32 = 1;
+const FLOAT: i32 = 2;
+
+/// Values of one channel of an image, stored row by row starting at the top
+#[derive(Debug, Clone)]
+pub enum ChannelData {
+    Float(Vec<f32>),
+    Uint(Vec<u32>),
+}
+
+impl ChannelData {
+    /// Value of a pixel converted to a float
+    pub fn get(&self, index: usize) -> f32 {
+        match *self {
+            ChannelData::Float(ref values) => values[index],
+            ChannelData::Uint(ref values) => values[index] as f32,
+        }
+    }
+
+    fn pixel_type(&self) -> i32 {
+        match *self {
+            ChannelData::Float(_) => FLOAT,
+            ChannelData::Uint(_) => UINT,
+        }
+    }
+}
+
+/// An image with any number of named channels. Layers are channels sharing a prefix,
+/// like `albedo.R`, `albedo.G` and `albedo.B`
+#[derive(Debug, Clone)]
+pub struct ExrImage {
+    pub width: u32,
+    pub height: u32,
+    // Sorted by name, which is the order channels are stored in
+    pub channels: BTreeMap<String, ChannelData>,
+}
+
+impl ExrImage {
+    pub fn new(width: u32, height: u32) -> ExrImage {
+        ExrImage {
+            width,
+            height,
+            channels: BTreeMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, name: &str, data: ChannelData) {
+        self.channels.insert(name.to_string(), data);
+    }
+
+    pub fn channel(&self, name: &str) -> Option<&ChannelData> {
+        self.channels.get(name)
+    }
+
+    /// The `R`, `G` and `B` channels as an 8 bit image, clamped to 0..1. Missing channels
+    /// are black
+    pub fn to_image(&self) -> RgbaImage {
+        let channel = |name: &str, index: usize| {
+            self.channel(name)
+                .map_or(0.0, |data| data.get(index))
+                .clamp(0.0, 1.0)
+        };
+
+        ImageBuffer::from_fn(self.width, self.height, |x, y| {
+            let index = (y * self.width + x) as usize;
+            let rgb = [
+                channel("R", index),
+                channel("G", index),
+                channel("B", index),
+            ];
+
+            let bytes: Vec<u8> = rgb.iter().map(|value| (value * 255.99) as u8).collect();
+            Rgba::from_channels(bytes[0], bytes[1], bytes[2], 255)
+        })
+    }
+
+    /// Write an uncompressed scanline file
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        writer.write_all(&MAGIC)?;
+        // Version 2, single part scanline file
+        writer.write_all(&2u32.to_le_bytes())?;
+
+        let mut channel_list = Vec::new();
+        for (name, data) in &self.channels {
+            channel_list.extend_from_slice(name.as_bytes());
+            channel_list.push(0);
+            channel_list.extend_from_slice(&data.pixel_type().to_le_bytes());
+            // Not perceptually linear, then three reserved bytes
+            channel_list.extend_from_slice(&[0, 0, 0, 0]);
+            // No subsampling in x or y
+            channel_list.extend_from_slice(&1i32.to_le_bytes());
+            channel_list.extend_from_slice(&1i32.to_le_bytes());
+        }
+        channel_list.push(0);
+
+        let mut window = Vec::new();
+        for &value in &[0, 0, self.width as i32 - 1, self.height as i32 - 1] {
+            window.extend_from_slice(&value.to_le_bytes());
+        }
+
+        write_attribute(&mut writer, "channels", "chlist", &channel_list)?;
+        // No compression
+        write_attribute(&mut writer, "compression", "compression", &[0])?;
+        write_attribute(&mut writer, "dataWindow", "box2i", &window)?;
+        write_attribute(&mut writer, "displayWindow", "box2i", &window)?;
+        // Rows stored from top to bottom
+        write_attribute(&mut writer, "lineOrder", "lineOrder", &[0])?;
+        write_attribute(
+            &mut writer,
+            "pixelAspectRatio",
+            "float",
+            &1.0f32.to_le_bytes(),
+        )?;
+        write_attribute(&mut writer, "screenWindowCenter", "v2f", &[0; 8])?;
+        write_attribute(
+            &mut writer,
+            "screenWindowWidth",
+            "float",
+            &1.0f32.to_le_bytes(),
+        )?;
+        writer.write_all(&[0])?;
+
+        // Every value is four bytes, so all rows are the same size
+        let header_size =
+            MAGIC.len() as u64 + 4 + self.attributes_size(channel_list.len(), window.len());
+        let row_size = 4 * self.width as u64 * self.channels.len() as u64;
+        let table_size = 8 * u64::from(self.height);
+
+        for y in 0..u64::from(self.height) {
+            let offset = header_size + table_size + y * (8 + row_size);
+            writer.write_all(&offset.to_le_bytes())?;
+        }
+
+        for y in 0..self.height {
+            writer.write_all(&(y as i32).to_le_bytes())?;
+            writer.write_all(&(row_size as i32).to_le_bytes())?;
+
+            let start = (y * self.width) as usize;
+            let end = start + self.width as usize;
+            for data in self.channels.values() {
+                match *data {
+                    ChannelData::Float(ref values) => {
+                        for value in &values[start..end] {
+                            writer.write_all(&value.to_le_bytes())?;
+                        }
+                    }
+                    ChannelData::Uint(ref values) => {
+                        for value in &values[start..end] {
+                            writer.write_all(&value.to_le_bytes())?;
+                        }
+                    }
+                }
+            }
+        }
+
+        writer.flush()
+    }
+
+    // Size of the header attributes written by `save`, including the terminating zero
+    fn attributes_size(&self, channel_list_size: usize, window_size: usize) -> u64 {
+        let attributes = [
+            ("channels", "chlist", channel_list_size),
+            ("compression", "compression", 1),
+            ("dataWindow", "box2i", window_size),
+            ("displayWindow", "box2i", window_size),
+            ("lineOrder", "lineOrder", 1),
+            ("pixelAspectRatio", "float", 4),
+            ("screenWindowCenter", "v2f", 8),
+            ("screenWindowWidth", "float", 4),
+        ];
+
+        let size: usize = attributes
+            .iter()
+            .map(|&(name, kind, size)| name.len() + 1 + kind.len() + 1 + 4 + size)
+            .sum();
+
+        size as u64 + 1
+    }
+
+    /// Read an uncompressed scanline file, like the ones written by `save`. Half float
+    /// channels are converted to floats
+    pub fn load(path: &Path) -> io::Result<ExrImage> {
+        let mut bytes = Vec::new();
+        BufReader::new(File::open(path)?).read_to_end(&mut bytes)?;
+        let mut reader = ByteReader {
+            bytes: &bytes,
+            position: 0,
+        };
+
+        if reader.take(4)? != MAGIC {
+            return Err(invalid_data("file is not an OpenEXR image"));
+        }
+
+        let version = reader.u32()?;
+        // Tiled, deep and multi-part files set flags above the version number
+        if version & 0xFF != 2 || version & 0x1A00 != 0 {
+            return Err(invalid_data(
+                "only single part scanline OpenEXR images are supported",
+            ));
+        }
+
+        let mut channels = Vec::new();
+        let mut compression = None;
+        let mut window = None;
+
+        loop {
+            let name = reader.string()?;
+            if name.is_empty() {
+                break;
+            }
+
+            let _kind = reader.string()?;
+            let size = reader.u32()? as usize;
+            let mut value = ByteReader {
+                bytes: reader.take(size)?,
+                position: 0,
+            };
+
+            match name.as_str() {
+                "channels" => loop {
+                    let channel = value.string()?;
+                    if channel.is_empty() {
+                        break;
+                    }
+
+                    let pixel_type = value.u32()? as i32;
+                    value.take(4)?;
+                    if value.u32()? != 1 || value.u32()? != 1 {
+                        return Err(invalid_data("subsampled channels are not supported"));
+                    }
+
+                    channels.push((channel, pixel_type));
+                },
+                "compression" => compression = Some(value.take(1)?[0]),
+                "dataWindow" => {
+                    let x_min = value.u32()? as i32;
+                    let y_min = value.u32()? as i32;
+                    let x_max = value.u32()? as i32;
+                    let y_max = value.u32()? as i32;
+                    let extent = |min: i32, max: i32| {
+                        max.checked_sub(min).and_then(|size| size.checked_add(1))
+                    };
+                    window = Some((extent(x_min, x_max), extent(y_min, y_max)));
+                }
+                _ => {}
+            }
+        }
+
+        if compression != Some(0) {
+            return Err(invalid_data(
+                "only uncompressed OpenEXR images are supported",
+            ));
+        }
+
+        let (width, height) = match window {
+            Some((Some(width), Some(height))) if width > 0 && height > 0 => {
+                (width as u32, height as u32)
+            }
+            _ => return Err(invalid_data("OpenEXR image has no valid data window")),
+        };
+
+        let mut pixel_size = 0;
+        for &(_, pixel_type) in &channels {
+            pixel_size += match pixel_type {
+                HALF => 2,
+                UINT | FLOAT => 4,
+                _ => return Err(invalid_data("OpenEXR image has an unknown pixel type")),
+            };
+        }
+
+        // Check the pixels fit in the file before allocating room for them. Every row has
+        // an entry in the offset table, and a y coordinate and size in front of its pixels
+        let size = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|count| count.checked_mul(pixel_size))
+            .and_then(|size| size.checked_add(16 * height as usize));
+        if size.is_none_or(|size| size > reader.remaining()) {
+            return Err(invalid_data("OpenEXR image is truncated"));
+        }
+        // Can't overflow now that the rows are known to fit in the file
+        let pixel_count = width as usize * height as usize;
+
+        // Channels are stored sorted by name, the same order as the header lists them
+        let mut data: Vec<ChannelData> = channels
+            .iter()
+            .map(|&(_, pixel_type)| match pixel_type {
+                UINT => ChannelData::Uint(Vec::with_capacity(pixel_count)),
+                _ => ChannelData::Float(Vec::with_capacity(pixel_count)),
+            })
+            .collect();
+
+        // Skip the offset table, rows are read in order
+        reader.take(8 * height as usize)?;
+
+        for _ in 0..height {
+            let _y = reader.u32()?;
+            let _size = reader.u32()?;
+
+            for (channel, &(_, pixel_type)) in data.iter_mut().zip(&channels) {
+                for _ in 0..width {
+                    match *channel {
+                        ChannelData::Uint(ref mut values) => values.push(reader.u32()?),
+                        ChannelData::Float(ref mut values) => values.push(if pixel_type == HALF {
+                            half_to_f32(reader.u16()?)
+                        } else {
+                            f32::from_bits(reader.u32()?)
+                        }),
+                    }
+                }
+            }
+        }
+
+        let mut image = ExrImage::new(width, height);
+        for ((name, _), channel) in channels.into_iter().zip(data) {
+            image.channels.insert(name, channel);
+        }
+
+        Ok(image)
+    }
+}
+
+fn write_attribute<W: Write>(
+    writer: &mut W,
+    name: &str,
+    kind: &str,
+    value: &[u8],
+) -> io::Result<()> {
+    writer.write_all(name.as_bytes())?;
+    writer.write_all(&[0])?;
+    writer.write_all(kind.as_bytes())?;
+    writer.write_all(&[0])?;
+    writer.write_all(&(value.len() as u32).to_le_bytes())?;
+    writer.write_all(value)
+}
+
+// Reads little endian values from a byte slice
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        if count > self.remaining() {
+            return Err(invalid_data("OpenEXR image is truncated"));
+        }
+        let end = self.position + count;
+
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.position
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    // Zero terminated string
+    fn string(&mut self) -> io::Result<String> {
+        let length = self.bytes[self.position..]
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or_else(|| invalid_data("OpenEXR header is truncated"))?;
+        let bytes = self.take(length + 1)?;
+
+        Ok(String::from_utf8_lossy(&bytes[..length]).into_owned())
+    }
+}
+
+// Convert an IEEE 754 half precision float to a float
+fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = i32::from((half >> 10) & 0x1F);
+    let mantissa = f32::from(half & 0x3FF);
+
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        31 if mantissa == 0.0 => sign * f32::INFINITY,
+        31 => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+
+    // Saves a small image, replaces its data window and loads it back
+    fn with_window(name: &str, window: [i32; 4]) -> io::Result<ExrImage> {
+        let path = env::temp_dir().join(name);
+        let mut image = ExrImage::new(3, 2);
+        image.insert("Y", ChannelData::Float(vec![0.5; 6]));
+        image.save(&path)?;
+
+        let mut bytes = fs::read(&path)?;
+        let attribute = b"dataWindow\0box2i\0";
+        let start = bytes
+            .windows(attribute.len())
+            .position(|bytes| bytes == attribute)
+            .unwrap()
+            + attribute.len()
+            + 4;
+        for (i, value) in window.iter().enumerate() {
+            bytes[start + 4 * i..start + 4 * i + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        fs::write(&path, bytes)?;
+
+        let image = ExrImage::load(&path);
+        fs::remove_file(path)?;
+        image
+    }
+
+    #[test]
+    fn round_trip() {
+        let image = with_window("ray-tracer-exr-round-trip.exr", [0, 0, 2, 1]).unwrap();
+        assert_eq!((image.width, image.height), (3, 2));
+        assert_eq!(image.channel("Y").unwrap().get(5), 0.5);
+    }
+
+    #[test]
+    fn overflowing_data_window_is_rejected() {
+        let error = with_window(
+            "ray-tracer-exr-overflowing-window.exr",
+            [i32::MIN, 0, i32::MAX, 1],
+        )
+        .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn data_window_larger_than_the_file_is_rejected() {
+        let error = with_window(
+            "ray-tracer-exr-huge-window.exr",
+            [0, 0, i32::MAX - 1, i32::MAX - 1],
+        )
+        .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn offsets_point_at_the_scanlines() {
+        let path = env::temp_dir().join("ray-tracer-exr-offsets.exr");
+        let mut image = ExrImage::new(3, 2);
+        image.insert("Y", ChannelData::Float(vec![0.5; 6]));
+        image.save(&path).unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        // Each row is its y coordinate, its size and three floats
+        let row_size = 4 + 4 + 3 * 4;
+        let table_start = bytes.len() - 2 * row_size - 2 * 8;
+        for y in 0..2 {
+            let entry = table_start + 8 * y;
+            let mut offset = [0; 8];
+            offset.copy_from_slice(&bytes[entry..entry + 8]);
+
+            let offset = u64::from_le_bytes(offset) as usize;
+            assert_eq!(offset, table_start + 2 * 8 + y * row_size);
+            assert_eq!(bytes[offset..offset + 4], (y as i32).to_le_bytes());
+        }
+    }
+}
@@ -0,0 +1,526 @@
+use cgmath::prelude::*;
+use cgmath::{Vector2, Vector3};
+
+use image::{ImageBuffer, Pixel, Rgba, RgbaImage};
+
+use std::io::{self, Read, Write};
+
+use adaptive::PixelStatistics;
+use aov::{self, Aov, AovPixel, AovSample};
+use exr::{ChannelData, ExrImage};
+
+/// Names of every filter that can be created with `create_filter`
+pub const FILTER_NAMES: &[&str] = &["box", "tent", "gaussian", "mitchell", "lanczos"];
+
+/// Reconstruction filter deciding how much a sample contributes to the pixels around it.
+/// Every filter is separable, so the weight is the product of the x and y weights
+#[derive(Debug, Clone, Copy)]
+pub enum Filter {
+    Box { radius: f32 },
+    Tent { radius: f32 },
+    Gaussian { radius: f32, alpha: f32 },
+    Mitchell { radius: f32, b: f32, c: f32 },
+    Lanczos { radius: f32 },
+}
+
+/// Create a filter by name. Returns None if no filter has that name. Each filter has a
+/// default radius in pixels that is used when none is given
+pub fn create_filter(name: &str, radius: Option<f32>) -> Option<Filter> {
+    let filter = match name {
+        "box" => Filter::Box {
+            radius: radius.unwrap_or(0.5),
+        },
+        "tent" => Filter::Tent {
+            radius: radius.unwrap_or(1.0),
+        },
+        "gaussian" => Filter::Gaussian {
+            radius: radius.unwrap_or(1.5),
+            alpha: 2.0,
+        },
+        "mitchell" => Filter::Mitchell {
+            radius: radius.unwrap_or(2.0),
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        },
+        "lanczos" => Filter::Lanczos {
+            radius: radius.unwrap_or(3.0),
+        },
+        _ => return None,
+    };
+
+    Some(filter)
+}
+
+impl Filter {
+    /// Distance from a sample, in pixels, past which the filter is zero
+    pub fn radius(&self) -> f32 {
+        match *self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. }
+            | Filter::Lanczos { radius } => radius,
+        }
+    }
+
+    /// Weight of a sample offset from a pixel center by `offset` pixels
+    pub fn evaluate(&self, offset: Vector2<f32>) -> f32 {
+        self.evaluate_1d(offset.x) * self.evaluate_1d(offset.y)
+    }
+
+    fn evaluate_1d(&self, x: f32) -> f32 {
+        match *self {
+            // Half open so a sample on the border between two pixels only lands in one,
+            // which makes a radius of 0.5 exactly the same as averaging inside each pixel
+            Filter::Box { radius } => {
+                if x >= -radius && x < radius {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Filter::Tent { radius } => (radius - x.abs()).max(0.0),
+            // Shifted down so it reaches zero at the radius instead of being cut off
+            Filter::Gaussian { radius, alpha } => {
+                ((-alpha * x * x).exp() - (-alpha * radius * radius).exp()).max(0.0)
+            }
+            Filter::Mitchell { radius, b, c } => mitchell(2.0 * x.abs() / radius, b, c),
+            Filter::Lanczos { radius } => {
+                if x.abs() < radius {
+                    sinc(x) * sinc(x / radius)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+// Cubic from "Reconstruction Filters in Computer Graphics" by Mitchell and Netravali,
+// defined over the range 0 to 2
+fn mitchell(x: f32, b: f32, c: f32) -> f32 {
+    let weight = if x > 2.0 {
+        0.0
+    } else if x > 1.0 {
+        (-b - 6.0 * c) * x * x * x
+            + (6.0 * b + 30.0 * c) * x * x
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c)
+    } else {
+        (12.0 - 9.0 * b - 6.0 * c) * x * x * x
+            + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+            + (6.0 - 2.0 * b)
+    };
+
+    weight / 6.0
+}
+
+// Normalized sinc, sin(pi x) / (pi x)
+fn sinc(x: f32) -> f32 {
+    let x = x * std::f32::consts::PI;
+
+    if x.abs() < 1e-5 {
+        1.0
+    } else {
+        x.sin() / x
+    }
+}
+
+// Filtered sum of every sample landing on a pixel
+#[derive(Debug, Clone, Copy)]
+struct FilmPixel {
+    color: Vector3<f32>,
+    weight: f32,
+}
+
+impl FilmPixel {
+    fn new() -> FilmPixel {
+        FilmPixel {
+            color: Vector3::zero(),
+            weight: 0.0,
+        }
+    }
+}
+
+/// The image being rendered. Samples are splatted onto every pixel the filter reaches,
+/// and the final color of a pixel is the weighted average of those samples. The statistics
+/// of the samples taken inside of each pixel are kept as well, so sampling can pick up
+/// where it left off. AOVs are optional, and are averaged over the samples taken inside of
+/// each pixel rather than filtered.
+///
+/// Pixel (0, 0) is the bottom left corner, just like the camera
+pub struct Film {
+    width: u32,
+    height: u32,
+    filter: Filter,
+    pixels: Vec<FilmPixel>,
+    statistics: Vec<PixelStatistics>,
+    aovs: Option<Vec<AovPixel>>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32, filter: Filter) -> Film {
+        Film {
+            width,
+            height,
+            filter,
+            pixels: vec![FilmPixel::new(); (width * height) as usize],
+            statistics: vec![PixelStatistics::new(); (width * height) as usize],
+            aovs: None,
+        }
+    }
+
+    /// Start collecting AOVs for every sample. They take up a lot more memory than the
+    /// image itself, so they're only kept when asked for
+    pub fn enable_aovs(&mut self) {
+        self.aovs = Some(vec![AovPixel::new(); self.pixels.len()]);
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Create an empty tile that can hold every sample taken inside of rows
+    /// `y_start..y_end`, starting from the current statistics of those rows. Tiles can be
+    /// filled in parallel and merged back afterwards
+    pub fn tile(&self, y_start: u32, y_end: u32) -> FilmTile {
+        // Samples can reach rows up to the filter radius away
+        let reach = self.filter.radius().ceil() as u32;
+        let y_min = y_start.saturating_sub(reach);
+        let y_max = (y_end + reach).min(self.height);
+
+        let rows = (y_start * self.width) as usize..(y_end * self.width) as usize;
+
+        FilmTile {
+            width: self.width,
+            y_min,
+            y_max,
+            y_start,
+            filter: self.filter,
+            pixels: vec![FilmPixel::new(); (self.width * (y_max - y_min)) as usize],
+            statistics: self.statistics[rows.clone()].to_vec(),
+            aovs: self.aovs.as_ref().map(|aovs| aovs[rows].to_vec()),
+        }
+    }
+
+    /// Add the samples of a tile to the film. Tiles should always be merged in the
+    /// same order so rounding is the same in every render
+    pub fn merge_tile(&mut self, tile: &FilmTile) {
+        let offset = (tile.y_min * self.width) as usize;
+
+        for (pixel, tile_pixel) in self.pixels[offset..].iter_mut().zip(&tile.pixels) {
+            pixel.color += tile_pixel.color;
+            pixel.weight += tile_pixel.weight;
+        }
+
+        let offset = (tile.y_start * self.width) as usize;
+        self.statistics[offset..offset + tile.statistics.len()].copy_from_slice(&tile.statistics);
+
+        if let (Some(aovs), Some(tile_aovs)) = (self.aovs.as_mut(), tile.aovs.as_ref()) {
+            aovs[offset..offset + tile_aovs.len()].copy_from_slice(tile_aovs);
+        }
+    }
+
+    /// Reconstructed color of a pixel
+    pub fn pixel(&self, x: u32, y: u32) -> Vector3<f32> {
+        let pixel = self.pixels[(y * self.width + x) as usize];
+
+        // Filters with negative lobes can cancel out completely when there are few samples
+        if pixel.weight > 0.0 {
+            pixel.color / pixel.weight
+        } else {
+            Vector3::zero()
+        }
+    }
+
+    /// Write the filtered sums and statistics of every pixel as little endian values, so a
+    /// film of the same size can carry on from them with `read_state`
+    pub fn write_state<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for pixel in &self.pixels {
+            for &value in &[pixel.color.x, pixel.color.y, pixel.color.z, pixel.weight] {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+
+        for statistics in &self.statistics {
+            let (count, mean, m2) = statistics.parts();
+            writer.write_all(&count.to_le_bytes())?;
+            writer.write_all(&mean.to_le_bytes())?;
+            writer.write_all(&m2.to_le_bytes())?;
+        }
+
+        if let Some(ref aovs) = self.aovs {
+            for pixel in aovs {
+                for word in pixel.to_words() {
+                    writer.write_all(&word.to_le_bytes())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replace every pixel with the state written by `write_state`. AOVs have to be
+    /// enabled if they were when the state was written
+    pub fn read_state<R: Read>(&mut self, reader: &mut R) -> io::Result<()> {
+        for pixel in &mut self.pixels {
+            let mut values = [0.0f32; 4];
+            for value in &mut values {
+                let mut bytes = [0; 4];
+                reader.read_exact(&mut bytes)?;
+                *value = f32::from_le_bytes(bytes);
+            }
+
+            pixel.color = Vector3::new(values[0], values[1], values[2]);
+            pixel.weight = values[3];
+        }
+
+        for statistics in &mut self.statistics {
+            let mut count = [0; 4];
+            let mut mean = [0; 8];
+            let mut m2 = [0; 8];
+            reader.read_exact(&mut count)?;
+            reader.read_exact(&mut mean)?;
+            reader.read_exact(&mut m2)?;
+
+            *statistics = PixelStatistics::from_parts(
+                u32::from_le_bytes(count),
+                f64::from_le_bytes(mean),
+                f64::from_le_bytes(m2),
+            );
+        }
+
+        if let Some(ref mut aovs) = self.aovs {
+            let mut words = [0; AovPixel::WORDS];
+
+            for pixel in aovs {
+                for word in &mut words {
+                    let mut bytes = [0; 4];
+                    reader.read_exact(&mut bytes)?;
+                    *word = u32::from_le_bytes(bytes);
+                }
+
+                *pixel = AovPixel::from_words(&words);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Number of samples taken inside of a pixel
+    pub fn sample_count(&self, x: u32, y: u32) -> u32 {
+        self.statistics[(y * self.width + x) as usize].count()
+    }
+
+    /// Number of samples taken inside of all the pixels together
+    pub fn total_sample_count(&self) -> u64 {
+        self.statistics
+            .iter()
+            .map(|statistics| u64::from(statistics.count()))
+            .sum()
+    }
+
+    /// Average number of samples taken inside of each pixel
+    pub fn average_sample_count(&self) -> f32 {
+        self.total_sample_count() as f32 / self.statistics.len() as f32
+    }
+
+    /// Largest number of samples taken inside of any pixel
+    pub fn max_sample_count(&self) -> u32 {
+        self.statistics
+            .iter()
+            .map(|statistics| statistics.count())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Image showing how many samples each pixel took, going from dark blue for
+    /// `min_samples` to red for `max_samples`
+    pub fn sample_heatmap(&self, min_samples: u32, max_samples: u32) -> RgbaImage {
+        // Evenly spaced stops of the color ramp
+        let ramp = [
+            Vector3::new(0.0, 0.0, 0.5),
+            Vector3::new(0.0, 0.5, 1.0),
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(1.0, 1.0, 0.0),
+            Vector3::new(1.0, 0.0, 0.0),
+        ];
+        let range = (max_samples - min_samples).max(1) as f32;
+        let mut image_buffer = ImageBuffer::new(self.width, self.height);
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let samples = self.sample_count(x, y).max(min_samples) - min_samples;
+                let position = (samples as f32 / range).min(1.0) * (ramp.len() - 1) as f32;
+                let stop = (position as usize).min(ramp.len() - 2);
+                let rgb = ramp[stop].lerp(ramp[stop + 1], position - stop as f32) * 255.99;
+
+                let rgb = rgb.cast::<u8>().unwrap();
+                let pixel = Rgba::from_channels(rgb.x, rgb.y, rgb.z, 255);
+                image_buffer.put_pixel(x, self.height - y - 1, pixel);
+            }
+        }
+
+        image_buffer
+    }
+
+    /// 8 bit image previewing an AOV, or None if AOVs aren't enabled
+    pub fn aov_preview(&self, aov: Aov) -> Option<RgbaImage> {
+        let aovs = self.aovs.as_ref()?;
+        let mut image_buffer = ImageBuffer::new(self.width, self.height);
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let rgb = aov::preview_color(aov, &aovs[(y * self.width + x) as usize]);
+                let rgb = (rgb.map(|channel| channel.clamp(0.0, 1.0)) * 255.99)
+                    .cast::<u8>()
+                    .unwrap();
+                let pixel = Rgba::from_channels(rgb.x, rgb.y, rgb.z, 255);
+                image_buffer.put_pixel(x, self.height - y - 1, pixel);
+            }
+        }
+
+        Some(image_buffer)
+    }
+
+    /// Floating point image of the film, with the final colors in the R, G and B channels
+    /// and each AOV in a layer named after it. AOVs are left out if they aren't enabled
+    pub fn to_exr(&self, aovs: &[Aov]) -> ExrImage {
+        let mut image = ExrImage::new(self.width, self.height);
+
+        // Rows are stored from the top, the other way around from the film
+        let indices: Vec<usize> = (0..self.height)
+            .rev()
+            .flat_map(|y| (0..self.width).map(move |x| (y * self.width + x) as usize))
+            .collect();
+
+        for (channel, name) in ["R", "G", "B"].iter().enumerate() {
+            let values = indices
+                .iter()
+                .map(|&index| {
+                    let pixel = self.pixels[index];
+                    if pixel.weight > 0.0 {
+                        pixel.color[channel] / pixel.weight
+                    } else {
+                        0.0
+                    }
+                })
+                .collect();
+
+            image.insert(name, ChannelData::Float(values));
+        }
+
+        if let Some(ref aov_pixels) = self.aovs {
+            for &aov in aovs {
+                for (channel, name) in aov.channels().iter().enumerate() {
+                    let layer = format!("{}.{}", aov.name(), name);
+
+                    let data = if aov.is_id() {
+                        ChannelData::Uint(
+                            indices
+                                .iter()
+                                .map(|&index| aov_pixels[index].id(aov))
+                                .collect(),
+                        )
+                    } else {
+                        ChannelData::Float(
+                            indices
+                                .iter()
+                                .map(|&index| aov_pixels[index].value(aov)[channel])
+                                .collect(),
+                        )
+                    };
+
+                    image.insert(&layer, data);
+                }
+            }
+        }
+
+        image
+    }
+
+    /// Convert the film to an 8 bit image, flipped so the first row is the top of the image
+    pub fn to_image(&self) -> RgbaImage {
+        let mut image_buffer = ImageBuffer::new(self.width, self.height);
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                // Russian roulette can push individual samples above 1.0, and some filters have
+                // negative lobes, so clamp before converting to stop the byte cast from overflowing
+                let mut rgb = self.pixel(x, y).map(|channel| channel.clamp(0.0, 1.0));
+
+                // Convert from colors in range 0.0..1.0 to 0..255
+                rgb *= 255.99;
+                // Cast to bytes for storage in png
+                let rgb = rgb.cast::<u8>().unwrap();
+                let pixel = Rgba::from_channels(rgb.x, rgb.y, rgb.z, 255);
+                // Reverse image beacuse I am indexing from top-down
+                image_buffer.put_pixel(x, self.height - y - 1, pixel);
+            }
+        }
+
+        image_buffer
+    }
+}
+
+/// A horizontal strip of the film that collects samples for a range of rows
+pub struct FilmTile {
+    width: u32,
+    // Rows that samples can be splatted onto
+    y_min: u32,
+    y_max: u32,
+    // First row that samples are taken inside of
+    y_start: u32,
+    filter: Filter,
+    pixels: Vec<FilmPixel>,
+    statistics: Vec<PixelStatistics>,
+    aovs: Option<Vec<AovPixel>>,
+}
+
+impl FilmTile {
+    pub fn has_aovs(&self) -> bool {
+        self.aovs.is_some()
+    }
+
+    /// Add the AOVs of a sample taken inside of a pixel of the tile's rows. Does nothing
+    /// if AOVs aren't enabled
+    pub fn add_aovs(&mut self, x: u32, y: u32, sample: &AovSample) {
+        if let Some(ref mut aovs) = self.aovs {
+            aovs[((y - self.y_start) * self.width + x) as usize].add(sample);
+        }
+    }
+
+    /// Statistics of the samples taken inside of a pixel of the tile's rows
+    pub fn statistics(&mut self, x: u32, y: u32) -> &mut PixelStatistics {
+        &mut self.statistics[((y - self.y_start) * self.width + x) as usize]
+    }
+
+    /// Splat a sample at `film_position`, measured in pixels from the bottom left corner
+    pub fn add_sample(&mut self, film_position: Vector2<f32>, color: Vector3<f32>) {
+        let radius = self.filter.radius();
+
+        // Pixel centers are at half pixel offsets, so find every pixel whose center is
+        // within the radius of the sample
+        let x_start = (film_position.x - 0.5 - radius).ceil().max(0.0) as u32;
+        let x_end = ((film_position.x - 0.5 + radius).floor() + 1.0).max(0.0) as u32;
+        let y_start = (film_position.y - 0.5 - radius).ceil().max(0.0) as u32;
+        let y_end = ((film_position.y - 0.5 + radius).floor() + 1.0).max(0.0) as u32;
+
+        for y in y_start.max(self.y_min)..y_end.min(self.y_max) {
+            for x in x_start..x_end.min(self.width) {
+                let center = Vector2::new(x as f32 + 0.5, y as f32 + 0.5);
+                let weight = self.filter.evaluate(film_position - center);
+
+                if weight != 0.0 {
+                    let pixel = &mut self.pixels[((y - self.y_min) * self.width + x) as usize];
+                    pixel.color += color * weight;
+                    pixel.weight += weight;
+                }
+            }
+        }
+    }
+}
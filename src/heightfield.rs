@@ -0,0 +1,580 @@
+use cgmath::prelude::*;
+use cgmath::{Vector2, Vector3};
+use image;
+use image::hdr::HDRDecoder;
+use png::{self, HasParameters};
+
+use std::f32;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use aabb::Aabb;
+use exr::ExrImage;
+use hit::{HitRecord, Hittable};
+use material::Material;
+use ray::Ray;
+use stats::{self, Counter};
+
+/// A grid of heights, sampled at the corners of the cells of a `Heightfield`
+#[derive(Debug, Clone)]
+pub struct HeightMap {
+    columns: usize,
+    rows: usize,
+    // Stored row by row
+    heights: Vec<f32>,
+}
+
+impl HeightMap {
+    pub fn new(columns: usize, rows: usize, heights: Vec<f32>) -> HeightMap {
+        assert!(columns >= 2 && rows >= 2);
+        assert_eq!(heights.len(), columns * rows);
+
+        HeightMap {
+            columns,
+            rows,
+            heights,
+        }
+    }
+
+    /// Fill a map by evaluating `f` at every sample, with coordinates ranging from 0.0 to
+    /// 1.0 across the map
+    pub fn from_fn<F>(columns: usize, rows: usize, f: F) -> HeightMap
+    where
+        F: Fn(f32, f32) -> f32,
+    {
+        let mut heights = Vec::with_capacity(columns * rows);
+        for row in 0..rows {
+            for column in 0..columns {
+                heights.push(f(
+                    column as f32 / (columns - 1) as f32,
+                    row as f32 / (rows - 1) as f32,
+                ));
+            }
+        }
+
+        HeightMap::new(columns, rows, heights)
+    }
+
+    /// Load a map from an image, one sample per pixel. EXR files use their `Y` or `R`
+    /// channel and Radiance HDR files the average of their colors as they are. Any other
+    /// image is converted to grayscale, going from 0.0 for black to 1.0 for white. 16 bit
+    /// grayscale PNGs keep their full precision, other images are read with 8 bits
+    pub fn load(path: &Path) -> io::Result<HeightMap> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+
+        let (columns, rows, heights) = match extension.as_deref() {
+            Some("exr") => {
+                let image = ExrImage::load(path)?;
+                let channel = image
+                    .channel("Y")
+                    .or_else(|| image.channel("R"))
+                    .ok_or_else(|| invalid_data("height map has no Y or R channel"))?;
+
+                let heights = (0..(image.width * image.height) as usize)
+                    .map(|index| channel.get(index))
+                    .collect();
+                (image.width as usize, image.height as usize, heights)
+            }
+            Some("hdr") => {
+                let file = File::open(path)?;
+                let decoder = HDRDecoder::new(BufReader::new(file)).map_err(image_error)?;
+                let metadata = decoder.metadata();
+
+                let heights = decoder
+                    .read_image_hdr()
+                    .map_err(image_error)?
+                    .into_iter()
+                    .map(|pixel| (pixel.data[0] + pixel.data[1] + pixel.data[2]) / 3.0)
+                    .collect();
+                (metadata.width as usize, metadata.height as usize, heights)
+            }
+            Some("png") => match load_gray_png(path)? {
+                Some(map) => map,
+                None => load_luma(path)?,
+            },
+            _ => load_luma(path)?,
+        };
+
+        if columns < 2 || rows < 2 {
+            return Err(invalid_data("height map needs at least 2 by 2 pixels"));
+        }
+        if !heights.iter().all(|height| height.is_finite()) {
+            return Err(invalid_data(
+                "height map contains a height that is not finite",
+            ));
+        }
+
+        Ok(HeightMap::new(columns, rows, heights))
+    }
+
+    fn get(&self, column: usize, row: usize) -> f32 {
+        self.heights[row * self.columns + column]
+    }
+}
+
+// Columns, rows and heights of a 16 bit grayscale PNG, read directly since the image
+// crate cuts samples down to 8 bits. None for any other kind of PNG
+fn load_gray_png(path: &Path) -> io::Result<Option<(usize, usize, Vec<f32>)>> {
+    let mut decoder = png::Decoder::new(File::open(path)?);
+    decoder.set(png::Transformations::IDENTITY);
+    let (info, mut reader) = decoder.read_info()?;
+
+    let samples = match (info.color_type, info.bit_depth) {
+        (png::ColorType::Grayscale, png::BitDepth::Sixteen) => 1,
+        (png::ColorType::GrayscaleAlpha, png::BitDepth::Sixteen) => 2,
+        _ => return Ok(None),
+    };
+
+    let mut data = vec![0; info.buffer_size()];
+    reader.next_frame(&mut data)?;
+
+    // Samples are big endian, and the height is the first one of each pixel
+    let heights = data
+        .chunks(2 * samples)
+        .map(|pixel| f32::from(u16::from_be_bytes([pixel[0], pixel[1]])) / 65535.0)
+        .collect();
+
+    Ok(Some((info.width as usize, info.height as usize, heights)))
+}
+
+// Columns, rows and heights of any image the image crate can open, converted to 8 bit
+// grayscale
+fn load_luma(path: &Path) -> io::Result<(usize, usize, Vec<f32>)> {
+    let image = image::open(path).map_err(image_error)?.to_luma();
+
+    let heights = image
+        .pixels()
+        .map(|pixel| f32::from(pixel.data[0]) / 255.0)
+        .collect();
+    Ok((image.width() as usize, image.height() as usize, heights))
+}
+
+fn image_error(error: image::ImageError) -> io::Error {
+    match error {
+        image::ImageError::IoError(error) => error,
+        error => invalid_data(&error.to_string()),
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Terrain made from a height map stretched over a rectangle. The first sample of the map
+/// sits at `origin` and the last one `size.x` along the x-axis and `size.z` along the
+/// z-axis from it, raised by `size.y` times its height.
+///
+/// Each cell between four samples is split into two triangles, but rays walk through the
+/// grid of cells instead of going through a hierarchy of triangles, so even huge maps
+/// take no more memory than their heights and normals
+pub struct Heightfield {
+    map: HeightMap,
+    origin: Vector3<f32>,
+    size: Vector3<f32>,
+    // One per sample, used to smoothly shade over the triangles
+    normals: Vec<Vector3<f32>>,
+    bounds: Aabb,
+    material: Material,
+}
+
+impl Heightfield {
+    pub fn new(
+        map: HeightMap,
+        origin: Vector3<f32>,
+        size: Vector3<f32>,
+        material: Material,
+    ) -> Heightfield {
+        let (lowest, highest) = map
+            .heights
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(lowest, highest), &height| {
+                (lowest.min(height), highest.max(height))
+            });
+        let bounds = Aabb::new(
+            Vector3::new(origin.x, origin.y + lowest * size.y, origin.z),
+            Vector3::new(
+                origin.x + size.x,
+                origin.y + highest * size.y,
+                origin.z + size.z,
+            ),
+        );
+
+        let mut heightfield = Heightfield {
+            map,
+            origin,
+            size,
+            normals: Vec::new(),
+            bounds,
+            material,
+        };
+        heightfield.normals = heightfield.sample_normals();
+
+        heightfield
+    }
+
+    fn cell_size(&self) -> Vector2<f32> {
+        Vector2::new(
+            self.size.x / (self.map.columns - 1) as f32,
+            self.size.z / (self.map.rows - 1) as f32,
+        )
+    }
+
+    fn position(&self, column: usize, row: usize) -> Vector3<f32> {
+        let cell = self.cell_size();
+
+        self.origin
+            + Vector3::new(
+                column as f32 * cell.x,
+                self.map.get(column, row) * self.size.y,
+                row as f32 * cell.y,
+            )
+    }
+
+    // Normal at each sample from the slope between its neighbours, or between it and its
+    // only neighbour along the edges
+    fn sample_normals(&self) -> Vec<Vector3<f32>> {
+        let (columns, rows) = (self.map.columns, self.map.rows);
+        let mut normals = Vec::with_capacity(columns * rows);
+
+        for row in 0..rows {
+            for column in 0..columns {
+                let (left, right) = (column.saturating_sub(1), (column + 1).min(columns - 1));
+                let (back, front) = (row.saturating_sub(1), (row + 1).min(rows - 1));
+
+                let along_x = self.position(right, row) - self.position(left, row);
+                let along_z = self.position(column, front) - self.position(column, back);
+                normals.push(along_z.cross(along_x).normalize());
+            }
+        }
+
+        normals
+    }
+
+    // Closest hit on the two triangles of a cell, with the normal interpolated over them
+    fn hit_cell(
+        &self,
+        column: usize,
+        row: usize,
+        ray: Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<(f32, Vector3<f32>)> {
+        let corners = [
+            (column, row),
+            (column + 1, row),
+            (column + 1, row + 1),
+            (column, row + 1),
+        ];
+        let positions = corners.map(|(column, row)| self.position(column, row));
+        let normals = corners.map(|(column, row)| self.normals[row * self.map.columns + column]);
+
+        let mut closest = None;
+        let mut t_max = t_max;
+
+        for &[a, b, c] in &[[0, 1, 2], [0, 2, 3]] {
+            if let Some((t, u, v)) =
+                intersect_triangle(positions[a], positions[b], positions[c], ray, t_min, t_max)
+            {
+                let normal = normals[a] * (1.0 - u - v) + normals[b] * u + normals[c] * v;
+                closest = Some((t, normal.normalize()));
+                t_max = t;
+            }
+        }
+
+        closest
+    }
+}
+
+impl Hittable for Heightfield {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (t_enter, t_exit) = self.bounds.intersect(ray, t_min, t_max)?;
+
+        let cell = self.cell_size();
+        let last = [self.map.columns - 2, self.map.rows - 2];
+        let origin = ray.origin() - self.origin;
+        let direction = ray.direction();
+
+        // Walk through the cells the ray passes over, in order, starting where it enters
+        // the bounds. The first cell with a hit has the closest one
+        let entry = origin + direction * t_enter;
+        let mut current = [
+            ((entry.x / cell.x).max(0.0) as usize).min(last[0]),
+            ((entry.z / cell.y).max(0.0) as usize).min(last[1]),
+        ];
+
+        let mut step = [0isize; 2];
+        let mut t_next = [f32::MAX; 2];
+        let mut t_delta = [f32::MAX; 2];
+        for (axis, &(position, direction, size)) in [
+            (origin.x, direction.x, cell.x),
+            (origin.z, direction.z, cell.y),
+        ]
+        .iter()
+        .enumerate()
+        {
+            if direction > 0.0 {
+                step[axis] = 1;
+                t_next[axis] = ((current[axis] + 1) as f32 * size - position) / direction;
+                t_delta[axis] = size / direction;
+            } else if direction < 0.0 {
+                step[axis] = -1;
+                t_next[axis] = (current[axis] as f32 * size - position) / direction;
+                t_delta[axis] = -size / direction;
+            }
+        }
+
+        let mut t_cell = t_enter;
+        loop {
+            // Skip cells the ray passes entirely above or below
+            let t_leave = t_next[0].min(t_next[1]).min(t_exit);
+            let (y0, y1) = (
+                origin.y + direction.y * t_cell,
+                origin.y + direction.y * t_leave,
+            );
+            let heights = [
+                self.map.get(current[0], current[1]),
+                self.map.get(current[0] + 1, current[1]),
+                self.map.get(current[0], current[1] + 1),
+                self.map.get(current[0] + 1, current[1] + 1),
+            ];
+            let lowest = heights.iter().fold(f32::MAX, |a, &b| a.min(b)) * self.size.y;
+            let highest = heights.iter().fold(f32::MIN, |a, &b| a.max(b)) * self.size.y;
+
+            if y0.min(y1) <= highest && y0.max(y1) >= lowest {
+                if let Some((t, normal)) = self.hit_cell(current[0], current[1], ray, t_min, t_max)
+                {
+                    let position = ray.point_at_distance(t);
+                    let local = position - self.origin;
+
+                    return Some(HitRecord {
+                        t,
+                        position,
+                        normal,
+                        uv: Vector2::new(local.x / self.size.x, local.z / self.size.z),
+                        material: self.material,
+                        object_id: 0,
+                        medium: None,
+                    });
+                }
+            }
+
+            // On to the neighbouring cell the ray crosses into first
+            let axis = if t_next[0] < t_next[1] { 0 } else { 1 };
+            if t_next[axis] > t_exit {
+                return None;
+            }
+            if (step[axis] < 0 && current[axis] == 0)
+                || (step[axis] > 0 && current[axis] == last[axis])
+            {
+                return None;
+            }
+
+            current[axis] = (current[axis] as isize + step[axis]) as usize;
+            t_cell = t_next[axis];
+            t_next[axis] += t_delta[axis];
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bounds
+    }
+}
+
+// Distance along the ray and barycentric coordinates of b and c where it hits the
+// triangle abc, if it does so between t_min and t_max
+fn intersect_triangle(
+    a: Vector3<f32>,
+    b: Vector3<f32>,
+    c: Vector3<f32>,
+    ray: Ray,
+    t_min: f32,
+    t_max: f32,
+) -> Option<(f32, f32, f32)> {
+    stats::count(Counter::IntersectionTests);
+
+    let edge1 = b - a;
+    let edge2 = c - a;
+
+    let p = ray.direction().cross(edge2);
+    let determinant = edge1.dot(p);
+    if determinant.abs() < 1e-12 {
+        return None;
+    }
+
+    let inverse_determinant = 1.0 / determinant;
+    let to_origin = ray.origin() - a;
+
+    let u = to_origin.dot(p) * inverse_determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = to_origin.cross(edge1);
+    let v = ray.direction().dot(q) * inverse_determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(q) * inverse_determinant;
+    if t > t_min && t < t_max {
+        Some((t, u, v))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+
+    use exr::ChannelData;
+
+    fn material() -> Material {
+        Material::new_lambertian(0.5, 0.5, 0.5)
+    }
+
+    #[test]
+    fn flat_map_is_a_plane() {
+        let map = HeightMap::from_fn(8, 8, |_, _| 0.5);
+        let field = Heightfield::new(
+            map,
+            Vector3::new(-2.0, 0.0, -2.0),
+            Vector3::new(4.0, 2.0, 4.0),
+            material(),
+        );
+
+        let ray = Ray::new(Vector3::new(1.0, 5.0, -1.0), Vector3::new(0.0, -1.0, 0.0));
+        let record = field.hit(ray, 0.001, f32::MAX).unwrap();
+        assert!((record.t - 4.0).abs() < 1e-4);
+        assert!((record.normal - Vector3::unit_y()).magnitude() < 1e-4);
+        assert!((record.uv - Vector2::new(0.75, 0.25)).magnitude() < 1e-4);
+
+        // Grazing rays walk through many cells before they come down
+        let ray = Ray::new(
+            Vector3::new(-3.0, 1.4, 0.3),
+            Vector3::new(1.0, -0.1, 0.05).normalize(),
+        );
+        let record = field.hit(ray, 0.001, f32::MAX).unwrap();
+        assert!((record.position.y - 1.0).abs() < 1e-4);
+        assert!((record.position.x - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn ray_passing_over_misses() {
+        let map = HeightMap::from_fn(8, 8, |u, v| u * v);
+        let field = Heightfield::new(
+            map,
+            Vector3::zero(),
+            Vector3::new(1.0, 1.0, 1.0),
+            material(),
+        );
+
+        let ray = Ray::new(Vector3::new(-1.0, 1.5, 0.5), Vector3::new(1.0, 0.0, 0.0));
+        assert!(field.hit(ray, 0.001, f32::MAX).is_none());
+    }
+
+    #[test]
+    fn slope_is_hit_where_it_rises_to_the_ray() {
+        // Rising by one over the whole x-axis
+        let map = HeightMap::from_fn(17, 5, |u, _| u);
+        let field = Heightfield::new(
+            map,
+            Vector3::zero(),
+            Vector3::new(1.0, 1.0, 1.0),
+            material(),
+        );
+
+        let ray = Ray::new(Vector3::new(-1.0, 0.25, 0.5), Vector3::new(1.0, 0.0, 0.0));
+        let record = field.hit(ray, 0.001, f32::MAX).unwrap();
+        assert!((record.position.x - 0.25).abs() < 1e-4);
+        assert!((record.normal - Vector3::new(-1.0, 1.0, 0.0).normalize()).magnitude() < 1e-4);
+    }
+
+    #[test]
+    fn rays_walking_backwards_hit_the_slope() {
+        // Falling by one over the whole x-axis and the whole z-axis
+        let along_x = Heightfield::new(
+            HeightMap::from_fn(17, 5, |u, _| 1.0 - u),
+            Vector3::zero(),
+            Vector3::new(1.0, 1.0, 1.0),
+            material(),
+        );
+        let along_z = Heightfield::new(
+            HeightMap::from_fn(5, 17, |_, v| 1.0 - v),
+            Vector3::zero(),
+            Vector3::new(1.0, 1.0, 1.0),
+            material(),
+        );
+
+        let ray = Ray::new(Vector3::new(2.0, 0.25, 0.5), Vector3::new(-1.0, 0.0, 0.0));
+        let record = along_x.hit(ray, 0.001, f32::MAX).unwrap();
+        assert!((record.position.x - 0.75).abs() < 1e-4);
+
+        let ray = Ray::new(Vector3::new(0.5, 0.25, 2.0), Vector3::new(0.0, 0.0, -1.0));
+        let record = along_z.hit(ray, 0.001, f32::MAX).unwrap();
+        assert!((record.position.z - 0.75).abs() < 1e-4);
+
+        // Crossing cells along both axes
+        let ray = Ray::new(
+            Vector3::new(2.0, 0.25, 1.5),
+            Vector3::new(-1.0, 0.0, -0.5).normalize(),
+        );
+        let record = along_x.hit(ray, 0.001, f32::MAX).unwrap();
+        assert!((record.position.x - 0.75).abs() < 1e-4);
+        assert!((record.position.z - 0.875).abs() < 1e-4);
+    }
+
+    #[test]
+    fn sixteen_bit_png_keeps_its_precision() {
+        let path = env::temp_dir().join("ray-tracer-height-map-16-bit.png");
+        let samples: [u16; 4] = [0, 1, 32768, 65535];
+        let data: Vec<u8> = samples
+            .iter()
+            .flat_map(|sample| sample.to_be_bytes())
+            .collect();
+
+        let mut encoder = png::Encoder::new(File::create(&path).unwrap(), 2, 2);
+        encoder
+            .set(png::ColorType::Grayscale)
+            .set(png::BitDepth::Sixteen);
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(&data)
+            .unwrap();
+
+        let map = HeightMap::load(&path);
+        fs::remove_file(path).unwrap();
+        let map = map.unwrap();
+
+        for (index, &sample) in samples.iter().enumerate() {
+            assert_eq!(map.get(index % 2, index / 2), f32::from(sample) / 65535.0);
+        }
+    }
+
+    #[test]
+    fn non_finite_heights_are_rejected() {
+        let path = env::temp_dir().join("ray-tracer-height-map-nan.exr");
+        let mut image = ExrImage::new(2, 2);
+        image.insert("Y", ChannelData::Float(vec![0.0, f32::NAN, 0.0, 0.0]));
+        image.save(&path).unwrap();
+
+        let error = HeightMap::load(&path);
+        fs::remove_file(path).unwrap();
+        assert_eq!(error.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn missing_file_is_an_io_error() {
+        let path = env::temp_dir().join("ray-tracer-height-map-missing.png");
+        let error = HeightMap::load(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -1,22 +1,62 @@
-use cgmath::Vector3;
+use cgmath::{Vector2, Vector3};
 
+use std::sync::Arc;
+
+use aabb::Aabb;
 use material::Material;
+use medium::Medium;
 use ray::Ray;
 
 /// Interface of all objects that a ray can interact with
 pub trait Hittable {
-    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>>;
+
+    /// Box containing the whole object, used to build acceleration structures
+    fn bounding_box(&self) -> Aabb;
+}
+
+// Lets an object be part of the world and also be sampled as a light
+impl<T: Hittable + ?Sized> Hittable for Arc<T> {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        (**self).hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        (**self).bounding_box()
+    }
+}
+
+/// A closed object with an inside. Only solids can be combined with constructive solid
+/// geometry
+pub trait Solid: Hittable {
+    /// Every stretch of the ray's line inside the solid in order along the ray, including
+    /// the ones behind the origin of the ray. Normals point out of the solid at both ends
+    fn spans(&self, ray: Ray) -> Vec<Span<'_>>;
+}
+
+/// Stretch of a ray inside a solid, between where it enters and where it leaves
+#[derive(Debug, Clone, Copy)]
+pub struct Span<'a> {
+    pub enter: HitRecord<'a>,
+    pub exit: HitRecord<'a>,
 }
 
 /// Struct containg all the data necessary to model a ray-object collision
 #[derive(Debug, Clone, Copy)]
-pub struct HitRecord {
+pub struct HitRecord<'a> {
     // Distance along ray that it hit
     pub t: f32,
     // Position in the world that ray intersected
     pub position: Vector3<f32>,
     // Surface normal at the point where the ray hit
     pub normal: Vector3<f32>,
+    // Surface parameterization in the range 0.0 to 1.0 at the point where the ray hit
+    pub uv: Vector2<f32>,
     // The material of the surface that the ray last hit
     pub material: Material,
+    // Index of the object in the scene, starting at 1. Set by the list holding the object
+    pub object_id: u32,
+    // Set when the surface is the boundary of a participating medium. Boundaries are
+    // invisible and only change which medium the ray is traveling through
+    pub medium: Option<&'a dyn Medium>,
 }
@@ -1,11 +1,12 @@
-use cgmath::prelude::*;
-
+use aabb::Aabb;
+use bvh::Bvh;
 use hit::{HitRecord, Hittable};
 use ray::Ray;
+use stats::{self, Counter};
 
 // A collection of Hittable objects
 pub struct HittableList {
-    hittable: Vec<Box<Hittable + Sync>>,
+    hittable: Vec<Box<dyn Hittable + Sync>>,
 }
 
 impl HittableList {
@@ -15,29 +16,46 @@ impl HittableList {
         }
     }
 
-    pub fn insert(&mut self, obj: Box<Hittable + Sync>) {
+    pub fn insert(&mut self, obj: Box<dyn Hittable + Sync>) {
         self.hittable.push(obj);
     }
+
+    /// Put the objects in a bounding volume hierarchy. Rays then only test the objects
+    /// along their way, instead of every object in the list
+    pub fn into_bvh(self) -> HittableBvh {
+        let bounds: Vec<Aabb> = self
+            .hittable
+            .iter()
+            .map(|hittable| hittable.bounding_box())
+            .collect();
+
+        HittableBvh {
+            bvh: Bvh::build(&bounds),
+            hittable: self.hittable,
+        }
+    }
 }
 
 // Returns the closest object in the colleciton to the camera
 // since all others would be occluded
 impl Hittable for HittableList {
-    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        // The list is the only node there is to go through
+        stats::count(Counter::NodesVisited);
+
         let mut current_closest_hit = None;
 
         // Iterate through the list of objects and check if they were it
-        for hittable in &self.hittable {
-            if let Some(record) = hittable.hit(ray, t_min, t_max) {
+        for (index, hittable) in self.hittable.iter().enumerate() {
+            if let Some(mut record) = hittable.hit(ray, t_min, t_max) {
+                record.object_id = index as u32 + 1;
+
                 // Calculate which one was closest to us
                 match current_closest_hit {
                     None => current_closest_hit = Some(record),
                     Some(closest) => {
-                        // Calculate distances from origin to determine which is closer
-                        let closest_distance = closest.position.distance2(ray.origin());
-                        let current_distance = record.position.distance2(ray.origin());
-
-                        if current_distance < closest_distance {
+                        // Compare distances along the ray to determine which is closer
+                        if record.t < closest.t {
                             current_closest_hit = Some(record);
                         }
                     }
@@ -47,4 +65,39 @@ impl Hittable for HittableList {
 
         current_closest_hit
     }
+
+    fn bounding_box(&self) -> Aabb {
+        self.hittable
+            .iter()
+            .fold(Aabb::empty(), |bounds, hittable| {
+                bounds.union(&hittable.bounding_box())
+            })
+    }
+}
+
+/// A collection of Hittable objects in a bounding volume hierarchy. Objects keep the ids
+/// they had in the list it was made from
+pub struct HittableBvh {
+    hittable: Vec<Box<dyn Hittable + Sync>>,
+    bvh: Bvh,
+}
+
+impl Hittable for HittableBvh {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let mut closest = None;
+
+        self.bvh.closest_hit(ray, t_min, t_max, |index, t_max| {
+            let mut record = self.hittable[index].hit(ray, t_min, t_max)?;
+            record.object_id = index as u32 + 1;
+            closest = Some(record);
+
+            Some(record.t)
+        });
+
+        closest
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bvh.bounds()
+    }
 }
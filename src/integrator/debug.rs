@@ -0,0 +1,427 @@
+use cgmath::prelude::*;
+use cgmath::Vector3;
+
+use std::f32;
+
+use hit::HitRecord;
+use integrator::{entered_medium, trace, trace_shadow, Integrator};
+use material::random_cosine_direction;
+use medium::Medium;
+use onb::Onb;
+use ray::Ray;
+use sampler::Sampler;
+use scene::Scene;
+
+// Medium boundaries a shadow ray may pass through before giving up
+const MAX_CROSSINGS: u32 = 64;
+
+// Color of rays that miss everything in the debug views
+const BACKGROUND: Vector3<f32> = Vector3 {
+    x: 0.0,
+    y: 0.0,
+    z: 0.0,
+};
+
+/// Shows the surface normal, remapped from -1.0..1.0 to 0.0..1.0
+pub struct NormalIntegrator;
+
+impl Integrator for NormalIntegrator {
+    fn radiance(&self, ray: Ray, scene: &Scene, _sampler: &mut dyn Sampler) -> Vector3<f32> {
+        match trace(scene, ray) {
+            Some(record) => 0.5 * (record.normal + Vector3::new(1.0, 1.0, 1.0)),
+            None => BACKGROUND,
+        }
+    }
+}
+
+/// Shows the distance to the first hit, white close to the camera fading to black
+pub struct DepthIntegrator;
+
+impl Integrator for DepthIntegrator {
+    fn radiance(&self, ray: Ray, scene: &Scene, _sampler: &mut dyn Sampler) -> Vector3<f32> {
+        match trace(scene, ray) {
+            Some(record) => {
+                // Measure in world units rather than multiples of the ray direction
+                let distance = record.t * ray.direction().magnitude();
+                let brightness = 1.0 / (1.0 + distance);
+
+                Vector3::new(brightness, brightness, brightness)
+            }
+            None => BACKGROUND,
+        }
+    }
+}
+
+/// Shows the surface parameterization with u in red and v in green
+pub struct UvIntegrator;
+
+impl Integrator for UvIntegrator {
+    fn radiance(&self, ray: Ray, scene: &Scene, _sampler: &mut dyn Sampler) -> Vector3<f32> {
+        match trace(scene, ray) {
+            Some(record) => Vector3::new(record.uv.x, record.uv.y, 0.0),
+            None => BACKGROUND,
+        }
+    }
+}
+
+/// Gives every distinct material a random flat color
+pub struct MaterialIdIntegrator;
+
+impl Integrator for MaterialIdIntegrator {
+    fn radiance(&self, ray: Ray, scene: &Scene, _sampler: &mut dyn Sampler) -> Vector3<f32> {
+        match trace(scene, ray) {
+            Some(record) => {
+                let id = record.material.id();
+
+                // Use the low bytes of the id as color channels
+                Vector3::new(
+                    (id & 0xFF) as f32 / 255.0,
+                    ((id >> 8) & 0xFF) as f32 / 255.0,
+                    ((id >> 16) & 0xFF) as f32 / 255.0,
+                )
+            }
+            None => BACKGROUND,
+        }
+    }
+}
+
+/// Shows the barycentric coordinates (1 - u - v, u, v) of the hit. Primitives that
+/// aren't triangles use their surface parameterization instead
+pub struct BarycentricIntegrator;
+
+impl Integrator for BarycentricIntegrator {
+    fn radiance(&self, ray: Ray, scene: &Scene, _sampler: &mut dyn Sampler) -> Vector3<f32> {
+        match trace(scene, ray) {
+            Some(record) => Vector3::new(1.0 - record.uv.x - record.uv.y, record.uv.x, record.uv.y),
+            None => BACKGROUND,
+        }
+    }
+}
+
+/// Ambient occlusion. Each sample casts one cosine weighted ray from the first hit and
+/// returns white if nothing is hit within `distance`
+pub struct AmbientOcclusionIntegrator {
+    distance: f32,
+}
+
+impl AmbientOcclusionIntegrator {
+    pub fn new(distance: f32) -> AmbientOcclusionIntegrator {
+        AmbientOcclusionIntegrator { distance }
+    }
+}
+
+impl Integrator for AmbientOcclusionIntegrator {
+    fn radiance(&self, ray: Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Vector3<f32> {
+        let record = match trace(scene, ray) {
+            Some(record) => record,
+            None => return Vector3::new(1.0, 1.0, 1.0),
+        };
+
+        // Occlusion is measured on the side of the surface the camera sees
+        let normal = if ray.direction().dot(record.normal) > 0.0 {
+            -record.normal
+        } else {
+            record.normal
+        };
+
+        sampler.start_bounce(0);
+        let direction = Onb::from_w(normal).to_world(random_cosine_direction(sampler));
+        let occlusion_ray = Ray::new(record.position, direction);
+
+        match trace_shadow(scene, occlusion_ray, self.distance) {
+            Some(_) => Vector3::zero(),
+            None => Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+/// Only includes light that reaches a diffuse or glossy surface straight from a light or
+/// the background. Mirror reflections and refractions are followed for up to max_depth
+/// bounces until such a surface is found. There, the lights of the scene are sampled
+/// directly as well as through the material, and the two are combined with multiple
+/// importance sampling
+pub struct DirectLightingIntegrator {
+    max_depth: u32,
+}
+
+impl DirectLightingIntegrator {
+    pub fn new(max_depth: u32) -> DirectLightingIntegrator {
+        DirectLightingIntegrator { max_depth }
+    }
+}
+
+impl Integrator for DirectLightingIntegrator {
+    fn radiance(&self, mut ray: Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Vector3<f32> {
+        let mut radiance = Vector3::zero();
+        let mut throughput = Vector3::new(1.0, 1.0, 1.0);
+        let mut medium: Option<&dyn Medium> = None;
+
+        for bounce in 0..self.max_depth {
+            sampler.start_bounce(bounce);
+            let hit = trace(scene, ray);
+
+            // Scattering inside a medium counts as a diffuse bounce
+            if let Some(current) = medium {
+                let sample = current.sample(ray, hit.map_or(f32::MAX, |record| record.t), sampler);
+                radiance += throughput.mul_element_wise(sample.emission);
+                throughput.mul_assign_element_wise(sample.weight);
+
+                if let Some(t) = sample.scatter_t {
+                    let direction = current.phase().sample(ray.direction(), sampler);
+                    let scattered_ray = Ray::new(ray.point_at_distance(t), direction);
+
+                    return radiance
+                        + throughput.mul_element_wise(unblocked_light(
+                            scattered_ray,
+                            scene,
+                            medium,
+                            sampler,
+                        ));
+                }
+            }
+
+            let record = match hit {
+                Some(record) => record,
+                None => {
+                    return radiance + throughput.mul_element_wise(scene.background.radiance(ray))
+                }
+            };
+
+            if let Some(boundary) = record.medium {
+                medium = entered_medium(ray, record.normal, boundary);
+                ray = Ray::new(record.position, ray.direction());
+                continue;
+            }
+
+            radiance += throughput.mul_element_wise(record.material.emitted(ray, record));
+
+            // Light bouncing off a diffuse or glossy surface only counts if it came
+            // straight from a light or the background
+            if !record.material.is_delta() {
+                return radiance
+                    + throughput
+                        .mul_element_wise(direct_light(ray, record, scene, medium, sampler));
+            }
+
+            let scattered_ray = match record.material.scatter(ray, record, sampler) {
+                Some(scattered_ray) => scattered_ray,
+                None => break,
+            };
+
+            throughput.mul_assign_element_wise(scattered_ray.attenuation);
+            ray = scattered_ray.ray;
+        }
+
+        radiance
+    }
+}
+
+// Light reflected towards the ray by a diffuse or glossy surface, arriving straight from a
+// light or the background. The material and the lights are both sampled once and weighted with the
+// power heuristic, so each strategy counts the most where it's the better of the two
+fn direct_light<'a>(
+    ray: Ray,
+    record: HitRecord<'a>,
+    scene: &'a Scene,
+    medium: Option<&'a dyn Medium>,
+    sampler: &mut dyn Sampler,
+) -> Vector3<f32> {
+    let mut light = Vector3::zero();
+
+    if let Some(scattered_ray) = record.material.scatter(ray, record, sampler) {
+        let direction = scattered_ray.ray.direction();
+        let material_pdf = record.material.scattering_pdf(ray, record, direction);
+        let weight = power_heuristic(material_pdf, lights_pdf(scene, record.position, direction));
+
+        let arriving = unblocked_light(scattered_ray.ray, scene, medium, sampler);
+        light += scattered_ray.attenuation.mul_element_wise(arriving) * weight;
+    }
+
+    if scene.lights.is_empty() {
+        return light;
+    }
+
+    let count = scene.lights.len();
+    let index = ((sampler.next_f32() * count as f32) as usize).min(count - 1);
+    let direction = scene.lights[index].sample_direction(record.position, sampler);
+
+    let light_pdf = lights_pdf(scene, record.position, direction);
+    let cosine = direction.normalize().dot(record.normal).abs();
+    let reflected = record.material.eval(ray, record, direction) * cosine;
+
+    if light_pdf > 0.0 && reflected != Vector3::zero() {
+        let material_pdf = record.material.scattering_pdf(ray, record, direction);
+        let weight = power_heuristic(light_pdf, material_pdf);
+
+        let shadow_ray = Ray::new(record.position, direction);
+        let arriving = unblocked_light(shadow_ray, scene, medium, sampler);
+        light += reflected.mul_element_wise(arriving) * (weight / light_pdf);
+    }
+
+    light
+}
+
+// Density of picking `direction` from `origin` by choosing one of the lights at random
+// and sampling it
+fn lights_pdf(scene: &Scene, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+    if scene.lights.is_empty() {
+        return 0.0;
+    }
+
+    let total: f32 = scene
+        .lights
+        .iter()
+        .map(|light| light.pdf(origin, direction))
+        .sum();
+
+    total / scene.lights.len() as f32
+}
+
+// Weight of a sample taken with density `pdf` when another strategy could have taken it
+// with density `other`, from "Optimally Combining Sampling Techniques for Monte Carlo
+// Rendering" by Veach and Guibas
+fn power_heuristic(pdf: f32, other: f32) -> f32 {
+    // Written as a ratio so infinite densities don't turn into NaN
+    let ratio = other / pdf;
+    1.0 / (1.0 + ratio * ratio)
+}
+
+// Light arriving along the ray from the background or the first surface it hits,
+// attenuated by any media it passes through. Only surfaces that emit light contribute
+fn unblocked_light<'a>(
+    mut ray: Ray,
+    scene: &'a Scene,
+    mut medium: Option<&'a dyn Medium>,
+    sampler: &mut dyn Sampler,
+) -> Vector3<f32> {
+    let mut transmittance = Vector3::new(1.0, 1.0, 1.0);
+
+    for _ in 0..MAX_CROSSINGS {
+        let hit = trace_shadow(scene, ray, f32::MAX);
+
+        if let Some(current) = medium {
+            let t_max = hit.map_or(f32::MAX, |record| record.t);
+            transmittance.mul_assign_element_wise(current.transmittance(ray, t_max, sampler));
+        }
+
+        match hit {
+            None => return transmittance.mul_element_wise(scene.background.radiance(ray)),
+            Some(record) => match record.medium {
+                Some(boundary) => {
+                    medium = entered_medium(ray, record.normal, boundary);
+                    ray = Ray::new(record.position, ray.direction());
+                }
+                None => {
+                    return transmittance.mul_element_wise(record.material.emitted(ray, record))
+                }
+            },
+        }
+    }
+
+    Vector3::zero()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use camera::Camera;
+    use sampler;
+    use scene;
+
+    const RESOLUTION_X: u32 = 24;
+    const RESOLUTION_Y: u32 = 16;
+    const SAMPLES: u32 = 256;
+
+    // Running mean and variance of a series of samples
+    #[derive(Default)]
+    struct Moments {
+        count: f64,
+        sum: f64,
+        sum_squared: f64,
+    }
+
+    impl Moments {
+        fn add(&mut self, value: f64) {
+            self.count += 1.0;
+            self.sum += value;
+            self.sum_squared += value * value;
+        }
+
+        fn mean(&self) -> f64 {
+            self.sum / self.count
+        }
+
+        fn variance(&self) -> f64 {
+            (self.sum_squared / self.count - self.mean() * self.mean()).max(0.0)
+        }
+
+        // Standard deviation of the mean
+        fn error(&self) -> f64 {
+            (self.variance() / self.count).sqrt()
+        }
+    }
+
+    #[test]
+    fn light_sampling_matches_material_sampling() {
+        // Without any lights to sample every path relies on the material, which is
+        // unbiased but noisy, so it's the reference for combining both
+        let combined = scene::veach_mis();
+        let mut reference = scene::veach_mis();
+        reference.lights.clear();
+
+        let integrator = DirectLightingIntegrator::new(10);
+        let camera = Camera::new(RESOLUTION_X, RESOLUTION_Y, combined.camera);
+        let mut sampler = sampler::create_sampler("random", 1, SAMPLES).unwrap();
+
+        // Rays go through the middle of each pixel, so all of the noise comes from the way
+        // light is found
+        let mut brightness = |scene: &Scene, x: u32, y: u32, index: u32| {
+            let ray = camera.get_ray_at_coords(
+                (x as f32 + 0.5) / RESOLUTION_X as f32,
+                (y as f32 + 0.5) / RESOLUTION_Y as f32,
+            );
+
+            sampler.start_sample(x, y, index);
+            let radiance = integrator.radiance(ray, scene, &mut *sampler);
+
+            f64::from(radiance.x + radiance.y + radiance.z) / 3.0
+        };
+
+        let mut difference = Moments::default();
+        let mut light_noise = 0.0;
+        let mut material_noise = 0.0;
+        for y in 0..RESOLUTION_Y {
+            for x in 0..RESOLUTION_X {
+                let mut light = Moments::default();
+                let mut material = Moments::default();
+
+                for index in 0..SAMPLES {
+                    let with_lights = brightness(&combined, x, y, index);
+                    let without_lights = brightness(&reference, x, y, index);
+
+                    light.add(with_lights);
+                    material.add(without_lights);
+                    difference.add(with_lights - without_lights);
+                }
+
+                light_noise += light.variance();
+                material_noise += material.variance();
+            }
+        }
+
+        assert!(
+            difference.mean().abs() < 4.0 * difference.error(),
+            "Light sampling differs from material sampling by {} on average, with a \
+             standard error of {}",
+            difference.mean(),
+            difference.error()
+        );
+        assert!(
+            light_noise < 0.1 * material_noise,
+            "Sampling the lights as well only lowers the variance from {} to {}",
+            material_noise,
+            light_noise
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use cgmath::prelude::*;
+use cgmath::Vector3;
+
+use aov::AovSample;
+use hit::HitRecord;
+use medium::Medium;
+use ray::Ray;
+use sampler::Sampler;
+use scene::Scene;
+use stats::{self, Counter};
+
+mod debug;
+mod path;
+
+pub use self::debug::{
+    AmbientOcclusionIntegrator, BarycentricIntegrator, DepthIntegrator, DirectLightingIntegrator,
+    MaterialIdIntegrator, NormalIntegrator, UvIntegrator,
+};
+pub use self::path::PathIntegrator;
+
+/// Names of every integrator that can be created with `create_integrator`
+pub const INTEGRATOR_NAMES: &[&str] = &[
+    "path",
+    "normals",
+    "depth",
+    "uv",
+    "material-id",
+    "barycentrics",
+    "ao",
+    "direct",
+];
+
+/// An integrator is a strategy for calculating how much light travels back
+/// along a camera ray
+pub trait Integrator: Sync {
+    fn radiance(&self, ray: Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Vector3<f32>;
+
+    /// Same as `radiance`, but also fills in the AOVs of the sample. By default only the
+    /// first surface along the ray is recorded and the light isn't split up
+    fn radiance_with_aovs(
+        &self,
+        ray: Ray,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+        aovs: &mut AovSample,
+    ) -> Vector3<f32> {
+        if let Some(record) = scene.world.hit(ray, T_MIN, f32::MAX) {
+            aovs.record_hit(ray, &record);
+        }
+
+        self.radiance(ray, scene, sampler)
+    }
+}
+
+/// Create an integrator by name. Returns None if no integrator has that name
+pub fn create_integrator(name: &str, max_depth: u32) -> Option<Box<dyn Integrator>> {
+    let integrator: Box<dyn Integrator> = match name {
+        "path" => Box::new(PathIntegrator::new(max_depth)),
+        "normals" => Box::new(NormalIntegrator),
+        "depth" => Box::new(DepthIntegrator),
+        "uv" => Box::new(UvIntegrator),
+        "material-id" => Box::new(MaterialIdIntegrator),
+        "barycentrics" => Box::new(BarycentricIntegrator),
+        "ao" => Box::new(AmbientOcclusionIntegrator::new(1.0)),
+        "direct" => Box::new(DirectLightingIntegrator::new(max_depth)),
+        _ => return None,
+    };
+
+    Some(integrator)
+}
+
+// Minimum distance along a ray before it can hit something. Stops rays from immediately
+// hitting the surface they left due to low precision floats
+const T_MIN: f32 = 0.001;
+
+// Closest hit along a ray followed by a path, counted in the render statistics
+fn trace(scene: &Scene, ray: Ray) -> Option<HitRecord<'_>> {
+    stats::count(Counter::PathRays);
+    scene.world.hit(ray, T_MIN, f32::MAX)
+}
+
+// Closest hit before `t_max` along a ray that only checks whether light is blocked
+fn trace_shadow(scene: &Scene, ray: Ray, t_max: f32) -> Option<HitRecord<'_>> {
+    stats::count(Counter::ShadowRays);
+    scene.world.hit(ray, T_MIN, t_max)
+}
+
+// Medium a ray is in after crossing the boundary of `boundary` at a point with `normal`
+fn entered_medium(ray: Ray, normal: Vector3<f32>, boundary: &dyn Medium) -> Option<&dyn Medium> {
+    if ray.direction().dot(normal) < 0.0 {
+        Some(boundary)
+    } else {
+        None
+    }
+}
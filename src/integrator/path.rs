@@ -0,0 +1,174 @@
+use cgmath::prelude::*;
+use cgmath::Vector3;
+
+use std::f32;
+
+use aov::{AovSample, Lobe};
+use integrator::{entered_medium, trace, Integrator};
+use medium::Medium;
+use ray::Ray;
+use sampler::Sampler;
+use scene::Scene;
+
+// Bounces before russian roulette is allowed to terminate a path
+const MIN_ROULETTE_DEPTH: u32 = 3;
+
+// Medium boundaries a path may pass through. They don't count as bounces, so this stops
+// rays from getting stuck between overlapping boundaries
+const MAX_CROSSINGS: u32 = 64;
+
+/// Unidirectional path tracer. Follows a path through the world for up to max_depth
+/// scatters/reflections and returns the light carried back along it
+pub struct PathIntegrator {
+    max_depth: u32,
+}
+
+impl PathIntegrator {
+    pub fn new(max_depth: u32) -> PathIntegrator {
+        PathIntegrator { max_depth }
+    }
+}
+
+impl Integrator for PathIntegrator {
+    fn radiance(&self, ray: Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Vector3<f32> {
+        self.radiance_with_aovs(ray, scene, sampler, &mut AovSample::new())
+    }
+
+    // Paths are terminated early with russian roulette once their throughput gets low, with
+    // survivors scaled up to keep the estimate unbiased.
+    //
+    // Light is split into AOVs by the first bounce along the path. Light that arrives without
+    // any bounce is emission, light arriving right after the first bounce is direct and
+    // everything after that is indirect
+    fn radiance_with_aovs(
+        &self,
+        mut ray: Ray,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+        aovs: &mut AovSample,
+    ) -> Vector3<f32> {
+        // Light gathered along the path so far
+        let mut radiance = Vector3::zero();
+        // Fraction of the light at the end of the path that makes it back to the camera
+        let mut throughput = Vector3::new(1.0, 1.0, 1.0);
+        // Participating medium the ray is currently traveling through
+        let mut medium: Option<&dyn Medium> = None;
+        // Kind of the first bounce, once there has been one
+        let mut first_lobe = None;
+
+        let mut depth = 0;
+        let mut crossings = 0;
+        sampler.start_bounce(depth);
+
+        while depth < self.max_depth {
+            let hit = trace(scene, ray);
+
+            // Light can scatter inside a medium before it reaches the next surface
+            if let Some(current) = medium {
+                let sample = current.sample(ray, hit.map_or(f32::MAX, |record| record.t), sampler);
+                let emission = throughput.mul_element_wise(sample.emission);
+                radiance += emission;
+                aovs.add_light(first_lobe, depth <= 1, emission);
+                throughput.mul_assign_element_wise(sample.weight);
+
+                if let Some(t) = sample.scatter_t {
+                    let direction = current.phase().sample(ray.direction(), sampler);
+                    ray = Ray::new(ray.point_at_distance(t), direction);
+
+                    // Scattering straight from the camera makes the medium visible
+                    if depth == 0 {
+                        aovs.alpha = 1.0;
+                    }
+                    first_lobe = first_lobe.or(Some(Lobe::Diffuse));
+
+                    depth += 1;
+                    sampler.start_bounce(depth);
+                    if !survives_roulette(depth, &mut throughput, sampler) {
+                        break;
+                    }
+
+                    continue;
+                }
+            }
+
+            let record = match hit {
+                Some(record) => record,
+                None => {
+                    let light = throughput.mul_element_wise(scene.background.radiance(ray));
+                    aovs.add_light(first_lobe, depth <= 1, light);
+
+                    // Media in front of the background cover up part of it
+                    if depth == 0 {
+                        let transmittance = (throughput.x + throughput.y + throughput.z) / 3.0;
+                        aovs.alpha = (1.0 - transmittance).clamp(0.0, 1.0);
+                    }
+
+                    return radiance + light;
+                }
+            };
+
+            // Crossing into or out of a medium doesn't change the direction of the ray
+            if let Some(boundary) = record.medium {
+                crossings += 1;
+                if crossings > MAX_CROSSINGS {
+                    break;
+                }
+
+                medium = entered_medium(ray, record.normal, boundary);
+                ray = Ray::new(record.position, ray.direction());
+
+                continue;
+            }
+
+            if depth == 0 {
+                aovs.record_hit(ray, &record);
+            }
+
+            let emission = throughput.mul_element_wise(record.material.emitted(ray, record));
+            radiance += emission;
+            aovs.add_light(first_lobe, depth <= 1, emission);
+
+            let lobe = if record.material.is_specular() {
+                Lobe::Specular
+            } else {
+                Lobe::Diffuse
+            };
+            first_lobe = first_lobe.or(Some(lobe));
+
+            match record.material.scatter(ray, record, sampler) {
+                Some(scattered_ray) => {
+                    // Attenuate ray based on the surface color
+                    throughput.mul_assign_element_wise(scattered_ray.attenuation);
+                    ray = scattered_ray.ray;
+                }
+                // The surface absorbed the ray
+                None => break,
+            }
+
+            depth += 1;
+            sampler.start_bounce(depth);
+            if !survives_roulette(depth, &mut throughput, sampler) {
+                break;
+            }
+        }
+
+        // Ray was terminated or has scattered so many times that it has been completely absorbed
+        radiance
+    }
+}
+
+// Randomly decide whether a path keeps going after `depth` bounces. Paths carrying little
+// light are likely to be killed off, but the ones that survive make up for the lost energy
+fn survives_roulette(depth: u32, throughput: &mut Vector3<f32>, sampler: &mut dyn Sampler) -> bool {
+    if depth <= MIN_ROULETTE_DEPTH {
+        return true;
+    }
+
+    let survival = throughput.x.max(throughput.y).max(throughput.z).min(0.95);
+    if sampler.next_f32() >= survival {
+        return false;
+    }
+
+    *throughput /= survival;
+    true
+}
@@ -0,0 +1,136 @@
+use cgmath::Vector3;
+
+use sampler::Sampler;
+
+/// An object giving off light that integrators can aim rays at directly, instead of
+/// waiting for paths to run into it by chance. Lights are also part of the world, so rays
+/// aimed at them can still be blocked
+pub trait Light: Send + Sync {
+    /// Direction from `origin` towards a random point on the light
+    fn sample_direction(&self, origin: Vector3<f32>, sampler: &mut dyn Sampler) -> Vector3<f32>;
+
+    /// Probability density, with respect to solid angle, of `sample_direction` picking
+    /// `direction` from `origin`. Zero if the direction misses the light
+    fn pdf(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cgmath::prelude::*;
+
+    use std::{f32, f64};
+
+    use hit::Hittable;
+    use material::Material;
+    use mesh::Mesh;
+    use ray::Ray;
+    use sampler;
+    use sphere::Sphere;
+
+    const SAMPLES: u32 = 10_000;
+
+    fn sphere() -> Sphere {
+        Sphere::new(
+            Vector3::new(0.0, 0.0, 3.0),
+            1.0,
+            Material::new_emissive(1.0, 1.0, 1.0),
+        )
+    }
+
+    // Tilted so it isn't lined up with the axes
+    fn quad() -> Mesh {
+        Mesh::quad(
+            Vector3::new(-1.0, -1.0, 2.0),
+            Vector3::new(1.0, -1.0, 2.5),
+            Vector3::new(1.0, 1.0, 2.5),
+            Vector3::new(-1.0, 1.0, 2.0),
+            Material::new_emissive(1.0, 1.0, 1.0),
+        )
+    }
+
+    fn check_samples_hit<L: Light + Hittable>(light: &L, name: &str) {
+        let mut sampler = sampler::create_sampler("random", 1, SAMPLES).unwrap();
+
+        for index in 0..SAMPLES {
+            sampler.start_sample(0, 0, index);
+            let direction = light.sample_direction(Vector3::zero(), &mut *sampler);
+
+            assert!(
+                light.pdf(Vector3::zero(), direction) > 0.0,
+                "{} has no density towards {:?}",
+                name,
+                direction
+            );
+            assert!(
+                light
+                    .hit(Ray::new(Vector3::zero(), direction), 0.0, f32::MAX)
+                    .is_some(),
+                "{} was missed by {:?}",
+                name,
+                direction
+            );
+        }
+    }
+
+    // Integrate the pdf over every direction with the midpoint rule
+    fn integrate_pdf<L: Light>(light: &L) -> f64 {
+        const THETA_STEPS: usize = 2000;
+        const PHI_STEPS: usize = 400;
+
+        let theta_step = f64::consts::PI / THETA_STEPS as f64;
+        let phi_step = 2.0 * f64::consts::PI / PHI_STEPS as f64;
+        let mut total = 0.0;
+
+        for i in 0..THETA_STEPS {
+            let theta = (i as f64 + 0.5) * theta_step;
+
+            for j in 0..PHI_STEPS {
+                let phi = (j as f64 + 0.5) * phi_step;
+                let direction = Vector3::new(
+                    (theta.sin() * phi.cos()) as f32,
+                    (theta.sin() * phi.sin()) as f32,
+                    theta.cos() as f32,
+                );
+
+                let pdf = f64::from(light.pdf(Vector3::zero(), direction));
+                total += pdf * theta.sin() * theta_step * phi_step;
+            }
+        }
+
+        total
+    }
+
+    #[test]
+    fn sampled_directions_hit_the_light() {
+        check_samples_hit(&sphere(), "sphere");
+        check_samples_hit(&quad(), "quad");
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        for &(name, total) in &[
+            ("sphere", integrate_pdf(&sphere())),
+            ("quad", integrate_pdf(&quad())),
+        ] {
+            assert!(
+                (total - 1.0).abs() < 0.01,
+                "pdf of the {} integrates to {}",
+                name,
+                total
+            );
+        }
+    }
+
+    #[test]
+    fn pdf_is_uniform_inside_a_sphere() {
+        let light = sphere();
+        let inside = Vector3::new(0.0, 0.5, 3.0);
+
+        for &direction in &[Vector3::unit_x(), -Vector3::unit_y(), Vector3::unit_z()] {
+            let pdf = light.pdf(inside, direction);
+            assert!((pdf - 0.25 / f32::consts::PI).abs() < 1e-6);
+        }
+    }
+}
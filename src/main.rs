@@ -1,32 +1,54 @@
 extern crate cgmath;
 extern crate clap;
+extern crate ctrlc;
 extern crate image;
-extern crate rand;
+extern crate png;
 extern crate rayon;
 
+mod aabb;
+mod adaptive;
+mod aov;
+mod bench;
+mod bvh;
 mod camera;
+mod checkpoint;
+mod compare;
+mod csg;
+mod cuboid;
+mod denoise;
+mod exr;
+mod film;
+mod heightfield;
 mod hit;
 mod hittable_list;
+mod integrator;
+mod light;
 mod material;
+mod medium;
+mod mesh;
+mod onb;
+mod progress;
+mod quadric;
 mod ray;
+mod renderer;
+mod sampler;
 mod scene;
+mod sdf;
 mod sphere;
+mod stats;
+mod torus;
 mod util;
+mod volume;
+mod voxel;
 
-use cgmath::prelude::*;
-use cgmath::Vector3;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 
-use clap::{App, Arg};
+use std::path::Path;
+use std::sync::atomic::Ordering;
 
-use image::Pixel;
-
-use rand::Rng;
-use rayon::prelude::*;
-
-use std::f32;
-
-use hit::Hittable;
-use ray::Ray;
+// Samples per pixel in each pass when passes are only needed for saving checkpoints or
+// keeping to a time limit
+const DEFAULT_PASS_SAMPLES: u32 = 4;
 
 fn main() {
     // Set up clap
@@ -34,13 +56,14 @@ fn main() {
         .version("0.2")
         .about("Ray traces a scene")
         .author("Warren")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(
             Arg::with_name("samples")
                 .short("s")
                 .long("samples")
                 .value_name("SAMPLES")
                 .help("Sets the number of samples per pixel")
-                .required(true)
+                .required_unless("time-limit")
                 .takes_value(true),
         )
         .arg(
@@ -61,8 +84,349 @@ fn main() {
                 .required(true)
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("max-depth")
+                .short("d")
+                .long("max-depth")
+                .value_name("DEPTH")
+                .help("Sets the maximum number of bounces per path")
+                .default_value("50")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("integrator")
+                .short("i")
+                .long("integrator")
+                .value_name("NAME")
+                .help("Sets the method used to calculate the color of each sample")
+                .possible_values(integrator::INTEGRATOR_NAMES)
+                .default_value("path")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("scene")
+                .long("scene")
+                .value_name("NAME")
+                .help("Sets the built-in scene to render")
+                .possible_values(scene::SCENE_NAMES)
+                .default_value("spheres")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("scene-seed")
+                .long("scene-seed")
+                .value_name("SEED")
+                .help("Sets the seed of the generated scenes, cover and sphere-cloud. The same seed always generates the same scene")
+                .default_value("0")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("count")
+                .long("count")
+                .value_name("OBJECTS")
+                .help("Sets the number of objects in the generated scenes, cover and sphere-cloud")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("volume")
+                .long("volume")
+                .value_name("FILE")
+                .help("Loads the density grid of the cloud in the volumes scene from a .raw or text voxel file")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("heightmap")
+                .long("heightmap")
+                .value_name("FILE")
+                .help("Loads the heights of the terrain scene from a grayscale, .hdr or .exr image")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .value_name("SEED")
+                .help("Sets the seed for random numbers. The same seed always renders the same image")
+                .default_value("0")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("sampler")
+                .long("sampler")
+                .value_name("NAME")
+                .help("Sets how random numbers are distributed across the samples of a pixel")
+                .possible_values(sampler::SAMPLER_NAMES)
+                .default_value("random")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-samples")
+                .long("max-samples")
+                .value_name("SAMPLES")
+                .help("Turns on adaptive sampling, where noisy pixels keep sampling up to this many samples and --samples is the minimum")
+                .requires("samples")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("noise-threshold")
+                .long("noise-threshold")
+                .value_name("ERROR")
+                .help("Sets the relative error at which adaptive sampling stops sampling a pixel")
+                .default_value("0.01")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("sample-heatmap")
+                .long("sample-heatmap")
+                .value_name("FILE")
+                .help("Writes an image showing how many samples each pixel took")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("filter")
+                .long("filter")
+                .value_name("NAME")
+                .help("Sets the reconstruction filter used to spread samples over nearby pixels")
+                .possible_values(film::FILTER_NAMES)
+                .default_value("box")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("filter-radius")
+                .long("filter-radius")
+                .value_name("PIXELS")
+                .help("Sets the radius of the reconstruction filter. Each filter has its own default")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .help("Sets the file the image is written to")
+                .default_value("output.png")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("progressive")
+                .long("progressive")
+                .value_name("SAMPLES")
+                .help("Renders in passes of this many samples per pixel, writing the image after every pass")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("write-interval")
+                .long("write-interval")
+                .value_name("SECONDS")
+                .help("In progressive mode, only writes the image after a pass if this many seconds passed since the last write")
+                .requires("progressive")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("checkpoint")
+                .long("checkpoint")
+                .value_name("FILE")
+                .help("Saves the render to a checkpoint file periodically, when interrupted and at the end")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("checkpoint-interval")
+                .long("checkpoint-interval")
+                .value_name("SECONDS")
+                .help("Sets how often the checkpoint is saved")
+                .default_value("300")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("resume")
+                .long("resume")
+                .help("Continues adding samples to the checkpoint file, which must be for the same scene and settings")
+                .requires("checkpoint"),
+        )
+        .arg(
+            Arg::with_name("time-limit")
+                .long("time-limit")
+                .value_name("SECONDS")
+                .help("Keeps adding passes of samples until the time runs out, up to --samples if it's given")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("aovs")
+                .long("aovs")
+                .value_name("NAMES")
+                .help("Renders extra images alongside the final one, separated by commas. Integrators other than path only fill in the ones that come from the first hit")
+                .possible_values(aov::AOV_NAMES)
+                .possible_value("all")
+                .use_delimiter(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("aov-output")
+                .long("aov-output")
+                .value_name("FILE")
+                .help("Sets where AOVs are written. An .exr file gets every AOV as a layer, any other format gets one file per AOV with the name added on")
+                .default_value("aovs.exr")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("denoise")
+                .long("denoise")
+                .help("Denoises the final image, guided by the albedo and normals of the scene"),
+        )
+        .arg(
+            Arg::with_name("stats-json")
+                .long("stats-json")
+                .value_name("FILE")
+                .help("Writes the render statistics to a JSON file")
+                .takes_value(true),
+        )
+        .subcommand(
+            SubCommand::with_name("denoise")
+                .about("Denoises a rendered image")
+                .arg(
+                    Arg::with_name("input")
+                        .value_name("FILE")
+                        .help("Sets the EXR image to denoise, which needs albedo and normal layers like the ones written by --aovs albedo,normal")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .value_name("FILE")
+                        .help("Sets the file the denoised image is written to")
+                        .default_value("denoised.png")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("color-sigma")
+                        .long("color-sigma")
+                        .value_name("SIGMA")
+                        .help("Sets how different colors can be and still get blurred together")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("normal-sigma")
+                        .long("normal-sigma")
+                        .value_name("SIGMA")
+                        .help("Sets how different normals can be and still get blurred together")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("albedo-sigma")
+                        .long("albedo-sigma")
+                        .value_name("SIGMA")
+                        .help("Sets how different albedos can be and still get blurred together")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("compare")
+                .about("Compares an image against a reference image")
+                .arg(
+                    Arg::with_name("reference")
+                        .value_name("REFERENCE")
+                        .help("Sets the reference image, a PNG, EXR or any other image file")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("image")
+                        .value_name("IMAGE")
+                        .help("Sets the image compared against the reference")
+                        .required(true)
+                        .index(2),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .value_name("FILE")
+                        .help("Sets the file the false color difference image is written to")
+                        .default_value("difference.png")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("bench")
+                .about("Renders built-in scenes and reports how fast rays were traced")
+                .arg(
+                    Arg::with_name("scenes")
+                        .long("scenes")
+                        .value_name("NAMES")
+                        .help("Sets the scenes to render, separated by commas. By default every scene meant for measuring performance is rendered")
+                        .possible_values(scene::SCENE_NAMES)
+                        .use_delimiter(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("resx")
+                        .short("x")
+                        .long("resx")
+                        .value_name("PIXELS")
+                        .help("Sets the width of every image")
+                        .default_value("320")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("resy")
+                        .short("y")
+                        .long("resy")
+                        .value_name("PIXELS")
+                        .help("Sets the height of every image")
+                        .default_value("180")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("samples")
+                        .short("s")
+                        .long("samples")
+                        .value_name("SAMPLES")
+                        .help("Sets the number of samples per pixel")
+                        .default_value("16")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("max-depth")
+                        .long("max-depth")
+                        .value_name("DEPTH")
+                        .help("Sets the maximum number of bounces of each path")
+                        .default_value("50")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("runs")
+                        .long("runs")
+                        .value_name("RUNS")
+                        .help("Sets how many times each scene is rendered, the median time is reported")
+                        .default_value("3")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("seed")
+                        .long("seed")
+                        .value_name("SEED")
+                        .help("Sets the seed of the random numbers used for sampling")
+                        .default_value("0")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .value_name("FILE")
+                        .help("Writes the results to a JSON file")
+                        .takes_value(true),
+                ),
+        )
         .get_matches();
 
+    match matches.subcommand() {
+        ("denoise", Some(matches)) => return run_denoise(matches),
+        ("compare", Some(matches)) => return run_compare(matches),
+        ("bench", Some(matches)) => return run_bench(matches),
+        _ => {}
+    }
+
     // Convert arg to a u32
     let pixel_res_x = match matches.value_of("resx").unwrap().parse() {
         Ok(res_x) => res_x,
@@ -81,75 +445,436 @@ fn main() {
         }
     };
 
+    // Convert arg to a u32. Only a time limit can render without one, and then there's no
+    // limit to the number of samples
+    let num_samples = match matches.value_of("samples").map(str::parse) {
+        Some(Ok(samples)) => samples,
+        Some(Err(_)) => {
+            println!("Provided number of samples was not valid");
+            std::process::exit(-1);
+        }
+        None => u32::MAX,
+    };
+
+    // Convert arg to a Duration
+    let time_limit = matches
+        .value_of("time-limit")
+        .map(|seconds| match seconds.parse::<f32>() {
+            Ok(seconds) if seconds >= 0.0 => std::time::Duration::from_secs_f32(seconds),
+            _ => {
+                println!("Provided time limit was not valid");
+                std::process::exit(-1);
+            }
+        });
+
     // Convert arg to a u32
-    let num_samples = match matches.value_of("samples").unwrap().parse() {
-        Ok(samples) => samples,
+    let max_depth = match matches.value_of("max-depth").unwrap().parse() {
+        Ok(depth) => depth,
         Err(_) => {
-            println!("Provided number of samples was not valid");
+            println!("Provided maximum depth was not valid");
             std::process::exit(-1);
         }
     };
 
-    // Create a scene to render
-    let scene = scene::load_scene();
+    // Convert arg to a u64
+    let seed = match matches.value_of("seed").unwrap().parse() {
+        Ok(seed) => seed,
+        Err(_) => {
+            println!("Provided seed was not valid");
+            std::process::exit(-1);
+        }
+    };
 
-    // Start the rendering stopwatch
-    let start_time = std::time::Instant::now();
-    println!(
-        "Now rendering a {} by {} image with {} samples per pixel",
-        pixel_res_x, pixel_res_y, num_samples
+    // Convert args to a u32 and f32, adaptive sampling is only used if there's a maximum
+    let sampling = match matches.value_of("max-samples") {
+        Some(max_samples) => {
+            let max_samples = match max_samples.parse() {
+                Ok(max_samples) if max_samples >= num_samples => max_samples,
+                _ => {
+                    println!("Provided maximum number of samples was not valid, it must be at least the number of samples");
+                    std::process::exit(-1);
+                }
+            };
+
+            let noise_threshold = match matches.value_of("noise-threshold").unwrap().parse() {
+                Ok(threshold) if threshold >= 0.0 => threshold,
+                _ => {
+                    println!("Provided noise threshold was not valid");
+                    std::process::exit(-1);
+                }
+            };
+
+            adaptive::AdaptiveSampling::new(num_samples, max_samples, noise_threshold)
+        }
+        None => adaptive::AdaptiveSampling::fixed(num_samples),
+    };
+
+    // Convert arg to a positive f32
+    let filter_radius =
+        matches
+            .value_of("filter-radius")
+            .map(|radius| match radius.parse::<f32>() {
+                Ok(radius) if radius > 0.0 => radius,
+                _ => {
+                    println!("Provided filter radius was not valid");
+                    std::process::exit(-1);
+                }
+            });
+
+    // Possible values are already validated by clap
+    let filter = film::create_filter(matches.value_of("filter").unwrap(), filter_radius).unwrap();
+
+    // Convert arg to a u32, without it the whole image is rendered in a single pass
+    let pass_samples = matches
+        .value_of("progressive")
+        .map(|samples| match samples.parse() {
+            Ok(samples) if samples > 0 => samples,
+            _ => {
+                println!("Provided number of samples per pass was not valid");
+                std::process::exit(-1);
+            }
+        });
+
+    // Convert arg to a Duration
+    let write_interval =
+        matches
+            .value_of("write-interval")
+            .map(|seconds| match seconds.parse::<f32>() {
+                Ok(seconds) if seconds >= 0.0 => std::time::Duration::from_secs_f32(seconds),
+                _ => {
+                    println!("Provided write interval was not valid");
+                    std::process::exit(-1);
+                }
+            });
+
+    let output_path = matches.value_of("output").unwrap();
+
+    let checkpoint_path = matches.value_of("checkpoint").map(Path::new);
+
+    // Possible values are already validated by clap
+    let aovs: Vec<aov::Aov> = match matches.values_of("aovs") {
+        Some(names) => {
+            let names: Vec<&str> = names.collect();
+            let names = if names.contains(&"all") {
+                aov::AOV_NAMES.to_vec()
+            } else {
+                names
+            };
+
+            let mut aovs: Vec<aov::Aov> = Vec::new();
+            for aov in names.into_iter().filter_map(aov::Aov::from_name) {
+                if !aovs.contains(&aov) {
+                    aovs.push(aov);
+                }
+            }
+
+            aovs
+        }
+        None => Vec::new(),
+    };
+
+    // Convert arg to a Duration
+    let checkpoint_interval = match matches
+        .value_of("checkpoint-interval")
+        .unwrap()
+        .parse::<f32>()
+    {
+        Ok(seconds) if seconds >= 0.0 => std::time::Duration::from_secs_f32(seconds),
+        _ => {
+            println!("Provided checkpoint interval was not valid");
+            std::process::exit(-1);
+        }
+    };
+
+    let scene_name = matches.value_of("scene").unwrap();
+
+    // Convert args to a u64 and a positive usize
+    let scene_options = scene::SceneOptions {
+        seed: match matches.value_of("scene-seed").unwrap().parse() {
+            Ok(seed) => seed,
+            Err(_) => {
+                println!("Provided scene seed was not valid");
+                std::process::exit(-1);
+            }
+        },
+        count: matches.value_of("count").map(|count| match count.parse() {
+            Ok(count) if count > 0 => count,
+            _ => {
+                println!("Provided number of objects was not valid");
+                std::process::exit(-1);
+            }
+        }),
+    };
+
+    // The scene seed has a default, so only complain about it when it was given
+    let scene_options_given =
+        matches.is_present("count") || matches.occurrences_of("scene-seed") > 0;
+    if scene_options_given && !scene::GENERATED_SCENES.contains(&scene_name) {
+        println!("Only generated scenes take a scene seed or number of objects");
+        std::process::exit(-1);
+    }
+
+    if matches.is_present("volume") && scene_name != "volumes" {
+        println!("Only the volumes scene has a cloud to load a volume into");
+        std::process::exit(-1);
+    }
+
+    if matches.is_present("heightmap") && scene_name != "terrain" {
+        println!("Only the terrain scene has heights to load");
+        std::process::exit(-1);
+    }
+
+    // Everything that changes the samples of a render. A checkpoint can only be resumed
+    // with the same settings
+    let render_settings = format!(
+        "scene={} scene-seed={} count={:?} integrator={} max-depth={} sampler={} seed={} \
+         filter={:?} volume={} heightmap={} aovs={}",
+        scene_name,
+        scene_options.seed,
+        scene_options.count,
+        matches.value_of("integrator").unwrap(),
+        max_depth,
+        matches.value_of("sampler").unwrap(),
+        seed,
+        filter,
+        matches.value_of("volume").unwrap_or("none"),
+        matches.value_of("heightmap").unwrap_or("none"),
+        !aovs.is_empty() || matches.is_present("denoise"),
     );
 
-    // Render the scene
-    let output_buffer = {
-        // Camera contains the ray emitter and calculates colors for a PIXEL_RES_X
-        // and PIXEL_RES_Y sized image
-        let camera = camera::Camera::new(pixel_res_x, pixel_res_y);
+    // Possible values are already validated by clap
+    let integrator =
+        integrator::create_integrator(matches.value_of("integrator").unwrap(), max_depth).unwrap();
 
-        // Create PNG for final output
-        let mut image_buffer = image::ImageBuffer::new(pixel_res_x, pixel_res_y);
+    let cloud_density = matches.value_of("volume").map(|path| {
+        match voxel::VoxelGrid::load(std::path::Path::new(path)) {
+            Ok(grid) => grid,
+            Err(e) => {
+                println!("Could not load voxel grid {}: {}", path, e);
+                std::process::exit(-1);
+            }
+        }
+    });
 
-        for y in 0..pixel_res_y {
-            for x in 0..pixel_res_x {
-                // Create ray based on offsets from origin to point on plane z = -1
-                // The actual ray is exactly the opposite of how it works in real life
-                let total_color: Vector3<f32> = (0..num_samples)
-                    .into_par_iter()
-                    .map(|_| {
-                        // Create pRNG for MSAA
-                        let mut rng = rand::thread_rng();
+    let terrain_heights = matches.value_of("heightmap").map(|path| {
+        match heightfield::HeightMap::load(std::path::Path::new(path)) {
+            Ok(heights) => heights,
+            Err(e) => {
+                println!("Could not load height map {}: {}", path, e);
+                std::process::exit(-1);
+            }
+        }
+    });
 
-                        // Randomly offset each ray by a tiny, random amount to get nice AA
-                        let horizontal_offset = (x as f32 + rng.next_f32()) / pixel_res_x as f32;
-                        let vertical_offset = (y as f32 + rng.next_f32()) / pixel_res_y as f32;
+    // Possible values are already validated by clap
+    let sampler_prototype = sampler::create_sampler(
+        matches.value_of("sampler").unwrap(),
+        seed,
+        sampling.max_samples,
+    )
+    .unwrap();
 
-                        // Initialize a ray starting at the camera aimed at these coords
-                        let ray = camera.get_ray_at_coords(horizontal_offset, vertical_offset);
+    // Create a scene to render, only the volumes scene has a cloud and only the terrain
+    // has heights
+    let scene = match scene_name {
+        "volumes" => scene::volume_scene(cloud_density),
+        "terrain" => scene::terrain_scene(terrain_heights),
+        // Possible values are already validated by clap
+        name => scene::create_scene(name, scene_options).unwrap(),
+    };
 
-                        // Ray trace the ray and calculate the final color of the ray. color is a
-                        // recursive function over the depth. So set depth to zero to start with
-                        color(ray, &scene, 0)
-                    })
-                    .sum();
+    // Start the rendering stopwatch
+    let start_time = std::time::Instant::now();
+    if let Some(limit) = time_limit {
+        println!(
+            "Now rendering a {} by {} image for {}",
+            pixel_res_x,
+            pixel_res_y,
+            util::format_seconds(limit.as_secs())
+        );
+    } else if sampling.is_adaptive() {
+        println!(
+            "Now rendering a {} by {} image with {} to {} samples per pixel",
+            pixel_res_x, pixel_res_y, sampling.min_samples, sampling.max_samples
+        );
+    } else {
+        println!(
+            "Now rendering a {} by {} image with {} samples per pixel",
+            pixel_res_x, pixel_res_y, num_samples
+        );
+    }
+
+    // Camera contains the ray emitter and calculates colors for a PIXEL_RES_X
+    // and PIXEL_RES_Y sized image
+    let camera = camera::Camera::new(pixel_res_x, pixel_res_y, scene.camera);
+    let renderer = renderer::Renderer::new(camera, scene, integrator, sampler_prototype, sampling);
+    let mut film = film::Film::new(pixel_res_x, pixel_res_y, filter);
+    // The denoiser is guided by the albedo and normal AOVs
+    if !aovs.is_empty() || matches.is_present("denoise") {
+        film.enable_aovs();
+    }
+
+    // Samples per pixel of the last pass that finished
+    let mut sample_limit = 0;
 
-                // Final color is the average of all samples on a pixel
-                let mut rgb = total_color.div_element_wise(num_samples as f32);
+    if matches.is_present("resume") {
+        let path = checkpoint_path.unwrap();
 
-                // Convert from colors in range 0.0..1.0 to 0..255
-                rgb *= 255.99;
-                // Cast to bytes for storage in png
-                let rgb = rgb.cast::<u8>().unwrap();
-                let pixel = image::Rgba::from_channels(rgb.x, rgb.y, rgb.z, 255);
-                // Reverse image beacuse I am indexing from top-down
-                image_buffer.put_pixel(x, pixel_res_y - y - 1, pixel);
+        match checkpoint::load(path, &render_settings, &mut film) {
+            Ok(limit) => {
+                sample_limit = limit;
+                println!(
+                    "Resuming from {} with {} samples per pixel",
+                    path.display(),
+                    sample_limit
+                );
             }
+            Err(e) => {
+                println!("Could not resume from checkpoint {}: {}", path.display(), e);
+                std::process::exit(-1);
+            }
+        }
+    }
+
+    // The first Ctrl-C stops rendering and saves what's there, the second quits right away
+    let stop = renderer.stop_flag();
+    let _ = ctrlc::set_handler(move || {
+        if stop.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
         }
 
-        image_buffer
+        println!("Stopping, press Ctrl-C again to quit without saving");
+    });
+
+    // Stop rendering once the time limit is up, counting from the start of the stopwatch
+    if let Some(limit) = time_limit {
+        let stop = renderer.stop_flag();
+        let remaining = limit.checked_sub(start_time.elapsed()).unwrap_or_default();
+
+        std::thread::spawn(move || {
+            std::thread::sleep(remaining);
+            stop.store(true, Ordering::SeqCst);
+        });
+    }
+
+    // Render the scene, either all at once or in passes that each add a few samples to
+    // every pixel. Checkpoints can only be saved in between passes, and a time limit
+    // needs passes so every pixel gets about the same number of samples when it runs out
+    let pass_samples = match pass_samples {
+        Some(samples) => samples,
+        None if checkpoint_path.is_some() || time_limit.is_some() => DEFAULT_PASS_SAMPLES,
+        None => sampling.max_samples,
+    };
+    let mut pass = 0;
+    let mut last_write = std::time::Instant::now();
+    let mut last_checkpoint = std::time::Instant::now();
+
+    // Progress is the fraction of samples taken, or of the time used if that's further along
+    let progress_bar = {
+        let samples_left =
+            (u64::from(pixel_res_x) * u64::from(pixel_res_y) * u64::from(sampling.max_samples))
+                .saturating_sub(film.total_sample_count())
+                .max(1);
+
+        progress::ProgressBar::start(move || {
+            let samples = stats::total(stats::Counter::CameraRays) as f64 / samples_left as f64;
+            let time = time_limit.map_or(0.0, |limit| {
+                start_time.elapsed().as_secs_f64() / limit.as_secs_f64()
+            });
+
+            samples.max(time)
+        })
     };
 
-    // Write output to the current working directory
-    let _ = output_buffer.save("output.png");
+    while sample_limit < sampling.max_samples {
+        let pass_limit = sample_limit
+            .saturating_add(pass_samples)
+            .min(sampling.max_samples);
+
+        // Adaptive sampling can finish every pixel before the maximum
+        let samples_taken = renderer.render_pass(&mut film, pass_limit);
+        if renderer.is_stopped() {
+            break;
+        }
+
+        sample_limit = pass_limit;
+        pass += 1;
+        if samples_taken == 0 || sample_limit == sampling.max_samples {
+            break;
+        }
+
+        if let Some(path) = checkpoint_path {
+            if last_checkpoint.elapsed() >= checkpoint_interval {
+                save_checkpoint(path, &render_settings, sample_limit, &film);
+                last_checkpoint = std::time::Instant::now();
+            }
+        }
+
+        if matches.is_present("progressive") {
+            let interval_passed = match write_interval {
+                Some(interval) => last_write.elapsed() >= interval,
+                None => true,
+            };
+
+            if interval_passed {
+                write_image(&film, output_path);
+                last_write = std::time::Instant::now();
+                println!(
+                    "Finished pass {} with up to {} samples per pixel, wrote {}",
+                    pass, sample_limit, output_path
+                );
+            }
+        }
+    }
+
+    progress_bar.finish();
+    let render_stats = stats::RenderStats::collect(start_time.elapsed());
+
+    if matches.is_present("denoise") {
+        let image = film.to_exr(&[aov::Aov::Albedo, aov::Aov::Normal]);
+        let denoised = denoise::denoise_image(&image, denoise::DenoiseSettings::default()).unwrap();
+
+        if let Err(e) = save_image(&denoised, output_path) {
+            println!("Could not write image {}: {}", output_path, e);
+        }
+    } else {
+        write_image(&film, output_path);
+    }
+
+    if !aovs.is_empty() {
+        write_aovs(&film, &aovs, matches.value_of("aov-output").unwrap());
+    }
+
+    if let Some(path) = checkpoint_path {
+        save_checkpoint(path, &render_settings, sample_limit, &film);
+    }
+
+    let time_ran_out = time_limit.is_some_and(|limit| start_time.elapsed() >= limit);
+    if renderer.is_stopped() && !time_ran_out {
+        println!("Rendering was interrupted");
+    }
+
+    if time_limit.is_some() {
+        println!(
+            "Reached an average of {:.1} samples per pixel in the time limit",
+            film.average_sample_count()
+        );
+    } else if sampling.is_adaptive() {
+        println!(
+            "Took an average of {:.1} samples per pixel",
+            film.average_sample_count()
+        );
+    }
+
+    if let Some(path) = matches.value_of("sample-heatmap") {
+        // Time limited renders can stop long before the maximum
+        let max_samples = sampling.max_samples.min(film.max_sample_count());
+        let heatmap = film.sample_heatmap(sampling.min_samples.min(max_samples), max_samples);
+
+        if let Err(e) = heatmap.save(path) {
+            println!("Could not write sample heatmap {}: {}", path, e);
+        }
+    }
 
     // Calculate elapsed time
     let elapsed_time = start_time.elapsed();
@@ -157,37 +882,240 @@ fn main() {
         "Raytracing took {}",
         util::format_seconds(elapsed_time.as_secs())
     );
+    println!("{}", render_stats.report());
+
+    if let Some(path) = matches.value_of("stats-json") {
+        if let Err(e) = std::fs::write(path, render_stats.to_json()) {
+            println!("Could not write render statistics {}: {}", path, e);
+        }
+    }
+}
+
+// Save a checkpoint of the film, a failure shouldn't stop the render
+fn save_checkpoint(path: &Path, settings: &str, sample_limit: u32, film: &film::Film) {
+    match checkpoint::save(path, settings, sample_limit, film) {
+        Ok(()) => println!(
+            "Saved checkpoint {} with {} samples per pixel",
+            path.display(),
+            sample_limit
+        ),
+        Err(e) => println!("Could not save checkpoint {}: {}", path.display(), e),
+    }
+}
+
+// Write the film to an image file, the format is picked from the extension. EXR files
+// keep the full range of the colors
+fn write_image(film: &film::Film, path: &str) {
+    let result = if is_exr(path) {
+        film.to_exr(&[]).save(Path::new(path))
+    } else {
+        film.to_image().save(path).map_err(std::io::Error::other)
+    };
+
+    if let Err(e) = result {
+        println!("Could not write image {}: {}", path, e);
+    }
 }
 
-// Ray takes on the end color after up to 50 scatters/reflections
-fn color<H: Hittable>(ray: Ray, world: &H, depth: u32) -> Vector3<f32> {
-    // Ray trace through the world and check if it hit anything between 0.001 and f32::MAX distance
-    if let Some(record) = world.hit(ray, 0.001, f32::MAX) {
-        // Limit the closest distance because otherwise rays would just keep bouncing due to
-        // low precision floats or would recurse too deeply and overflow the stack
-        if depth < 50 {
-            if let Some(scattered_ray) = record.material.scatter(ray, record) {
-                // Attenuate ray based on the surface color
-                return scattered_ray.attenuation.mul_element_wise(color(
-                    scattered_ray.ray,
-                    world,
-                    depth + 1,
-                ));
-            }
-        }
-
-        // Ray has scattered so many times that it has been completely absorbed
-        return Vector3::new(0.0, 0.0, 0.0);
+// Write an image in memory to a file, EXR files keep the full range of the colors and
+// other formats are clamped to 8 bits
+fn save_image(image: &exr::ExrImage, path: &str) -> std::io::Result<()> {
+    if is_exr(path) {
+        image.save(Path::new(path))
     } else {
-        // Didn't hit anything so set up the skybox
-        // Create a background gradient by lerping white and blue over the height
-
-        // Normalize ray height to -1.0 to 1.0
-        let height = ray.direction().normalize().y;
-        // Scale ray to range 0.0 to 1.0 to get lerp factor
-        let t = 0.5 * (height + 1.0);
-        // Lerp height to get color
-        // Blended Value = (1 - t) * start_value + t * end_value where t is the lerp factor
-        (1.0 - t) * Vector3::new(1.0, 1.0, 1.0) + t * Vector3::new(0.5, 0.7, 1.0)
+        image.to_image().save(path).map_err(std::io::Error::other)
+    }
+}
+
+// Write AOVs as layers of an EXR file, along with the final image, or as one 8 bit
+// preview image per AOV named after the AOV
+fn write_aovs(film: &film::Film, aovs: &[aov::Aov], path: &str) {
+    if is_exr(path) {
+        if let Err(e) = film.to_exr(aovs).save(Path::new(path)) {
+            println!("Could not write AOVs {}: {}", path, e);
+        }
+
+        return;
+    }
+
+    let path = Path::new(path);
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("aov");
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("png");
+
+    for &aov in aovs {
+        let aov_path = path.with_file_name(format!("{}.{}.{}", stem, aov.name(), extension));
+
+        if let Some(image) = film.aov_preview(aov) {
+            if let Err(e) = image.save(&aov_path) {
+                println!("Could not write AOV {}: {}", aov_path.display(), e);
+            }
+        }
+    }
+}
+
+fn is_exr(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("exr"))
+}
+
+// Denoise an image saved by an earlier render
+fn run_denoise(matches: &ArgMatches) {
+    let input_path = matches.value_of("input").unwrap();
+    let output_path = matches.value_of("output").unwrap();
+
+    // Convert args to positive f32s, anything not given keeps its default
+    let mut settings = denoise::DenoiseSettings::default();
+    for (name, sigma) in [
+        ("color-sigma", &mut settings.color_sigma),
+        ("normal-sigma", &mut settings.normal_sigma),
+        ("albedo-sigma", &mut settings.albedo_sigma),
+    ] {
+        if let Some(value) = matches.value_of(name) {
+            match value.parse::<f32>() {
+                Ok(value) if value > 0.0 => *sigma = value,
+                _ => {
+                    println!("Provided {} was not valid", name);
+                    std::process::exit(-1);
+                }
+            }
+        }
+    }
+
+    let image = match exr::ExrImage::load(Path::new(input_path)) {
+        Ok(image) => image,
+        Err(e) => {
+            println!("Could not load image {}: {}", input_path, e);
+            std::process::exit(-1);
+        }
+    };
+
+    let denoised = match denoise::denoise_image(&image, settings) {
+        Ok(denoised) => denoised,
+        Err(e) => {
+            println!("Could not denoise {}: {}", input_path, e);
+            std::process::exit(-1);
+        }
+    };
+
+    if let Err(e) = save_image(&denoised, output_path) {
+        println!("Could not write image {}: {}", output_path, e);
+        std::process::exit(-1);
+    }
+
+    println!("Wrote denoised image to {}", output_path);
+}
+
+// Compare two images and write an image of where they differ
+fn run_compare(matches: &ArgMatches) {
+    let load = |name: &str| {
+        let path = matches.value_of(name).unwrap();
+
+        match compare::ColorBuffer::load(Path::new(path)) {
+            Ok(image) => image,
+            Err(e) => {
+                println!("Could not load image {}: {}", path, e);
+                std::process::exit(-1);
+            }
+        }
+    };
+
+    let reference = load("reference");
+    let image = load("image");
+
+    if (image.width, image.height) != (reference.width, reference.height) {
+        println!(
+            "Image is {} by {} but the reference is {} by {}",
+            image.width, image.height, reference.width, reference.height
+        );
+        std::process::exit(-1);
+    }
+
+    let comparison = compare::compare(&image, &reference);
+    println!("MSE:          {:.6}", comparison.mse);
+    println!("Relative MSE: {:.6}", comparison.relative_mse);
+    println!("PSNR:         {:.2} dB", comparison.psnr);
+    println!("SSIM:         {:.4}", comparison.ssim);
+    println!("FLIP:         {:.4}", comparison.flip);
+
+    let output_path = matches.value_of("output").unwrap();
+    let difference = compare::false_color(&comparison.error_map, image.width, image.height);
+    if let Err(e) = difference.save(output_path) {
+        println!("Could not write difference image {}: {}", output_path, e);
+        std::process::exit(-1);
+    }
+}
+
+// Render the built-in scenes with the same settings and report how fast each one was
+fn run_bench(matches: &ArgMatches) {
+    // Convert args to positive numbers
+    fn parse<T: std::str::FromStr + PartialOrd + Default>(matches: &ArgMatches, name: &str) -> T {
+        match matches.value_of(name).unwrap().parse() {
+            Ok(value) if value > T::default() => value,
+            _ => {
+                println!("Provided {} was not valid", name);
+                std::process::exit(-1);
+            }
+        }
+    }
+
+    let settings = bench::BenchSettings {
+        width: parse(matches, "resx"),
+        height: parse(matches, "resy"),
+        samples: parse(matches, "samples"),
+        max_depth: parse(matches, "max-depth"),
+        runs: parse(matches, "runs"),
+        seed: match matches.value_of("seed").unwrap().parse() {
+            Ok(seed) => seed,
+            Err(_) => {
+                println!("Provided seed was not valid");
+                std::process::exit(-1);
+            }
+        },
+    };
+
+    // Possible values are already validated by clap
+    let names: Vec<&str> = match matches.values_of("scenes") {
+        Some(names) => names.collect(),
+        None => bench::DEFAULT_SCENES.to_vec(),
+    };
+
+    println!(
+        "Rendering {} scenes at {} by {} with {} samples per pixel, {} runs each",
+        names.len(),
+        settings.width,
+        settings.height,
+        settings.samples,
+        settings.runs
+    );
+
+    let start_time = std::time::Instant::now();
+    let mut results = Vec::new();
+    for name in names {
+        let result = bench::run_scene(name, settings).unwrap();
+        println!(
+            "{}: {:.3}s, {:.0} rays per second",
+            name,
+            result.median_seconds(),
+            result.stats.rays_per_second()
+        );
+        results.push(result);
+    }
+
+    println!();
+    print!("{}", bench::report(&results));
+
+    if let Some(path) = matches.value_of("json") {
+        let json = bench::to_json(settings, &results, start_time.elapsed());
+        if let Err(e) = std::fs::write(path, json) {
+            println!("Could not write benchmark results {}: {}", path, e);
+            std::process::exit(-1);
+        }
     }
 }
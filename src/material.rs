@@ -1,9 +1,14 @@
-use cgmath::Vector3;
 use cgmath::prelude::*;
-use rand::{self, Rand};
+use cgmath::Vector3;
+
+use std::collections::hash_map::DefaultHasher;
+use std::f32;
+use std::hash::Hasher;
 
 use hit::HitRecord;
+use onb::Onb;
 use ray::Ray;
+use sampler::Sampler;
 
 /// Contains the data of a newly created ray, attenuation is a measure of
 /// how much the hit impacted the ray absorbtion
@@ -18,6 +23,12 @@ pub enum Material {
     Lambertian {
         albedo: Vector3<f32>,
     },
+    // Rough diffuse surface. Roughness is the standard deviation of the microfacet
+    // slope angle in radians, a roughness of zero is identical to Lambertian
+    OrenNayar {
+        albedo: Vector3<f32>,
+        roughness: f32,
+    },
     Metallic {
         albedo: Vector3<f32>,
         fuzziness: f32,
@@ -25,6 +36,10 @@ pub enum Material {
     Dielectric {
         refractive_index: f32,
     },
+    // Light source, glowing on the side the normal points to and absorbing all light
+    Emissive {
+        emission: Vector3<f32>,
+    },
 }
 
 impl Material {
@@ -34,6 +49,13 @@ impl Material {
         }
     }
 
+    pub fn new_oren_nayar(r: f32, g: f32, b: f32, roughness: f32) -> Material {
+        Material::OrenNayar {
+            albedo: Vector3::new(r, g, b),
+            roughness,
+        }
+    }
+
     pub fn new_metallic(r: f32, g: f32, b: f32, fuzziness: f32) -> Material {
         Material::Metallic {
             albedo: Vector3::new(r, g, b),
@@ -44,22 +66,107 @@ impl Material {
     pub fn new_dielectric(refractive_index: f32) -> Material {
         Material::Dielectric { refractive_index }
     }
+
+    pub fn new_emissive(r: f32, g: f32, b: f32) -> Material {
+        Material::Emissive {
+            emission: Vector3::new(r, g, b),
+        }
+    }
 }
 
 impl Material {
+    // Whether the material reflects or refracts around a mirror direction instead of
+    // scattering light all over the hemisphere
+    pub fn is_specular(&self) -> bool {
+        match *self {
+            Material::Lambertian { .. }
+            | Material::OrenNayar { .. }
+            | Material::Emissive { .. } => false,
+            Material::Metallic { .. } | Material::Dielectric { .. } => true,
+        }
+    }
+
+    // Whether the material scatters into a single direction, in which case eval() and
+    // scattering_pdf() can't be used for it. Fuzzy metal is specular but not a delta
+    pub fn is_delta(&self) -> bool {
+        match *self {
+            Material::Metallic { fuzziness, .. } => fuzziness == 0.0,
+            Material::Dielectric { .. } => true,
+            Material::Lambertian { .. }
+            | Material::OrenNayar { .. }
+            | Material::Emissive { .. } => false,
+        }
+    }
+
+    // Color of the surface, white for glass since it lets all light through and black
+    // for lights since they don't reflect any
+    pub fn albedo(&self) -> Vector3<f32> {
+        match *self {
+            Material::Lambertian { albedo }
+            | Material::OrenNayar { albedo, .. }
+            | Material::Metallic { albedo, .. } => albedo,
+            Material::Dielectric { .. } => Vector3::new(1.0, 1.0, 1.0),
+            Material::Emissive { .. } => Vector3::zero(),
+        }
+    }
+
+    // Light given off towards the ray by the surface it hit
+    pub fn emitted(&self, ray: Ray, record: HitRecord) -> Vector3<f32> {
+        match *self {
+            Material::Emissive { emission } if ray.direction().dot(record.normal) < 0.0 => emission,
+            _ => Vector3::zero(),
+        }
+    }
+
+    // Identifier derived from the material type and its parameters, so identical
+    // materials share an id
+    pub fn id(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+
+        let parameters = match *self {
+            Material::Lambertian { albedo } => [0.0, albedo.x, albedo.y, albedo.z, 0.0],
+            Material::OrenNayar { albedo, roughness } => {
+                [1.0, albedo.x, albedo.y, albedo.z, roughness]
+            }
+            Material::Metallic { albedo, fuzziness } => {
+                [2.0, albedo.x, albedo.y, albedo.z, fuzziness]
+            }
+            Material::Dielectric { refractive_index } => [3.0, refractive_index, 0.0, 0.0, 0.0],
+            Material::Emissive { emission } => [4.0, emission.x, emission.y, emission.z, 0.0],
+        };
+
+        for parameter in &parameters {
+            hasher.write_u32(parameter.to_bits());
+        }
+
+        hasher.finish()
+    }
+
     // Figure out what happens to a ray when it hits an object. Returns None if the ray was absorbed
-    pub fn scatter(&self, ray: Ray, record: HitRecord) -> Option<ScatteredRay> {
+    pub fn scatter(
+        &self,
+        ray: Ray,
+        record: HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatteredRay> {
         match *self {
-            // Add a random value in a unit sphere to the surface normal to get the ray, resulting in a perfectly
-            // diffuse material
-            Material::Lambertian { albedo } => {
-                // bounce direction for a diffuse material
-                let bounce_dir = record.position + record.normal + random_position_in_unit_sphere();
-                let bounced_ray = Ray::new(record.position, bounce_dir - record.position);
+            // Diffuse materials importance sample the cosine term of the rendering equation,
+            // so for a Lambertian surface the attenuation works out to exactly the albedo
+            Material::Lambertian { .. } | Material::OrenNayar { .. } => {
+                let normal = facing_normal(ray, record);
+                let direction = Onb::from_w(normal).to_world(random_cosine_direction(sampler));
+
+                let pdf = self.scattering_pdf(ray, record, direction);
+                if pdf <= 0.0 {
+                    return None;
+                }
+
+                let cosine = direction.dot(normal);
+                let bounced_ray = Ray::new(record.position, direction);
 
                 Some(ScatteredRay {
                     ray: bounced_ray,
-                    attenuation: albedo,
+                    attenuation: self.eval(ray, record, direction) * (cosine / pdf),
                 })
             }
             // Metallic materials just do a simple reflection, with an optional random fuzziness parameter
@@ -67,7 +174,13 @@ impl Material {
                 // Calculate reflected ray vector with some cross products
                 let reflected = reflect(ray.direction(), record.normal); //ray.direction() - 2.0 * (ray.direction().dot(record.normal)) * record.normal;
                                                                          // Add an fuziness parameter to the ray bounce direction
-                let fuzzy_ray = reflected + (random_position_in_unit_sphere() * fuzziness);
+                let fuzzy_ray = reflected + (random_position_in_unit_sphere(sampler) * fuzziness);
+                // Fuzz can push the reflection below the surface, where it would have to pass
+                // through the object. Treat it as absorbed instead
+                if fuzzy_ray.dot(facing_normal(ray, record)) <= 0.0 {
+                    return None;
+                }
+
                 // Create a new ray starting from the hit location and pointing toward the reflected ray dir
                 let bounced_ray = Ray::new(record.position, fuzzy_ray);
 
@@ -78,14 +191,12 @@ impl Material {
             }
             // Materials like glass or water
             Material::Dielectric { refractive_index } => {
-                let reflected = reflect(ray.direction(), record.normal);
-
-                // If > 0, the incoming ray is in the same dir as the normal, so for refractions
-                // the normal used is opposite the normal normal
+                // If > 0, the incoming ray is in the same dir as the normal, so it's leaving
+                // the material and for refractions the normal used is opposite the normal normal
                 let (normal_out, ni_over_nt) = if ray.direction().dot(record.normal) > 0.0 {
-                    (-record.normal, 1.0 / refractive_index)
+                    (-record.normal, refractive_index)
                 } else {
-                    (record.normal, refractive_index)
+                    (record.normal, 1.0 / refractive_index)
                 };
 
                 if let Some(refracted) = refract(ray.direction(), normal_out, ni_over_nt) {
@@ -107,21 +218,157 @@ impl Material {
                     })
                 }
             }
+            Material::Emissive { .. } => None,
+        }
+    }
+
+    // Evaluate the BRDF for light leaving along the incoming ray after arriving from `direction`.
+    // Delta materials have no closed form and always evaluate to zero
+    pub fn eval(&self, ray: Ray, record: HitRecord, direction: Vector3<f32>) -> Vector3<f32> {
+        let frame = Onb::from_w(facing_normal(ray, record));
+        let wo = frame.to_local(-ray.direction().normalize());
+        let wi = frame.to_local(direction.normalize());
+
+        // Diffuse surfaces don't transmit light
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Vector3::zero();
+        }
+
+        match *self {
+            Material::Lambertian { albedo } => albedo / f32::consts::PI,
+            Material::OrenNayar { albedo, roughness } => {
+                albedo * (oren_nayar(roughness, wo, wi) / f32::consts::PI)
+            }
+            // Whatever scatter() picks comes out with the albedo as its attenuation
+            Material::Metallic { albedo, fuzziness } if fuzziness > 0.0 => {
+                albedo * (self.scattering_pdf(ray, record, direction) / wi.z)
+            }
+            Material::Metallic { .. } | Material::Dielectric { .. } | Material::Emissive { .. } => {
+                Vector3::zero()
+            }
+        }
+    }
+
+    // Probability density, with respect to solid angle, of scatter() picking `direction`.
+    // Exposed so that other sampling strategies can be combined with multiple importance sampling
+    pub fn scattering_pdf(&self, ray: Ray, record: HitRecord, direction: Vector3<f32>) -> f32 {
+        match *self {
+            Material::Lambertian { .. } | Material::OrenNayar { .. } => {
+                let cosine = direction.normalize().dot(facing_normal(ray, record));
+                cosine.max(0.0) / f32::consts::PI
+            }
+            Material::Metallic { fuzziness, .. } if fuzziness > 0.0 => {
+                fuzzy_reflection_pdf(ray, record, direction, fuzziness)
+            }
+            Material::Metallic { .. } | Material::Dielectric { .. } | Material::Emissive { .. } => {
+                0.0
+            }
         }
     }
 }
 
-// See docs/Diffuse.PNG
-fn random_position_in_unit_sphere() -> Vector3<f32> {
-    let mut rng = rand::thread_rng();
+// Fuzzy reflections point from the hit towards a random position in a ball of radius
+// `fuzziness` around the mirror direction. The density of a direction is the volume of the
+// ball inside a thin cone around it, divided by the volume of the whole ball
+fn fuzzy_reflection_pdf(
+    ray: Ray,
+    record: HitRecord,
+    direction: Vector3<f32>,
+    fuzziness: f32,
+) -> f32 {
+    let direction = direction.normalize();
+
+    // Reflections below the surface are absorbed
+    if direction.dot(facing_normal(ray, record)) <= 0.0 {
+        return 0.0;
+    }
+
+    // Distances along the direction to where it enters and leaves the ball
+    let center = reflect(ray.direction(), record.normal);
+    let middle = direction.dot(center);
+    let offset = (center - direction * middle).magnitude2();
+    let half_chord2 = fuzziness * fuzziness - offset;
+    if half_chord2 <= 0.0 {
+        return 0.0;
+    }
+
+    let far = middle + half_chord2.sqrt();
+    let near = (middle - half_chord2.sqrt()).max(0.0);
+    if far <= 0.0 {
+        return 0.0;
+    }
 
-    let mut random_position = Vector3::<f32>::rand(&mut rng);
+    // A cone of solid angle dw holds r^2 dr dw of volume between r and r + dr
+    (far.powi(3) - near.powi(3)) / (4.0 * f32::consts::PI * fuzziness.powi(3))
+}
 
-    while random_position.distance2(Vector3::zero()) >= 1.0 {
-        random_position = Vector3::<f32>::rand(&mut rng);
+// Surface normal flipped to the side of the surface the ray arrived from
+fn facing_normal(ray: Ray, record: HitRecord) -> Vector3<f32> {
+    if ray.direction().dot(record.normal) > 0.0 {
+        -record.normal
+    } else {
+        record.normal
     }
+}
 
-    random_position
+// Cosine weighted direction on the hemisphere around +z. Uniformly samples a disk and
+// projects up onto the hemisphere (Malley's method)
+pub fn random_cosine_direction(sampler: &mut dyn Sampler) -> Vector3<f32> {
+    let r1 = sampler.next_f32();
+    let r2 = sampler.next_f32();
+
+    let phi = 2.0 * f32::consts::PI * r1;
+    let radius = r2.sqrt();
+
+    Vector3::new(
+        radius * phi.cos(),
+        radius * phi.sin(),
+        (1.0 - r2).max(0.0).sqrt(),
+    )
+}
+
+// Qualitative Oren-Nayar model, returns the factor the Lambertian BRDF is scaled by.
+// Both directions are in the local shading frame
+// http://www1.cs.columbia.edu/CAVE/publications/pdfs/Oren_SIGGRAPH94.pdf
+fn oren_nayar(roughness: f32, wo: Vector3<f32>, wi: Vector3<f32>) -> f32 {
+    let sigma2 = roughness * roughness;
+    let a = 1.0 - sigma2 / (2.0 * (sigma2 + 0.33));
+    let b = 0.45 * sigma2 / (sigma2 + 0.09);
+
+    let sin_theta_i = (1.0 - wi.z * wi.z).max(0.0).sqrt();
+    let sin_theta_o = (1.0 - wo.z * wo.z).max(0.0).sqrt();
+
+    // cos(phi_i - phi_o), expanded so no trig functions are needed
+    let max_cos = if sin_theta_i > 1e-4 && sin_theta_o > 1e-4 {
+        let cos_phi_diff = (wi.x * wo.x + wi.y * wo.y) / (sin_theta_i * sin_theta_o);
+        cos_phi_diff.max(0.0)
+    } else {
+        0.0
+    };
+
+    // alpha is the larger of the two polar angles and beta the smaller
+    let (sin_alpha, tan_beta) = if wi.z > wo.z {
+        (sin_theta_o, sin_theta_i / wi.z)
+    } else {
+        (sin_theta_i, sin_theta_o / wo.z)
+    };
+
+    a + b * max_cos * sin_alpha * tan_beta
+}
+
+// See docs/Diffuse.PNG
+// Always uses exactly three dimensions of the sampler, unlike rejection sampling, so the
+// dimensions of later bounces stay lined up
+fn random_position_in_unit_sphere(sampler: &mut dyn Sampler) -> Vector3<f32> {
+    // Uniform direction from the height and angle around the pole
+    let z = 1.0 - 2.0 * sampler.next_f32();
+    let phi = 2.0 * f32::consts::PI * sampler.next_f32();
+    let ring_radius = (1.0 - z * z).max(0.0).sqrt();
+
+    // Volume grows with the cube of the radius
+    let radius = sampler.next_f32().cbrt();
+
+    radius * Vector3::new(ring_radius * phi.cos(), ring_radius * phi.sin(), z)
 }
 
 // Returns a reflected ray from a normal
@@ -148,11 +395,395 @@ fn refract(
     }
 }
 
-// Reflectivity angle approximation by Christophe Schlick
-// https://en.wikipedia.org/wiki/Schlick%27s_approximation
-fn schlick(cosine: f32, refractive_index: f32) -> f32 {
-    let mut r0 = (1.0 - refractive_index) / (1.0 + refractive_index);
-    r0 = r0 * r0;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cgmath::Vector2;
+
+    use std::f64;
+
+    use sampler::{self, Sampler};
+
+    const SAMPLES: u32 = 100_000;
+
+    // Angles between the incoming ray and the normal, in degrees
+    const INCOMING_ANGLES: [f32; 4] = [0.0, 30.0, 60.0, 85.0];
 
-    r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
+    fn materials() -> Vec<Material> {
+        vec![
+            Material::new_lambertian(1.0, 1.0, 1.0),
+            Material::new_oren_nayar(1.0, 1.0, 1.0, 0.3),
+            Material::new_oren_nayar(1.0, 1.0, 1.0, 1.0),
+            Material::new_metallic(1.0, 1.0, 1.0, 0.0),
+            Material::new_metallic(1.0, 1.0, 1.0, 0.3),
+            Material::new_metallic(1.0, 1.0, 1.0, 1.0),
+            Material::new_dielectric(1.5),
+        ]
+    }
+
+    fn sampler() -> Box<dyn Sampler + Sync> {
+        sampler::create_sampler("random", 1, SAMPLES).unwrap()
+    }
+
+    // Hit on a surface facing +z at the origin
+    fn record(material: Material) -> HitRecord<'static> {
+        HitRecord {
+            t: 1.0,
+            position: Vector3::zero(),
+            normal: Vector3::unit_z(),
+            uv: Vector2::new(0.0, 0.0),
+            material,
+            object_id: 1,
+            medium: None,
+        }
+    }
+
+    // Ray arriving at the origin from `degrees` away from the normal
+    fn incoming(degrees: f32) -> Ray {
+        let theta = degrees.to_radians();
+        let direction = Vector3::new(theta.sin(), 0.0, -theta.cos());
+
+        Ray::new(-direction, direction)
+    }
+
+    // Directional albedo, the average fraction of light scattered in any direction, lost
+    // when the material absorbs the ray
+    fn directional_albedo(material: Material, ray: Ray) -> Vector3<f32> {
+        let mut sampler = sampler();
+        let mut total = Vector3::zero();
+
+        for index in 0..SAMPLES {
+            sampler.start_sample(0, 0, index);
+
+            if let Some(scattered) = material.scatter(ray, record(material), &mut *sampler) {
+                total += scattered.attenuation;
+            }
+        }
+
+        total / SAMPLES as f32
+    }
+
+    #[test]
+    fn white_furnace() {
+        for material in materials() {
+            for &angle in &INCOMING_ANGLES {
+                let albedo = directional_albedo(material, incoming(angle));
+
+                for channel in 0..3 {
+                    assert!(
+                        albedo[channel] <= 1.01,
+                        "{:?} gains energy at {} degrees, albedo is {:?}",
+                        material,
+                        angle,
+                        albedo
+                    );
+                }
+
+                // Nothing absorbs light for these, so they can't lose energy either
+                let lossless = match material {
+                    Material::Lambertian { .. } | Material::Dielectric { .. } => true,
+                    Material::Metallic { fuzziness, .. } => fuzziness == 0.0,
+                    Material::OrenNayar { .. } | Material::Emissive { .. } => false,
+                };
+                if lossless {
+                    assert!(
+                        albedo.x >= 0.99,
+                        "{:?} loses energy at {} degrees, albedo is {:?}",
+                        material,
+                        angle,
+                        albedo
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn albedo_is_an_upper_bound() {
+        let colors = [
+            Material::new_lambertian(0.8, 0.4, 0.1),
+            Material::new_oren_nayar(0.8, 0.4, 0.1, 0.5),
+            Material::new_metallic(0.8, 0.4, 0.1, 0.5),
+        ];
+
+        for &material in &colors {
+            for &angle in &INCOMING_ANGLES {
+                let albedo = directional_albedo(material, incoming(angle));
+
+                for channel in 0..3 {
+                    assert!(
+                        albedo[channel] <= material.albedo()[channel] * 1.01,
+                        "{:?} reflects more than its albedo at {} degrees: {:?}",
+                        material,
+                        angle,
+                        albedo
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn scattered_rays_leave_the_surface() {
+        for material in materials() {
+            // Glass transmits light through the surface
+            if let Material::Dielectric { .. } = material {
+                continue;
+            }
+
+            for &angle in &INCOMING_ANGLES {
+                let mut sampler = sampler();
+
+                for index in 0..SAMPLES / 10 {
+                    sampler.start_sample(0, 0, index);
+
+                    let scattered =
+                        material.scatter(incoming(angle), record(material), &mut *sampler);
+                    if let Some(scattered) = scattered {
+                        assert!(
+                            scattered.ray.direction().z > 0.0,
+                            "{:?} scattered into the surface at {} degrees",
+                            material,
+                            angle
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reciprocity() {
+        let diffuse = [
+            Material::new_lambertian(0.5, 0.5, 0.5),
+            Material::new_oren_nayar(0.5, 0.5, 0.5, 0.3),
+            Material::new_oren_nayar(0.5, 0.5, 0.5, 1.0),
+        ];
+
+        let mut sampler = sampler();
+        for &material in &diffuse {
+            for index in 0..1000 {
+                sampler.start_sample(0, 0, index);
+                let a = random_cosine_direction(&mut *sampler);
+                let b = random_cosine_direction(&mut *sampler);
+
+                // Light going from a to b, and from b to a
+                let forward = material.eval(Ray::new(a, -a), record(material), b);
+                let backward = material.eval(Ray::new(b, -b), record(material), a);
+
+                assert!(
+                    (forward - backward).magnitude() <= 1e-4 * forward.magnitude().max(1.0),
+                    "{:?} isn't reciprocal between {:?} and {:?}: {:?} and {:?}",
+                    material,
+                    a,
+                    b,
+                    forward,
+                    backward
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn sampled_directions_match_pdf() {
+        const THETA_BINS: usize = 10;
+        const PHI_BINS: usize = 20;
+        // Steps per axis when integrating the pdf over a bin, enough to follow the edge of
+        // the narrow lobe of fuzzy metal
+        const STEPS: usize = 16;
+
+        let sampled = [
+            Material::new_lambertian(0.5, 0.5, 0.5),
+            Material::new_oren_nayar(0.5, 0.5, 0.5, 1.0),
+            Material::new_metallic(0.5, 0.5, 0.5, 0.3),
+            Material::new_metallic(0.5, 0.5, 0.5, 1.0),
+            Material::new_metallic(0.5, 0.5, 0.5, 1.5),
+        ];
+
+        for &material in &sampled {
+            for &angle in &INCOMING_ANGLES {
+                let ray = incoming(angle);
+                let mut sampler = sampler();
+
+                // Bins cover the whole sphere, so directions below the surface are caught
+                let bin = |direction: Vector3<f32>| {
+                    let direction = direction.normalize();
+                    let theta = direction.z.clamp(-1.0, 1.0).acos();
+                    let phi = direction.y.atan2(direction.x) + f32::consts::PI;
+
+                    let theta_bin = (theta / f32::consts::PI * THETA_BINS as f32) as usize;
+                    let phi_bin = (phi / (2.0 * f32::consts::PI) * PHI_BINS as f32) as usize;
+                    theta_bin.min(THETA_BINS - 1) * PHI_BINS + phi_bin.min(PHI_BINS - 1)
+                };
+
+                let mut observed = vec![0.0; THETA_BINS * PHI_BINS];
+                for index in 0..SAMPLES {
+                    sampler.start_sample(0, 0, index);
+
+                    if let Some(scattered) = material.scatter(ray, record(material), &mut *sampler)
+                    {
+                        observed[bin(scattered.ray.direction())] += 1.0;
+                    }
+                }
+
+                // Integrate the pdf over the solid angle of each bin with the midpoint rule
+                let mut expected = vec![0.0; THETA_BINS * PHI_BINS];
+                let theta_step = f64::consts::PI / (THETA_BINS * STEPS) as f64;
+                let phi_step = 2.0 * f64::consts::PI / (PHI_BINS * STEPS) as f64;
+                for i in 0..THETA_BINS * STEPS {
+                    let theta = (i as f64 + 0.5) * theta_step;
+
+                    for j in 0..PHI_BINS * STEPS {
+                        let phi = (j as f64 + 0.5) * phi_step - f64::consts::PI;
+                        let direction = Vector3::new(
+                            (theta.sin() * phi.cos()) as f32,
+                            (theta.sin() * phi.sin()) as f32,
+                            theta.cos() as f32,
+                        );
+
+                        let pdf =
+                            f64::from(material.scattering_pdf(ray, record(material), direction));
+                        let index = (i / STEPS) * PHI_BINS + j / STEPS;
+                        expected[index] +=
+                            pdf * theta.sin() * theta_step * phi_step * f64::from(SAMPLES);
+                    }
+                }
+
+                assert_chi_square(
+                    &observed,
+                    &expected,
+                    &format!("{:?} at {} degrees", material, angle),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fuzz_is_uniform_in_unit_sphere() {
+        // Bins of equal volume, the cube of the radius, the height and the angle around
+        // the pole are all uniform for points uniform in the sphere
+        const BINS: usize = 6;
+
+        let mut sampler = sampler();
+        let mut observed = vec![0.0; BINS * BINS * BINS];
+
+        for index in 0..SAMPLES {
+            sampler.start_sample(0, 0, index);
+            let point = random_position_in_unit_sphere(&mut *sampler);
+
+            let radius = point.magnitude();
+            assert!(radius <= 1.0);
+            let z = if radius > 0.0 { point.z / radius } else { 0.0 };
+            let phi = point.y.atan2(point.x) + f32::consts::PI;
+
+            let bin = |value: f32| ((value * BINS as f32) as usize).min(BINS - 1);
+            let index = bin(radius.powi(3)) * BINS * BINS
+                + bin((z + 1.0) / 2.0) * BINS
+                + bin(phi / (2.0 * f32::consts::PI));
+            observed[index] += 1.0;
+        }
+
+        let expected = vec![f64::from(SAMPLES) / observed.len() as f64; observed.len()];
+        assert_chi_square(&observed, &expected, "fuzz offsets");
+    }
+
+    // Pearson's chi-square test of observed counts against expected ones. Bins expecting
+    // fewer than five samples are pooled so the test stays valid
+    fn assert_chi_square(observed: &[f64], expected: &[f64], name: &str) {
+        const MIN_EXPECTED: f64 = 5.0;
+        // Standard normal quantile of the significance level, one in a million so the
+        // test doesn't fail by chance
+        const Z: f64 = 4.753;
+
+        let mut statistic = 0.0;
+        let mut bins = 0;
+        let mut pooled_observed = 0.0;
+        let mut pooled_expected = 0.0;
+
+        for (&observed, &expected) in observed.iter().zip(expected) {
+            if expected < MIN_EXPECTED {
+                pooled_observed += observed;
+                pooled_expected += expected;
+            } else {
+                statistic += (observed - expected) * (observed - expected) / expected;
+                bins += 1;
+            }
+        }
+
+        if pooled_expected > 0.0 {
+            if pooled_expected >= MIN_EXPECTED {
+                statistic += (pooled_observed - pooled_expected)
+                    * (pooled_observed - pooled_expected)
+                    / pooled_expected;
+                bins += 1;
+            } else {
+                assert!(
+                    pooled_observed < MIN_EXPECTED * 10.0,
+                    "{}: {} samples landed where the pdf is about zero",
+                    name,
+                    pooled_observed
+                );
+            }
+        }
+
+        // Critical value from the Wilson-Hilferty approximation of the chi-square
+        // distribution with bins - 1 degrees of freedom
+        let dof = f64::from(bins - 1);
+        let critical = dof * (1.0 - 2.0 / (9.0 * dof) + Z * (2.0 / (9.0 * dof)).sqrt()).powi(3);
+
+        assert!(
+            statistic <= critical,
+            "{}: sampled directions don't match the pdf, chi-square is {:.1} with {} degrees of \
+             freedom, at most {:.1} expected",
+            name,
+            statistic,
+            dof,
+            critical
+        );
+    }
+
+    // Sine of the angle between transmitted rays and the normal, for every sample that
+    // went through the surface instead of being reflected
+    fn transmitted_sines(ray: Ray, normal: Vector3<f32>) -> Vec<f32> {
+        let material = Material::new_dielectric(1.5);
+        let mut hit = record(material);
+        hit.normal = normal;
+        let mut sampler = sampler();
+
+        (0..1000)
+            .filter_map(|index| {
+                sampler.start_sample(0, 0, index);
+                material.scatter(ray, hit, &mut *sampler)
+            })
+            .map(|scattered| scattered.ray.direction().normalize())
+            .filter(|direction| {
+                direction.dot(ray.direction()) > 0.0
+                    && direction.z.signum() == ray.direction().z.signum()
+            })
+            .map(|direction| (1.0 - direction.z * direction.z).sqrt())
+            .collect()
+    }
+
+    #[test]
+    fn refraction_follows_snells_law() {
+        // Entering glass the ray bends towards the normal
+        let entering = transmitted_sines(incoming(45.0), Vector3::unit_z());
+        assert!(!entering.is_empty());
+        for sine in entering {
+            let expected = 45f32.to_radians().sin() / 1.5;
+            assert!((sine - expected).abs() < 1e-4, "{} != {}", sine, expected);
+        }
+
+        // Leaving it bends away from the normal. The ray travels along the normal of the
+        // surface it leaves through
+        let theta = 20f32.to_radians();
+        let direction = Vector3::new(theta.sin(), 0.0, theta.cos());
+        let leaving = transmitted_sines(Ray::new(-direction, direction), Vector3::unit_z());
+        assert!(!leaving.is_empty());
+        for sine in leaving {
+            let expected = theta.sin() * 1.5;
+            assert!((sine - expected).abs() < 1e-4, "{} != {}", sine, expected);
+        }
+    }
 }
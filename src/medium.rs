@@ -0,0 +1,302 @@
+use cgmath::prelude::*;
+use cgmath::Vector3;
+
+use std::f32;
+use std::fmt::Debug;
+
+use aabb::Aabb;
+use onb::Onb;
+use ray::Ray;
+use sampler::Sampler;
+use voxel::VoxelGrid;
+
+/// Participating media are volumes that absorb and scatter light as it travels
+/// through them, rather than only at surfaces
+pub trait Medium: Sync + Debug {
+    /// Pick a distance along the ray for the light to interact with the medium, given
+    /// that the next surface is t_max away
+    fn sample(&self, ray: Ray, t_max: f32, sampler: &mut dyn Sampler) -> MediumSample;
+    /// Fraction of the light that makes it from the ray origin to t_max
+    fn transmittance(&self, ray: Ray, t_max: f32, sampler: &mut dyn Sampler) -> Vector3<f32>;
+    /// Distribution of directions that light scatters into
+    fn phase(&self) -> HenyeyGreenstein;
+}
+
+/// Result of sampling a free-flight distance through a medium
+pub struct MediumSample {
+    /// Distance along the ray that a scattering event happened, or None if the ray
+    /// made it through to the next surface
+    pub scatter_t: Option<f32>,
+    /// Factor to multiply the path throughput by
+    pub weight: Vector3<f32>,
+    /// Light emitted by the medium along the sampled segment, to be multiplied by the
+    /// path throughput from before this sample
+    pub emission: Vector3<f32>,
+}
+
+/// Phase function by Henyey and Greenstein. g ranges from -1.0 for complete back
+/// scattering to 1.0 for complete forward scattering, with 0.0 scattering equally
+/// in all directions
+#[derive(Debug, Clone, Copy)]
+pub struct HenyeyGreenstein {
+    g: f32,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(g: f32) -> HenyeyGreenstein {
+        assert!(g > -1.0 && g < 1.0);
+
+        HenyeyGreenstein { g }
+    }
+
+    /// Pick a new direction for light traveling along `incoming`. Directions are sampled
+    /// exactly proportional to the phase function so no extra weighting is needed
+    pub fn sample(&self, incoming: Vector3<f32>, sampler: &mut dyn Sampler) -> Vector3<f32> {
+        let r1 = sampler.next_f32();
+        let r2 = sampler.next_f32();
+
+        // Invert the CDF of the phase function to get the angle from the incoming direction
+        let cos_theta = if self.g.abs() < 1e-3 {
+            1.0 - 2.0 * r1
+        } else {
+            let g = self.g;
+            let term = (1.0 - g * g) / (1.0 - g + 2.0 * g * r1);
+            (1.0 + g * g - term * term) / (2.0 * g)
+        };
+        let cos_theta = cos_theta.clamp(-1.0, 1.0);
+
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * f32::consts::PI * r2;
+
+        Onb::from_w(incoming).to_world(Vector3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ))
+    }
+}
+
+/// Medium with the same density everywhere, like fog or murky water. Coefficients are
+/// per color channel and measured per unit of distance
+#[derive(Debug, Clone, Copy)]
+pub struct HomogeneousMedium {
+    absorption: Vector3<f32>,
+    scattering: Vector3<f32>,
+    phase: HenyeyGreenstein,
+}
+
+impl HomogeneousMedium {
+    pub fn new(
+        absorption: Vector3<f32>,
+        scattering: Vector3<f32>,
+        phase: HenyeyGreenstein,
+    ) -> HomogeneousMedium {
+        HomogeneousMedium {
+            absorption,
+            scattering,
+            phase,
+        }
+    }
+
+    fn extinction(&self) -> Vector3<f32> {
+        self.absorption + self.scattering
+    }
+}
+
+impl Medium for HomogeneousMedium {
+    fn sample(&self, ray: Ray, t_max: f32, sampler: &mut dyn Sampler) -> MediumSample {
+        let extinction = self.extinction();
+
+        // Every channel falls off at a different rate, so pick one to sample distances with
+        // and weight by the average pdf of all channels
+        let channel = ((sampler.next_f32() * 3.0) as usize).min(2);
+        let distance = -(1.0 - sampler.next_f32()).ln() / extinction[channel];
+
+        // Ray directions aren't normalized so convert between distance and t
+        let speed = ray.direction().magnitude();
+        let t = distance / speed;
+        let scattered = t < t_max;
+
+        let travelled = if scattered { distance } else { t_max * speed };
+        let transmittance = exp(-extinction * travelled);
+
+        // Pdf of stopping here if it scattered, or of making it all the way through if not
+        let density = if scattered {
+            extinction.mul_element_wise(transmittance)
+        } else {
+            transmittance
+        };
+        let pdf = (density.x + density.y + density.z) / 3.0;
+
+        if pdf <= 0.0 {
+            return MediumSample {
+                scatter_t: None,
+                weight: Vector3::zero(),
+                emission: Vector3::zero(),
+            };
+        }
+
+        if scattered {
+            MediumSample {
+                scatter_t: Some(t),
+                weight: transmittance.mul_element_wise(self.scattering) / pdf,
+                emission: Vector3::zero(),
+            }
+        } else {
+            MediumSample {
+                scatter_t: None,
+                weight: transmittance / pdf,
+                emission: Vector3::zero(),
+            }
+        }
+    }
+
+    fn transmittance(&self, ray: Ray, t_max: f32, _sampler: &mut dyn Sampler) -> Vector3<f32> {
+        exp(-self.extinction() * (t_max * ray.direction().magnitude()))
+    }
+
+    fn phase(&self) -> HenyeyGreenstein {
+        self.phase
+    }
+}
+
+/// Medium with a density that varies over a voxel grid, like clouds or explosions. The
+/// absorption and scattering coefficients are scaled by the density at each point.
+///
+/// Distances are sampled with delta tracking and transmittance estimated with ratio
+/// tracking, both of which use the largest density in the grid as a majorant
+#[derive(Debug, Clone)]
+pub struct GridMedium {
+    bounds: Aabb,
+    density: VoxelGrid,
+    absorption: f32,
+    scattering: f32,
+    // Optional grid of emitted light, scaled by the color
+    emission: Option<(VoxelGrid, Vector3<f32>)>,
+    phase: HenyeyGreenstein,
+    // Upper bound on the extinction coefficient anywhere in the grid
+    majorant: f32,
+}
+
+impl GridMedium {
+    /// The density grid is stretched to fill `bounds`
+    pub fn new(
+        bounds: Aabb,
+        density: VoxelGrid,
+        absorption: f32,
+        scattering: f32,
+        phase: HenyeyGreenstein,
+    ) -> GridMedium {
+        let majorant = density.max_value() * (absorption + scattering);
+
+        GridMedium {
+            bounds,
+            density,
+            absorption,
+            scattering,
+            emission: None,
+            phase,
+            majorant,
+        }
+    }
+
+    /// Make absorbing parts of the medium glow. Emitted light is `color` scaled by the
+    /// emission grid, which is stretched over the same bounds as the density grid
+    pub fn with_emission(mut self, emission: VoxelGrid, color: Vector3<f32>) -> GridMedium {
+        self.emission = Some((emission, color));
+        self
+    }
+
+    fn density_at(&self, position: Vector3<f32>) -> f32 {
+        self.density.lookup(self.bounds.relative_position(position))
+    }
+
+    // Distance to the next tentative collision, where collisions happen at the
+    // majorant rate. Measured in t so rays don't need normalized directions
+    fn step(&self, speed: f32, sampler: &mut dyn Sampler) -> f32 {
+        -(1.0 - sampler.next_f32()).ln() / (self.majorant * speed)
+    }
+}
+
+impl Medium for GridMedium {
+    // Delta tracking. Each tentative collision is real with probability density / majorant,
+    // otherwise it's a null collision and the ray keeps going
+    fn sample(&self, ray: Ray, t_max: f32, sampler: &mut dyn Sampler) -> MediumSample {
+        let mut sample = MediumSample {
+            scatter_t: None,
+            weight: Vector3::new(1.0, 1.0, 1.0),
+            emission: Vector3::zero(),
+        };
+
+        let (mut t, t_exit) = match self.bounds.intersect(ray, 0.0, t_max) {
+            Some(interval) if self.majorant > 0.0 => interval,
+            _ => return sample,
+        };
+
+        let speed = ray.direction().magnitude();
+        let extinction = self.absorption + self.scattering;
+
+        loop {
+            t += self.step(speed, sampler);
+            if t >= t_exit {
+                return sample;
+            }
+
+            let position = ray.point_at_distance(t);
+            let density = self.density_at(position);
+
+            // Every tentative collision is an unbiased estimate of the light emitted along
+            // the ray up to the first real collision
+            if let Some((ref grid, color)) = self.emission {
+                let glow = grid.lookup(self.bounds.relative_position(position));
+                sample.emission += color * (self.absorption * density * glow / self.majorant);
+            }
+
+            if sampler.next_f32() < density * extinction / self.majorant {
+                // Real collision. Scatter, with the chance of absorption folded into the weight
+                sample.scatter_t = Some(t);
+                sample.weight *= self.scattering / extinction;
+
+                return sample;
+            }
+        }
+    }
+
+    // Ratio tracking. Same tentative collisions as delta tracking, but instead of stopping
+    // at a real collision the transmittance is scaled by the chance of it being a null one
+    fn transmittance(&self, ray: Ray, t_max: f32, sampler: &mut dyn Sampler) -> Vector3<f32> {
+        let (mut t, t_exit) = match self.bounds.intersect(ray, 0.0, t_max) {
+            Some(interval) if self.majorant > 0.0 => interval,
+            _ => return Vector3::new(1.0, 1.0, 1.0),
+        };
+
+        let speed = ray.direction().magnitude();
+        let extinction = self.absorption + self.scattering;
+        let mut transmittance = 1.0;
+
+        loop {
+            t += self.step(speed, sampler);
+            if t >= t_exit {
+                break;
+            }
+
+            let density = self.density_at(ray.point_at_distance(t));
+            transmittance *= 1.0 - density * extinction / self.majorant;
+
+            if transmittance <= 0.0 {
+                return Vector3::zero();
+            }
+        }
+
+        Vector3::new(transmittance, transmittance, transmittance)
+    }
+
+    fn phase(&self) -> HenyeyGreenstein {
+        self.phase
+    }
+}
+
+// Beer-Lambert falloff of each channel
+fn exp(v: Vector3<f32>) -> Vector3<f32> {
+    v.map(|channel| channel.exp())
+}
@@ -0,0 +1,294 @@
+use cgmath::prelude::*;
+use cgmath::{Vector2, Vector3};
+
+use aabb::Aabb;
+use bvh::Bvh;
+use hit::{HitRecord, Hittable};
+use light::Light;
+use material::Material;
+use ray::Ray;
+use sampler::Sampler;
+use stats::{self, Counter};
+
+/// A triangle mesh made of a single material. Triangles are found with a bounding volume
+/// hierarchy, so meshes with millions of triangles are still quick to trace
+pub struct Mesh {
+    positions: Vec<Vector3<f32>>,
+    // One per vertex, used to smoothly shade over the triangles. Empty for flat shading
+    normals: Vec<Vector3<f32>>,
+    triangles: Vec<[u32; 3]>,
+    // Total area of the triangles up to and including each one, for picking triangles
+    // when the mesh is sampled as a light
+    areas: Vec<f32>,
+    material: Material,
+    bvh: Bvh,
+}
+
+impl Mesh {
+    /// Triangles index into the list of positions. Normals are per vertex, if there are
+    /// none the mesh is flat shaded. Triangles wind counter-clockwise when seen from the
+    /// side their flat normal points to
+    pub fn new(
+        positions: Vec<Vector3<f32>>,
+        normals: Option<Vec<Vector3<f32>>>,
+        triangles: Vec<[u32; 3]>,
+        material: Material,
+    ) -> Mesh {
+        let normals = normals.unwrap_or_default();
+        assert!(normals.is_empty() || normals.len() == positions.len());
+
+        let bounds: Vec<Aabb> = triangles
+            .iter()
+            .map(|triangle| {
+                triangle.iter().fold(Aabb::empty(), |bounds, &i| {
+                    bounds.grow(positions[i as usize])
+                })
+            })
+            .collect();
+
+        let areas = triangles
+            .iter()
+            .scan(0.0, |total, triangle| {
+                let [a, b, c] = triangle.map(|i| positions[i as usize]);
+                *total += 0.5 * (b - a).cross(c - a).magnitude();
+
+                Some(*total)
+            })
+            .collect();
+
+        Mesh {
+            bvh: Bvh::build(&bounds),
+            positions,
+            normals,
+            triangles,
+            areas,
+            material,
+        }
+    }
+
+    /// Smooth shaded mesh over a grid of `columns` by `rows` quads, with the corner at
+    /// (u, v) placed at `position(u, v)`. u and v go from 0.0 to 1.0
+    pub fn from_grid<F>(columns: u32, rows: u32, material: Material, position: F) -> Mesh
+    where
+        F: Fn(f32, f32) -> Vector3<f32>,
+    {
+        let mut positions = Vec::with_capacity(((columns + 1) * (rows + 1)) as usize);
+        for row in 0..=rows {
+            for column in 0..=columns {
+                positions.push(position(
+                    column as f32 / columns as f32,
+                    row as f32 / rows as f32,
+                ));
+            }
+        }
+
+        let mut triangles = Vec::with_capacity((2 * columns * rows) as usize);
+        for row in 0..rows {
+            for column in 0..columns {
+                let corner = row * (columns + 1) + column;
+                let above = corner + columns + 1;
+
+                triangles.push([corner, corner + 1, above + 1]);
+                triangles.push([corner, above + 1, above]);
+            }
+        }
+
+        let normals = vertex_normals(&positions, &triangles);
+        Mesh::new(positions, Some(normals), triangles, material)
+    }
+
+    /// Flat quad with corners a, b, c and d in counter-clockwise order
+    pub fn quad(
+        a: Vector3<f32>,
+        b: Vector3<f32>,
+        c: Vector3<f32>,
+        d: Vector3<f32>,
+        material: Material,
+    ) -> Mesh {
+        Mesh::new(vec![a, b, c, d], None, vec![[0, 1, 2], [0, 2, 3]], material)
+    }
+
+    /// Box with the given eight corners. The first four are the bottom face and the last
+    /// four the top face, both counter-clockwise when seen from above
+    pub fn cuboid(corners: [Vector3<f32>; 8], material: Material) -> Mesh {
+        let faces = [
+            // Bottom and top
+            [0, 3, 2, 1],
+            [4, 5, 6, 7],
+            // Sides
+            [0, 1, 5, 4],
+            [1, 2, 6, 5],
+            [2, 3, 7, 6],
+            [3, 0, 4, 7],
+        ];
+
+        let triangles = faces
+            .iter()
+            .flat_map(|f| vec![[f[0], f[1], f[2]], [f[0], f[2], f[3]]])
+            .collect();
+
+        Mesh::new(corners.to_vec(), None, triangles, material)
+    }
+
+    // Möller–Trumbore ray triangle intersection. Returns the distance along the ray and
+    // the barycentric coordinates of the second and third vertex
+    fn intersect(
+        &self,
+        triangle: usize,
+        ray: Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<(f32, f32, f32)> {
+        stats::count(Counter::IntersectionTests);
+
+        let [a, b, c] = self.corners(triangle);
+        let edge1 = b - a;
+        let edge2 = c - a;
+
+        let p = ray.direction().cross(edge2);
+        let determinant = edge1.dot(p);
+        if determinant.abs() < 1e-12 {
+            return None;
+        }
+
+        let inverse_determinant = 1.0 / determinant;
+        let to_origin = ray.origin() - a;
+
+        let u = to_origin.dot(p) * inverse_determinant;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = to_origin.cross(edge1);
+        let v = ray.direction().dot(q) * inverse_determinant;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = edge2.dot(q) * inverse_determinant;
+        if t > t_min && t < t_max {
+            Some((t, u, v))
+        } else {
+            None
+        }
+    }
+
+    fn corners(&self, triangle: usize) -> [Vector3<f32>; 3] {
+        let [a, b, c] = self.triangles[triangle];
+
+        [
+            self.positions[a as usize],
+            self.positions[b as usize],
+            self.positions[c as usize],
+        ]
+    }
+}
+
+impl Hittable for Mesh {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let mut closest = None;
+
+        self.bvh.closest_hit(ray, t_min, t_max, |triangle, t_max| {
+            let (t, u, v) = self.intersect(triangle, ray, t_min, t_max)?;
+            closest = Some((triangle, t, u, v));
+
+            Some(t)
+        });
+
+        let (triangle, t, u, v) = closest?;
+
+        let normal = if self.normals.is_empty() {
+            let [a, b, c] = self.corners(triangle);
+            (b - a).cross(c - a).normalize()
+        } else {
+            let [a, b, c] = self.triangles[triangle];
+            (self.normals[a as usize] * (1.0 - u - v)
+                + self.normals[b as usize] * u
+                + self.normals[c as usize] * v)
+                .normalize()
+        };
+
+        Some(HitRecord {
+            t,
+            position: ray.point_at_distance(t),
+            normal,
+            uv: Vector2::new(u, v),
+            material: self.material,
+            object_id: 0,
+            medium: None,
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bvh.bounds()
+    }
+}
+
+// Points are picked uniformly over the area of the mesh. Meant for flat lights like quads,
+// a direction is assumed to only hit the mesh once
+impl Light for Mesh {
+    fn sample_direction(&self, origin: Vector3<f32>, sampler: &mut dyn Sampler) -> Vector3<f32> {
+        let total = self.areas.last().cloned().unwrap_or(0.0);
+        let picked = sampler.next_f32() * total;
+        let triangle = self
+            .areas
+            .partition_point(|&area| area <= picked)
+            .min(self.triangles.len() - 1);
+
+        // Uniform point on the triangle from "Global Illumination Compendium" by Dutré
+        let (u, v) = sampler.next_2d();
+        let root = u.sqrt();
+        let [a, b, c] = self.corners(triangle);
+        let point = a * (1.0 - root) + b * (root * (1.0 - v)) + c * (root * v);
+
+        point - origin
+    }
+
+    fn pdf(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        let record = match self.hit(Ray::new(origin, direction), 0.0, f32::MAX) {
+            Some(record) => record,
+            None => return 0.0,
+        };
+
+        // Convert the density over the area to one over solid angle
+        let distance = record.t * direction.magnitude();
+        let cosine = record.normal.dot(direction.normalize()).abs();
+        let total = self.areas.last().cloned().unwrap_or(0.0);
+
+        if cosine > 0.0 && total > 0.0 {
+            distance * distance / (cosine * total)
+        } else {
+            0.0
+        }
+    }
+}
+
+// Normal of each vertex, averaged over the triangles around it weighted by their area
+fn vertex_normals(positions: &[Vector3<f32>], triangles: &[[u32; 3]]) -> Vec<Vector3<f32>> {
+    let mut normals = vec![Vector3::zero(); positions.len()];
+
+    for triangle in triangles {
+        let [a, b, c] = [
+            positions[triangle[0] as usize],
+            positions[triangle[1] as usize],
+            positions[triangle[2] as usize],
+        ];
+        // Twice the area in length
+        let normal = (b - a).cross(c - a);
+
+        for &vertex in triangle {
+            normals[vertex as usize] += normal;
+        }
+    }
+
+    normals
+        .into_iter()
+        .map(|normal| {
+            if normal.magnitude2() > 0.0 {
+                normal.normalize()
+            } else {
+                Vector3::unit_z()
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,39 @@
+use cgmath::prelude::*;
+use cgmath::Vector3;
+
+/// Orthonormal basis built around a single direction. Used as a local shading
+/// frame, where w is the surface normal and u and v are the tangents
+#[derive(Debug, Clone, Copy)]
+pub struct Onb {
+    u: Vector3<f32>,
+    v: Vector3<f32>,
+    w: Vector3<f32>,
+}
+
+impl Onb {
+    /// Build a basis whose w axis points along `normal`
+    pub fn from_w(normal: Vector3<f32>) -> Onb {
+        // Branchless construction from "Building an Orthonormal Basis, Revisited"
+        // by Duff et al. Avoids the singularity of the usual cross product trick
+        let w = normal.normalize();
+        let sign = 1.0f32.copysign(w.z);
+        let a = -1.0 / (sign + w.z);
+        let b = w.x * w.y * a;
+
+        let u = Vector3::new(1.0 + sign * w.x * w.x * a, sign * b, -sign * w.x);
+        let v = Vector3::new(b, sign + w.y * w.y * a, -w.y);
+
+        Onb { u, v, w }
+    }
+
+    /// Transform a direction from the local frame into world space
+    pub fn to_world(self, local: Vector3<f32>) -> Vector3<f32> {
+        local.x * self.u + local.y * self.v + local.z * self.w
+    }
+
+    /// Transform a direction from world space into the local frame, where
+    /// the z component is the cosine to the normal
+    pub fn to_local(self, world: Vector3<f32>) -> Vector3<f32> {
+        Vector3::new(world.dot(self.u), world.dot(self.v), world.dot(self.w))
+    }
+}
@@ -0,0 +1,85 @@
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use util;
+
+// Time between redraws of the progress bar
+const REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+
+// Number of characters inside of the progress bar
+const BAR_WIDTH: usize = 40;
+
+/// Progress bar with the elapsed time and an estimate of the time left, redrawn on
+/// stderr from a background thread until it's finished. Nothing is drawn when stderr
+/// isn't a terminal, so logs don't fill up with redraws
+pub struct ProgressBar {
+    finished: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ProgressBar {
+    /// Start drawing. `progress` is called on every redraw and returns how much of the
+    /// render is done, from 0.0 to 1.0
+    pub fn start<F>(progress: F) -> ProgressBar
+    where
+        F: Fn() -> f64 + Send + 'static,
+    {
+        let finished = Arc::new(AtomicBool::new(false));
+
+        let thread = if io::stderr().is_terminal() {
+            let finished = finished.clone();
+            let start_time = Instant::now();
+
+            Some(thread::spawn(move || {
+                while !finished.load(Ordering::SeqCst) {
+                    draw(progress(), start_time.elapsed());
+                    thread::sleep(REDRAW_INTERVAL);
+                }
+
+                draw(progress(), start_time.elapsed());
+                eprintln!();
+            }))
+        } else {
+            None
+        };
+
+        ProgressBar { finished, thread }
+    }
+
+    /// Draw the bar one last time and stop
+    pub fn finish(mut self) {
+        self.finished.store(true, Ordering::SeqCst);
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn draw(fraction: f64, elapsed: Duration) {
+    let fraction = fraction.clamp(0.0, 1.0);
+    let filled = (fraction * BAR_WIDTH as f64) as usize;
+
+    // Assume the rest of the render goes as fast as it has so far
+    let remaining = if fraction > 0.0 {
+        let seconds = elapsed.as_secs_f64() * (1.0 - fraction) / fraction;
+        util::format_seconds(seconds.round() as u64)
+    } else {
+        "--:--:--".to_string()
+    };
+
+    let mut stderr = io::stderr();
+    let _ = write!(
+        stderr,
+        "\r[{}{}] {:5.1}% elapsed {} ETA {}",
+        "=".repeat(filled),
+        " ".repeat(BAR_WIDTH - filled),
+        fraction * 100.0,
+        util::format_seconds(elapsed.as_secs()),
+        remaining
+    );
+    let _ = stderr.flush();
+}
@@ -0,0 +1,502 @@
+use cgmath::prelude::*;
+use cgmath::{Vector2, Vector3};
+
+use std::f32;
+
+use aabb::Aabb;
+use hit::{HitRecord, Hittable, Solid, Span};
+use material::Material;
+use ray::Ray;
+use stats::{self, Counter};
+
+/// Cylinder standing on its base at `center`, closed off by flat caps at both ends. With
+/// a `phi_max` below 360 degrees only that much of it is swept around the y-axis,
+/// starting from the x-axis towards the z-axis. The sweep has to be more than zero.
+/// Only a whole sweep is closed, so partial sweeps give the wrong spans to rays passing
+/// through the gap
+pub struct Cylinder {
+    center: Vector3<f32>,
+    radius: f32,
+    height: f32,
+    phi_max: f32,
+    material: Material,
+}
+
+impl Cylinder {
+    pub fn new(
+        center: Vector3<f32>,
+        radius: f32,
+        height: f32,
+        phi_max: f32,
+        material: Material,
+    ) -> Cylinder {
+        Cylinder {
+            center,
+            radius,
+            height,
+            phi_max: sweep(phi_max),
+            material,
+        }
+    }
+
+    // Every place the ray's line crosses the side or the caps, including the ones behind
+    // the origin of the ray
+    fn crossings(&self, ray: Ray) -> Crossings {
+        stats::count(Counter::IntersectionTests);
+
+        let origin = ray.origin() - self.center;
+        let direction = ray.direction();
+        let mut crossings = Crossings::new();
+
+        // Side, x^2 + z^2 = r^2
+        let a = direction.x * direction.x + direction.z * direction.z;
+        let b = 2.0 * (origin.x * direction.x + origin.z * direction.z);
+        let c = origin.x * origin.x + origin.z * origin.z - self.radius * self.radius;
+
+        for t in solve_quadratic(a, b, c) {
+            let point = origin + direction * t;
+            let phi = azimuth(point.x, point.z);
+
+            if point.y >= 0.0 && point.y <= self.height && phi <= self.phi_max {
+                crossings.add(
+                    t,
+                    Vector3::new(point.x, 0.0, point.z) / self.radius,
+                    Vector2::new(phi / self.phi_max, point.y / self.height),
+                );
+            }
+        }
+
+        // Caps
+        for &(y, normal_y) in &[(0.0, -1.0), (self.height, 1.0)] {
+            let t = (y - origin.y) / direction.y;
+            let point = origin + direction * t;
+            let distance = Vector2::new(point.x, point.z).magnitude();
+            let phi = azimuth(point.x, point.z);
+
+            if distance <= self.radius && phi <= self.phi_max {
+                crossings.add(
+                    t,
+                    Vector3::new(0.0, normal_y, 0.0),
+                    Vector2::new(phi / self.phi_max, distance / self.radius),
+                );
+            }
+        }
+
+        crossings
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.crossings(ray).first(ray, t_min, t_max, self.material)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::new(
+            self.center + Vector3::new(-self.radius, 0.0, -self.radius),
+            self.center + Vector3::new(self.radius, self.height, self.radius),
+        )
+    }
+}
+
+impl Solid for Cylinder {
+    fn spans(&self, ray: Ray) -> Vec<Span<'_>> {
+        self.crossings(ray).spans(ray, self.material)
+    }
+}
+
+/// Cone standing on its base at `center` with its tip `height` above, closed off by a
+/// flat cap at the base. Partial sweeps work the same as for a `Cylinder`
+pub struct Cone {
+    center: Vector3<f32>,
+    radius: f32,
+    height: f32,
+    phi_max: f32,
+    material: Material,
+}
+
+impl Cone {
+    pub fn new(
+        center: Vector3<f32>,
+        radius: f32,
+        height: f32,
+        phi_max: f32,
+        material: Material,
+    ) -> Cone {
+        Cone {
+            center,
+            radius,
+            height,
+            phi_max: sweep(phi_max),
+            material,
+        }
+    }
+
+    // Every place the ray's line crosses the side or the base, including the ones behind
+    // the origin of the ray
+    fn crossings(&self, ray: Ray) -> Crossings {
+        stats::count(Counter::IntersectionTests);
+
+        let origin = ray.origin() - self.center;
+        let direction = ray.direction();
+        let mut crossings = Crossings::new();
+
+        // Side, x^2 + z^2 = (k (h - y))^2 where k is how fast the radius shrinks
+        let k = self.radius / self.height;
+        let k2 = k * k;
+        let below_tip = self.height - origin.y;
+
+        let a =
+            direction.x * direction.x + direction.z * direction.z - k2 * direction.y * direction.y;
+        let b =
+            2.0 * (origin.x * direction.x + origin.z * direction.z + k2 * below_tip * direction.y);
+        let c = origin.x * origin.x + origin.z * origin.z - k2 * below_tip * below_tip;
+
+        for t in solve_quadratic(a, b, c) {
+            let point = origin + direction * t;
+            let phi = azimuth(point.x, point.z);
+
+            // The equation also holds for the mirrored cone above the tip
+            if point.y >= 0.0 && point.y <= self.height && phi <= self.phi_max {
+                // The side has no direction at the tip, so it points straight up there
+                let normal = if Vector2::new(point.x, point.z).magnitude2() > 0.0 {
+                    Vector3::new(point.x, k2 * (self.height - point.y), point.z).normalize()
+                } else {
+                    Vector3::unit_y()
+                };
+
+                crossings.add(
+                    t,
+                    normal,
+                    Vector2::new(phi / self.phi_max, point.y / self.height),
+                );
+            }
+        }
+
+        // Base
+        let t = -origin.y / direction.y;
+        let point = origin + direction * t;
+        let distance = Vector2::new(point.x, point.z).magnitude();
+        let phi = azimuth(point.x, point.z);
+
+        if distance <= self.radius && phi <= self.phi_max {
+            crossings.add(
+                t,
+                Vector3::new(0.0, -1.0, 0.0),
+                Vector2::new(phi / self.phi_max, distance / self.radius),
+            );
+        }
+
+        crossings
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.crossings(ray).first(ray, t_min, t_max, self.material)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::new(
+            self.center + Vector3::new(-self.radius, 0.0, -self.radius),
+            self.center + Vector3::new(self.radius, self.height, self.radius),
+        )
+    }
+}
+
+impl Solid for Cone {
+    fn spans(&self, ray: Ray) -> Vec<Span<'_>> {
+        self.crossings(ray).spans(ray, self.material)
+    }
+}
+
+/// Open bowl with its lowest point at `center`, curving up to `radius` at `height`.
+/// Partial sweeps work the same as for a `Cylinder`
+pub struct Paraboloid {
+    center: Vector3<f32>,
+    radius: f32,
+    height: f32,
+    phi_max: f32,
+    material: Material,
+}
+
+impl Paraboloid {
+    pub fn new(
+        center: Vector3<f32>,
+        radius: f32,
+        height: f32,
+        phi_max: f32,
+        material: Material,
+    ) -> Paraboloid {
+        Paraboloid {
+            center,
+            radius,
+            height,
+            phi_max: sweep(phi_max),
+            material,
+        }
+    }
+}
+
+impl Hittable for Paraboloid {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        stats::count(Counter::IntersectionTests);
+
+        let origin = ray.origin() - self.center;
+        let direction = ray.direction();
+        let mut crossings = Crossings::new();
+
+        // y = k (x^2 + z^2)
+        let k = self.height / (self.radius * self.radius);
+
+        let a = k * (direction.x * direction.x + direction.z * direction.z);
+        let b = 2.0 * k * (origin.x * direction.x + origin.z * direction.z) - direction.y;
+        let c = k * (origin.x * origin.x + origin.z * origin.z) - origin.y;
+
+        for t in solve_quadratic(a, b, c) {
+            let point = origin + direction * t;
+            let phi = azimuth(point.x, point.z);
+
+            if point.y <= self.height && phi <= self.phi_max {
+                // Gradient of k (x^2 + z^2) - y, pointing away from the inside of the bowl
+                crossings.add(
+                    t,
+                    Vector3::new(2.0 * k * point.x, -1.0, 2.0 * k * point.z).normalize(),
+                    Vector2::new(phi / self.phi_max, point.y / self.height),
+                );
+            }
+        }
+
+        crossings.first(ray, t_min, t_max, self.material)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::new(
+            self.center + Vector3::new(-self.radius, 0.0, -self.radius),
+            self.center + Vector3::new(self.radius, self.height, self.radius),
+        )
+    }
+}
+
+/// Angle around the y-axis from the x-axis towards the z-axis, in the range 0 to 2 pi
+pub fn azimuth(x: f32, z: f32) -> f32 {
+    let phi = z.atan2(x);
+    if phi < 0.0 {
+        phi + 2.0 * f32::consts::PI
+    } else {
+        phi
+    }
+}
+
+/// Convert a sweep in degrees to radians, limited to a full turn. Panics if the sweep
+/// isn't more than zero, there would be nothing left of the shape
+pub fn sweep(degrees: f32) -> f32 {
+    assert!(degrees > 0.0, "sweep of {} degrees isn't positive", degrees);
+    degrees.min(360.0).to_radians()
+}
+
+// Real roots of a t^2 + b t + c in increasing order. Computed so the two roots don't lose
+// precision when one is much smaller than the other
+fn solve_quadratic(a: f32, b: f32, c: f32) -> Vec<f32> {
+    if a == 0.0 {
+        return if b == 0.0 { Vec::new() } else { vec![-c / b] };
+    }
+
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return Vec::new();
+    }
+
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    let (t0, t1) = if q == 0.0 { (0.0, 0.0) } else { (q / a, c / q) };
+
+    if t0 < t1 {
+        vec![t0, t1]
+    } else {
+        vec![t1, t0]
+    }
+}
+
+/// Place where a ray's line crosses the surface of a shape, with the normal pointing out
+#[derive(Debug, Clone, Copy)]
+pub struct Crossing {
+    pub t: f32,
+    pub normal: Vector3<f32>,
+    pub uv: Vector2<f32>,
+}
+
+/// Every place a ray's line crosses the surface of a shape, in the shape's local space
+pub struct Crossings {
+    list: Vec<Crossing>,
+}
+
+impl Crossings {
+    pub fn new() -> Crossings {
+        Crossings { list: Vec::new() }
+    }
+
+    /// Crossings at an infinite or undefined distance, from rays parallel to a flat
+    /// surface, are left out
+    pub fn add(&mut self, t: f32, normal: Vector3<f32>, uv: Vector2<f32>) {
+        if t.is_finite() {
+            self.list.push(Crossing { t, normal, uv });
+        }
+    }
+
+    /// The closest crossing between `t_min` and `t_max`
+    pub fn first<'a>(
+        &self,
+        ray: Ray,
+        t_min: f32,
+        t_max: f32,
+        material: Material,
+    ) -> Option<HitRecord<'a>> {
+        self.list
+            .iter()
+            .filter(|crossing| crossing.t > t_min && crossing.t < t_max)
+            .min_by(|a, b| a.t.total_cmp(&b.t))
+            .map(|crossing| record(*crossing, ray, material))
+    }
+
+    /// Crossings in order along the ray, paired up into where the ray enters and leaves a
+    /// closed shape. A crossing left over from grazing an edge is dropped
+    pub fn spans<'a>(mut self, ray: Ray, material: Material) -> Vec<Span<'a>> {
+        self.list.sort_by(|a, b| a.t.total_cmp(&b.t));
+
+        self.list
+            .chunks_exact(2)
+            .map(|pair| Span {
+                enter: record(pair[0], ray, material),
+                exit: record(pair[1], ray, material),
+            })
+            .collect()
+    }
+}
+
+fn record<'a>(crossing: Crossing, ray: Ray, material: Material) -> HitRecord<'a> {
+    HitRecord {
+        t: crossing.t,
+        position: ray.point_at_distance(crossing.t),
+        normal: crossing.normal,
+        uv: crossing.uv,
+        material,
+        object_id: 0,
+        medium: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn material() -> Material {
+        Material::new_lambertian(0.5, 0.5, 0.5)
+    }
+
+    fn assert_vector_near(actual: Vector3<f32>, expected: Vector3<f32>) {
+        assert!(
+            (actual - expected).magnitude() < 1e-4,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn cylinder_side_and_caps() {
+        let cylinder = Cylinder::new(Vector3::zero(), 1.0, 2.0, 360.0, material());
+
+        let side = Ray::new(Vector3::new(-5.0, 1.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        let record = cylinder.hit(side, 0.001, f32::MAX).unwrap();
+        assert!((record.t - 4.0).abs() < 1e-4);
+        assert_vector_near(record.normal, Vector3::new(-1.0, 0.0, 0.0));
+        assert!((record.uv.y - 0.5).abs() < 1e-4);
+
+        let top = Ray::new(Vector3::new(0.5, 5.0, 0.0), Vector3::new(0.0, -1.0, 0.0));
+        let record = cylinder.hit(top, 0.001, f32::MAX).unwrap();
+        assert!((record.t - 3.0).abs() < 1e-4);
+        assert_vector_near(record.normal, Vector3::new(0.0, 1.0, 0.0));
+
+        // Inside the cylinder the ray leaves through the bottom cap
+        let inside = Ray::new(Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, -1.0, 0.0));
+        let record = cylinder.hit(inside, 0.001, f32::MAX).unwrap();
+        assert!((record.t - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn partial_sweep_leaves_a_gap() {
+        // Only the half with z >= 0 is there
+        let cylinder = Cylinder::new(Vector3::zero(), 1.0, 2.0, 180.0, material());
+
+        let into_gap = Ray::new(Vector3::new(0.0, 1.0, -5.0), Vector3::new(0.0, 0.0, 1.0));
+        let record = cylinder.hit(into_gap, 0.001, f32::MAX).unwrap();
+        // Passes through the missing half and hits the back of the other one
+        assert!((record.t - 6.0).abs() < 1e-4);
+        assert_vector_near(record.normal, Vector3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn cone_normal_and_tip() {
+        let cone = Cone::new(Vector3::zero(), 1.0, 1.0, 360.0, material());
+
+        let ray = Ray::new(Vector3::new(-5.0, 0.5, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        let record = cone.hit(ray, 0.001, f32::MAX).unwrap();
+        assert!((record.t - 4.5).abs() < 1e-4);
+        assert_vector_near(record.normal, Vector3::new(-1.0, 1.0, 0.0).normalize());
+
+        // The mirrored cone above the tip isn't part of it
+        let above = Ray::new(Vector3::new(-5.0, 1.5, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        assert!(cone.hit(above, 0.001, f32::MAX).is_none());
+    }
+
+    #[test]
+    fn cone_tip_faces_up() {
+        let cone = Cone::new(Vector3::zero(), 1.0, 1.0, 360.0, material());
+
+        let ray = Ray::new(Vector3::new(0.0, 5.0, 0.0), Vector3::new(0.0, -1.0, 0.0));
+        let record = cone.hit(ray, 0.001, f32::MAX).unwrap();
+        assert!((record.t - 4.0).abs() < 1e-4);
+        assert_vector_near(record.normal, Vector3::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn spans_cover_the_inside() {
+        let cylinder = Cylinder::new(Vector3::zero(), 1.0, 2.0, 360.0, material());
+        let cone = Cone::new(Vector3::zero(), 1.0, 1.0, 360.0, material());
+
+        // In through the side and out through the bottom cap
+        let ray = Ray::new(Vector3::new(-3.0, 2.5, 0.0), Vector3::new(1.0, -1.0, 0.0));
+        let spans = cylinder.spans(ray);
+        assert_eq!(spans.len(), 1);
+        assert!((spans[0].enter.t - 2.0).abs() < 1e-4);
+        assert!((spans[0].exit.t - 2.5).abs() < 1e-4);
+        assert_vector_near(spans[0].enter.normal, Vector3::new(-1.0, 0.0, 0.0));
+        assert_vector_near(spans[0].exit.normal, Vector3::new(0.0, -1.0, 0.0));
+
+        // Spans behind the origin of the ray are included
+        let ray = Ray::new(Vector3::new(0.0, 5.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+        let spans = cone.spans(ray);
+        assert_eq!(spans.len(), 1);
+        assert!((spans[0].enter.t - -5.0).abs() < 1e-4);
+        assert!((spans[0].exit.t - -4.0).abs() < 1e-4);
+        assert_vector_near(spans[0].enter.normal, Vector3::new(0.0, -1.0, 0.0));
+        assert_vector_near(spans[0].exit.normal, Vector3::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn empty_sweep_is_rejected() {
+        Cylinder::new(Vector3::zero(), 1.0, 2.0, 0.0, material());
+    }
+
+    #[test]
+    fn paraboloid_is_open_at_the_top() {
+        let bowl = Paraboloid::new(Vector3::zero(), 1.0, 1.0, 360.0, material());
+
+        // Straight down into the bowl hits the bottom from the inside
+        let ray = Ray::new(Vector3::new(0.5, 5.0, 0.0), Vector3::new(0.0, -1.0, 0.0));
+        let record = bowl.hit(ray, 0.001, f32::MAX).unwrap();
+        assert!((record.position.y - 0.25).abs() < 1e-4);
+        assert!(record.normal.y < 0.0);
+    }
+}
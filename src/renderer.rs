@@ -0,0 +1,143 @@
+use rayon::prelude::*;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use adaptive::AdaptiveSampling;
+use aov::AovSample;
+use camera::Camera;
+use film::{Film, FilmTile};
+use integrator::Integrator;
+use sampler::Sampler;
+use scene::Scene;
+use stats::{self, Counter};
+
+// Number of rows rendered together by a single thread
+const TILE_ROWS: u32 = 8;
+
+/// Everything needed to turn a scene into samples on a film
+pub struct Renderer {
+    camera: Camera,
+    scene: Scene,
+    integrator: Box<dyn Integrator>,
+    sampler: Box<dyn Sampler + Sync>,
+    sampling: AdaptiveSampling,
+    stop: Arc<AtomicBool>,
+}
+
+impl Renderer {
+    pub fn new(
+        camera: Camera,
+        scene: Scene,
+        integrator: Box<dyn Integrator>,
+        sampler: Box<dyn Sampler + Sync>,
+        sampling: AdaptiveSampling,
+    ) -> Self {
+        Renderer {
+            camera,
+            scene,
+            integrator,
+            sampler,
+            sampling,
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Flag that stops rendering as soon as possible once it's set, even from another
+    /// thread. Pixels keep every sample taken up to that point
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    /// Take more samples in every pixel of the film that still needs them, until each has
+    /// `sample_limit` samples in total, or until the renderer is stopped. Returns the
+    /// number of samples taken.
+    ///
+    /// Bands of rows are rendered in parallel into their own tiles, then merged in order.
+    /// Every pixel sums its samples in the same order, and sample indices carry on from
+    /// the samples already on the film, so the result is identical no matter how many
+    /// threads there are
+    pub fn render_pass(&self, film: &mut Film, sample_limit: u32) -> u64 {
+        let width = film.width();
+        let height = film.height();
+        let num_tiles = height.div_ceil(TILE_ROWS);
+
+        let tiles: Vec<(FilmTile, u64)> = (0..num_tiles)
+            .into_par_iter()
+            .map(|tile_index| {
+                let y_start = tile_index * TILE_ROWS;
+                let y_end = (y_start + TILE_ROWS).min(height);
+                let tile = film.tile(y_start, y_end);
+
+                self.render_tile(tile, width, y_start..y_end, sample_limit)
+            })
+            .collect();
+
+        let mut samples_taken = 0;
+        for (tile, tile_samples) in &tiles {
+            film.merge_tile(tile);
+            samples_taken += tile_samples;
+        }
+
+        samples_taken
+    }
+
+    fn render_tile(
+        &self,
+        mut tile: FilmTile,
+        width: u32,
+        rows: std::ops::Range<u32>,
+        sample_limit: u32,
+    ) -> (FilmTile, u64) {
+        let mut sampler = self.sampler.clone_box();
+        let mut samples_taken = 0;
+
+        for y in rows {
+            for x in 0..width {
+                let mut statistics = *tile.statistics(x, y);
+
+                while statistics.count() < sample_limit
+                    && !self.sampling.is_done(&statistics)
+                    && !self.is_stopped()
+                {
+                    // Every sample gets its own random numbers derived from the seed
+                    sampler.start_sample(x, y, statistics.count());
+
+                    // Create ray based on offsets from origin to point on plane z = -1
+                    // The actual ray is exactly the opposite of how it works in real life
+                    let (ray, film_position) = self.camera.get_ray(x, y, &mut *sampler);
+
+                    // Ray trace the ray and spread the color over the nearby pixels
+                    let color = if tile.has_aovs() {
+                        let mut aovs = AovSample::new();
+                        let color = self.integrator.radiance_with_aovs(
+                            ray,
+                            &self.scene,
+                            &mut *sampler,
+                            &mut aovs,
+                        );
+                        tile.add_aovs(x, y, &aovs);
+                        color
+                    } else {
+                        self.integrator.radiance(ray, &self.scene, &mut *sampler)
+                    };
+                    tile.add_sample(film_position, color);
+                    statistics.add(color);
+                    samples_taken += 1;
+                    stats::count(Counter::CameraRays);
+                }
+
+                *tile.statistics(x, y) = statistics;
+            }
+
+            // Keep the totals up to date for the progress bar
+            stats::flush();
+        }
+
+        (tile, samples_taken)
+    }
+}
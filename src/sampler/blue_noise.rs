@@ -0,0 +1,191 @@
+use std::sync::Arc;
+
+use sampler::sobol::SobolSequence;
+use sampler::{hash, Sequence, ONE_MINUS_EPSILON};
+
+// Width and height of the tiled blue noise mask
+const MASK_SIZE: usize = 64;
+
+// Standard deviation of the gaussian used to measure how clustered the mask is
+const MASK_SIGMA: f32 = 1.5;
+
+/// Every pixel uses the same scrambled Sobol points, shifted by the value of a blue noise
+/// mask at that pixel. Error then has no low frequencies across the screen, so it looks
+/// like fine grain instead of blotches at low sample counts
+#[derive(Clone)]
+pub struct BlueNoiseSequence {
+    seed: u64,
+    sobol: SobolSequence,
+    mask: Arc<BlueNoiseMask>,
+}
+
+impl BlueNoiseSequence {
+    pub fn new(seed: u64, mask: Arc<BlueNoiseMask>) -> BlueNoiseSequence {
+        BlueNoiseSequence {
+            seed,
+            sobol: SobolSequence::new(seed),
+            mask,
+        }
+    }
+}
+
+impl Sequence for BlueNoiseSequence {
+    fn sample(&self, pixel: [u32; 2], index: u32, dimension: u32) -> f32 {
+        let group_seed = hash(&[self.seed, u64::from(dimension / 4)]);
+        let value = self
+            .sobol
+            .scrambled(index, (dimension % 4) as usize, group_seed);
+
+        // Offset the mask differently in every dimension so dimensions aren't correlated
+        let offset = hash(&[self.seed, u64::from(dimension), 0x6D61_736B]);
+        let x = (pixel[0] as usize + offset as usize) % MASK_SIZE;
+        let y = (pixel[1] as usize + (offset >> 32) as usize) % MASK_SIZE;
+
+        let shifted = value + self.mask.values[y * MASK_SIZE + x];
+
+        (shifted - shifted.floor()).min(ONE_MINUS_EPSILON)
+    }
+}
+
+/// Tileable texture of values from 0.0 to 1.0 where similar values are spread out as far
+/// from each other as possible
+pub struct BlueNoiseMask {
+    values: Vec<f32>,
+}
+
+impl BlueNoiseMask {
+    /// Build the mask with the void and cluster algorithm by Robert Ulichney
+    pub fn generate(seed: u64) -> BlueNoiseMask {
+        let count = MASK_SIZE * MASK_SIZE;
+        let mut pattern = VoidAndCluster::new();
+
+        // Start from a random pattern with a tenth of the pixels set
+        let initial_count = count / 10;
+        let mut attempt = 0;
+        while pattern.set_count < initial_count {
+            let pixel = hash(&[seed, attempt]) as usize % count;
+            if !pattern.set[pixel] {
+                pattern.toggle(pixel);
+            }
+
+            attempt += 1;
+        }
+
+        // Move pixels from the tightest clusters into the largest voids until that stops
+        // changing anything
+        for _ in 0..count {
+            let cluster = pattern.tightest_cluster();
+            pattern.toggle(cluster);
+
+            let void = pattern.largest_void();
+            pattern.toggle(void);
+
+            if void == cluster {
+                break;
+            }
+        }
+
+        let mut ranks = vec![0; count];
+
+        // Pixels in the starting pattern are ranked by removing the tightest clusters first
+        let mut removal = pattern.clone();
+        for rank in (0..initial_count).rev() {
+            let cluster = removal.tightest_cluster();
+            removal.toggle(cluster);
+            ranks[cluster] = rank;
+        }
+
+        // Every other pixel is ranked by filling in the largest voids
+        for rank in initial_count..count {
+            let void = pattern.largest_void();
+            pattern.toggle(void);
+            ranks[void] = rank;
+        }
+
+        BlueNoiseMask {
+            values: ranks
+                .iter()
+                .map(|&rank| (rank as f32 + 0.5) / count as f32)
+                .collect(),
+        }
+    }
+}
+
+// Binary pattern along with how crowded each pixel is by the set pixels around it
+#[derive(Clone)]
+struct VoidAndCluster {
+    set: Vec<bool>,
+    set_count: usize,
+    energy: Vec<f32>,
+    // Gaussian falloff for every offset, wrapping around the edges of the mask
+    kernel: Arc<Vec<f32>>,
+}
+
+impl VoidAndCluster {
+    fn new() -> VoidAndCluster {
+        let count = MASK_SIZE * MASK_SIZE;
+        let mut kernel = Vec::with_capacity(count);
+
+        for dy in 0..MASK_SIZE {
+            for dx in 0..MASK_SIZE {
+                let x = dx.min(MASK_SIZE - dx) as f32;
+                let y = dy.min(MASK_SIZE - dy) as f32;
+
+                kernel.push((-(x * x + y * y) / (2.0 * MASK_SIGMA * MASK_SIGMA)).exp());
+            }
+        }
+
+        VoidAndCluster {
+            set: vec![false; count],
+            set_count: 0,
+            energy: vec![0.0; count],
+            kernel: Arc::new(kernel),
+        }
+    }
+
+    fn toggle(&mut self, pixel: usize) {
+        self.set[pixel] = !self.set[pixel];
+        let sign = if self.set[pixel] {
+            self.set_count += 1;
+            1.0
+        } else {
+            self.set_count -= 1;
+            -1.0
+        };
+
+        let (px, py) = (pixel % MASK_SIZE, pixel / MASK_SIZE);
+        for (other, energy) in self.energy.iter_mut().enumerate() {
+            let dx = (other % MASK_SIZE + MASK_SIZE - px) % MASK_SIZE;
+            let dy = (other / MASK_SIZE + MASK_SIZE - py) % MASK_SIZE;
+
+            *energy += sign * self.kernel[dy * MASK_SIZE + dx];
+        }
+    }
+
+    // Set pixel with the most set pixels around it
+    fn tightest_cluster(&self) -> usize {
+        self.extreme(true, |energy, best| energy > best)
+    }
+
+    // Unset pixel with the fewest set pixels around it
+    fn largest_void(&self) -> usize {
+        self.extreme(false, |energy, best| energy < best)
+    }
+
+    fn extreme<F: Fn(f32, f32) -> bool>(&self, set: bool, better: F) -> usize {
+        let mut best = None;
+
+        for (pixel, &energy) in self.energy.iter().enumerate() {
+            if self.set[pixel] != set {
+                continue;
+            }
+
+            match best {
+                Some((_, best_energy)) if !better(energy, best_energy) => {}
+                _ => best = Some((pixel, energy)),
+            }
+        }
+
+        best.map_or(0, |(pixel, _)| pixel)
+    }
+}
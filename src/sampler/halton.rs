@@ -0,0 +1,87 @@
+use std::sync::Arc;
+
+use sampler::{hash, permute, IndependentSequence, Sequence, ONE_MINUS_EPSILON};
+
+// Dimensions that get their own prime base, anything past this falls back to random numbers
+const MAX_DIMENSIONS: usize = 1024;
+
+/// The Halton sequence, where each dimension is the radical inverse of the sample index
+/// in a different prime base. Digits are randomly permuted for every pixel, dimension and
+/// digit position, which keeps the stratification of the sequence while breaking up the
+/// correlation between high dimensions and between neighbouring pixels
+#[derive(Clone)]
+pub struct HaltonSequence {
+    seed: u64,
+    primes: Arc<Vec<u32>>,
+}
+
+impl HaltonSequence {
+    pub fn new(seed: u64) -> HaltonSequence {
+        HaltonSequence {
+            seed,
+            primes: Arc::new(first_primes(MAX_DIMENSIONS)),
+        }
+    }
+}
+
+impl Sequence for HaltonSequence {
+    fn sample(&self, pixel: [u32; 2], index: u32, dimension: u32) -> f32 {
+        let base = match self.primes.get(dimension as usize) {
+            Some(&base) => base,
+            None => {
+                return IndependentSequence { seed: self.seed }.sample(pixel, index, dimension);
+            }
+        };
+
+        let seed = hash(&[
+            self.seed,
+            u64::from(pixel[0]),
+            u64::from(pixel[1]),
+            u64::from(dimension),
+        ]);
+
+        (scrambled_radical_inverse(base, index, seed) as f32).min(ONE_MINUS_EPSILON)
+    }
+}
+
+// Mirror the digits of index in the given base around the decimal point, permuting each
+// digit with a different permutation per digit position
+fn scrambled_radical_inverse(base: u32, mut index: u32, seed: u64) -> f64 {
+    let inverse_base = 1.0 / f64::from(base);
+    let mut factor = inverse_base;
+    let mut result = 0.0;
+    let mut position = 0;
+
+    // Keep going past the last digit of the index since permuted zeros aren't zero. Stop
+    // once digits are too small to change an f32
+    while factor > 1e-8 {
+        let pattern = hash(&[seed, position]) as u32;
+        let digit = permute(index % base, base, pattern);
+
+        result += f64::from(digit) * factor;
+        index /= base;
+        factor *= inverse_base;
+        position += 1;
+    }
+
+    result
+}
+
+fn first_primes(count: usize) -> Vec<u32> {
+    let mut primes: Vec<u32> = Vec::with_capacity(count);
+    let mut candidate = 2;
+
+    while primes.len() < count {
+        if primes
+            .iter()
+            .take_while(|&&prime| prime * prime <= candidate)
+            .all(|&prime| candidate % prime != 0)
+        {
+            primes.push(candidate);
+        }
+
+        candidate += 1;
+    }
+
+    primes
+}
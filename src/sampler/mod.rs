@@ -0,0 +1,226 @@
+mod blue_noise;
+mod halton;
+mod sobol;
+mod stratified;
+
+use std::sync::Arc;
+
+use self::blue_noise::{BlueNoiseMask, BlueNoiseSequence};
+use self::halton::HaltonSequence;
+use self::sobol::SobolSequence;
+use self::stratified::StratifiedSequence;
+
+/// Names of every sampler that can be created with `create_sampler`
+pub const SAMPLER_NAMES: &[&str] = &["random", "stratified", "halton", "sobol", "blue-noise"];
+
+// Largest f32 below 1.0
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+// Dimensions used by the camera: two for the position inside the pixel, then two for the
+// lens and one for time. The pinhole camera has no lens or shutter, but the dimensions are
+// still reserved so bounces line up whatever the camera does
+const CAMERA_DIMENSIONS: u32 = 5;
+
+// Dimensions reserved for each bounce of a path. Anything that needs more than this gets
+// independent random numbers instead
+const DIMENSIONS_PER_BOUNCE: u32 = 8;
+
+/// Source of random numbers for the samples of a pixel. Numbers are split into
+/// dimensions, with a fixed set of dimensions for the camera and for each bounce, so
+/// that samplers generating well distributed points can line up the same decisions
+/// across all the samples of a pixel.
+///
+/// Numbers only depend on the seed, pixel, sample index and dimension, so the image
+/// doesn't depend on which thread rendered it or in what order
+pub trait Sampler {
+    /// Start generating numbers for a new sample of a pixel
+    fn start_sample(&mut self, pixel_x: u32, pixel_y: u32, sample_index: u32);
+
+    /// Move on to the dimensions reserved for bounce `bounce` of the path
+    fn start_bounce(&mut self, bounce: u32);
+
+    /// Number in the range 0.0 to 1.0, excluding 1.0, for the next dimension
+    fn next_f32(&mut self) -> f32;
+
+    /// Pair of numbers for the next two dimensions
+    fn next_2d(&mut self) -> (f32, f32) {
+        let u = self.next_f32();
+        let v = self.next_f32();
+
+        (u, v)
+    }
+
+    /// Create a fresh copy of the sampler, sharing any precomputed tables
+    fn clone_box(&self) -> Box<dyn Sampler>;
+}
+
+/// Create a sampler by name. Returns None if no sampler has that name. Some samplers
+/// distribute points better when they know how many samples each pixel will get
+pub fn create_sampler(
+    name: &str,
+    seed: u64,
+    samples_per_pixel: u32,
+) -> Option<Box<dyn Sampler + Sync>> {
+    let sampler: Box<dyn Sampler + Sync> = match name {
+        "random" => Box::new(SequenceSampler::new(IndependentSequence { seed }, seed)),
+        "stratified" => Box::new(SequenceSampler::new(
+            StratifiedSequence::new(seed, samples_per_pixel),
+            seed,
+        )),
+        "halton" => Box::new(SequenceSampler::new(HaltonSequence::new(seed), seed)),
+        "sobol" => Box::new(SequenceSampler::new(SobolSequence::new(seed), seed)),
+        "blue-noise" => Box::new(SequenceSampler::new(
+            BlueNoiseSequence::new(seed, Arc::new(BlueNoiseMask::generate(seed))),
+            seed,
+        )),
+        _ => return None,
+    };
+
+    Some(sampler)
+}
+
+/// A sequence gives the value of any dimension of any sample of a pixel
+trait Sequence: Clone + Send + Sync + 'static {
+    fn sample(&self, pixel: [u32; 2], index: u32, dimension: u32) -> f32;
+}
+
+// Keeps track of the current dimension and hands out numbers from a sequence
+#[derive(Clone)]
+struct SequenceSampler<S: Sequence> {
+    sequence: S,
+    // Used for dimensions past the end of a bounce
+    overflow: IndependentSequence,
+    pixel: [u32; 2],
+    index: u32,
+    dimension: u32,
+    dimension_end: u32,
+}
+
+impl<S: Sequence> SequenceSampler<S> {
+    fn new(sequence: S, seed: u64) -> Self {
+        SequenceSampler {
+            sequence,
+            overflow: IndependentSequence {
+                seed: hash(&[seed, 0x6F76_6572_666C_6F77]),
+            },
+            pixel: [0, 0],
+            index: 0,
+            dimension: 0,
+            dimension_end: CAMERA_DIMENSIONS,
+        }
+    }
+}
+
+impl<S: Sequence> Sampler for SequenceSampler<S> {
+    fn start_sample(&mut self, pixel_x: u32, pixel_y: u32, sample_index: u32) {
+        self.pixel = [pixel_x, pixel_y];
+        self.index = sample_index;
+        self.dimension = 0;
+        self.dimension_end = CAMERA_DIMENSIONS;
+    }
+
+    fn start_bounce(&mut self, bounce: u32) {
+        self.dimension =
+            CAMERA_DIMENSIONS.saturating_add(bounce.saturating_mul(DIMENSIONS_PER_BOUNCE));
+        self.dimension_end = self.dimension.saturating_add(DIMENSIONS_PER_BOUNCE);
+    }
+
+    fn next_f32(&mut self) -> f32 {
+        let value = if self.dimension < self.dimension_end {
+            self.sequence.sample(self.pixel, self.index, self.dimension)
+        } else {
+            self.overflow.sample(self.pixel, self.index, self.dimension)
+        };
+
+        self.dimension = self.dimension.saturating_add(1);
+        value
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+// Independent uniform random numbers, hashed from the seed, pixel, sample and dimension
+#[derive(Clone)]
+struct IndependentSequence {
+    seed: u64,
+}
+
+impl Sequence for IndependentSequence {
+    fn sample(&self, pixel: [u32; 2], index: u32, dimension: u32) -> f32 {
+        let value = hash(&[
+            self.seed,
+            u64::from(pixel[0]),
+            u64::from(pixel[1]),
+            u64::from(index),
+            u64::from(dimension),
+        ]);
+
+        to_unit_float(value as u32)
+    }
+}
+
+// Combine values into a single well mixed 64 bit hash
+fn hash(values: &[u64]) -> u64 {
+    values
+        .iter()
+        .fold(0, |state, &value| splitmix64(state ^ value))
+}
+
+// Finalizer of the SplitMix64 generator, turns any 64 bit value into a well mixed one
+fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+
+    z ^ (z >> 31)
+}
+
+// Map 32 random bits to a float in the range 0.0 to 1.0, excluding 1.0. Only the top 24
+// bits are used since that's all an f32 can hold
+fn to_unit_float(bits: u32) -> f32 {
+    (bits >> 8) as f32 / (1u32 << 24) as f32
+}
+
+// Hash based permutation of the range 0 to length, a different one for every pattern.
+// From "Correlated Multi-Jittered Sampling" by Andrew Kensler
+fn permute(mut i: u32, length: u32, pattern: u32) -> u32 {
+    let p = pattern;
+
+    // Mask covering every bit that can be set in a value below length
+    let mut w = length - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+
+    // Cycle walk until the result lands inside of the range
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xE170_893D);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929_EB3F);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935_FA69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74DC_B303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9E50_1CC3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xC860_A3DF);
+        i &= w;
+        i ^= i >> 5;
+
+        if i < length {
+            break;
+        }
+    }
+
+    (i + p % length) % length
+}
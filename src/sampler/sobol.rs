@@ -0,0 +1,96 @@
+use sampler::{hash, to_unit_float, Sequence};
+
+// Primitive polynomials and initial direction numbers for the second to fourth dimensions,
+// as (degree, coefficients, initial numbers). From the tables by Joe and Kuo
+const DIRECTION_PARAMETERS: [(usize, u32, [u64; 3]); 3] =
+    [(1, 0, [1, 0, 0]), (2, 1, [1, 3, 0]), (3, 1, [1, 3, 1])];
+
+/// Owen scrambled Sobol points, using the hash based scrambling from "Practical Hash-based
+/// Owen Scrambling" by Brent Burley. Dimensions come in groups of four Sobol dimensions,
+/// and each group shuffles the sample order differently so that groups aren't correlated
+#[derive(Clone)]
+pub struct SobolSequence {
+    seed: u64,
+    matrices: [[u32; 32]; 4],
+}
+
+impl SobolSequence {
+    pub fn new(seed: u64) -> SobolSequence {
+        let mut matrices = [[0; 32]; 4];
+
+        // The first dimension is the van der Corput sequence
+        for (bit, column) in matrices[0].iter_mut().enumerate() {
+            *column = 1 << (31 - bit);
+        }
+
+        for (matrix, &(degree, coefficients, initial)) in
+            matrices[1..].iter_mut().zip(DIRECTION_PARAMETERS.iter())
+        {
+            // Direction numbers, indexed from 1 to match the usual recurrence
+            let mut m = [0u64; 33];
+
+            for k in 1..33 {
+                m[k] = if k <= degree {
+                    initial[k - 1]
+                } else {
+                    let mut value = m[k - degree] ^ (m[k - degree] << degree);
+                    for j in 1..degree {
+                        if (coefficients >> (degree - 1 - j)) & 1 == 1 {
+                            value ^= m[k - j] << j;
+                        }
+                    }
+
+                    value
+                };
+
+                matrix[k - 1] = (m[k] << (32 - k)) as u32;
+            }
+        }
+
+        SobolSequence { seed, matrices }
+    }
+
+    /// Value of one of the four Sobol dimensions, shuffled and scrambled by `seed`
+    pub fn scrambled(&self, index: u32, dimension: usize, seed: u64) -> f32 {
+        let shuffled_index = nested_uniform_scramble(index, seed as u32);
+
+        let mut value = 0;
+        for (bit, column) in self.matrices[dimension].iter().enumerate() {
+            if (shuffled_index >> bit) & 1 == 1 {
+                value ^= column;
+            }
+        }
+
+        let scramble_seed = hash(&[seed, dimension as u64]) as u32;
+        to_unit_float(nested_uniform_scramble(value, scramble_seed))
+    }
+}
+
+impl Sequence for SobolSequence {
+    fn sample(&self, pixel: [u32; 2], index: u32, dimension: u32) -> f32 {
+        let seed = hash(&[
+            self.seed,
+            u64::from(pixel[0]),
+            u64::from(pixel[1]),
+            u64::from(dimension / 4),
+        ]);
+
+        self.scrambled(index, (dimension % 4) as usize, seed)
+    }
+}
+
+// Owen scrambling, randomly flips every bit depending on the bits above it
+fn nested_uniform_scramble(value: u32, seed: u32) -> u32 {
+    laine_karras_permutation(value.reverse_bits(), seed).reverse_bits()
+}
+
+// Hash where every bit only depends on the bits below it
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6C50_B47C);
+    x ^= x.wrapping_mul(0xB82F_1E52);
+    x ^= x.wrapping_mul(0xC7AF_E638);
+    x ^= x.wrapping_mul(0x8D22_F6E6);
+
+    x
+}
@@ -0,0 +1,49 @@
+use sampler::{hash, permute, to_unit_float, Sequence, ONE_MINUS_EPSILON};
+
+/// Jittered stratified sampling. Each pair of dimensions is split into a grid with a
+/// cell for every sample of the pixel, and every sample lands at a random point inside
+/// a different cell. Cells are handed out in a random order per pixel and per pair of
+/// dimensions so that dimensions aren't correlated with each other
+#[derive(Clone)]
+pub struct StratifiedSequence {
+    seed: u64,
+    strata_per_axis: u32,
+}
+
+impl StratifiedSequence {
+    pub fn new(seed: u64, samples_per_pixel: u32) -> StratifiedSequence {
+        // Capped so the number of strata fits in a u32, even when the sample count is unbounded
+        let strata_per_axis = (f64::from(samples_per_pixel).sqrt().ceil() as u32).clamp(1, 4096);
+
+        StratifiedSequence {
+            seed,
+            strata_per_axis,
+        }
+    }
+}
+
+impl Sequence for StratifiedSequence {
+    fn sample(&self, pixel: [u32; 2], index: u32, dimension: u32) -> f32 {
+        let n = self.strata_per_axis;
+        let strata = n * n;
+
+        // Samples past the end of the grid start filling a new, differently shuffled grid
+        let key = hash(&[
+            self.seed,
+            u64::from(pixel[0]),
+            u64::from(pixel[1]),
+            u64::from(dimension / 2),
+            u64::from(index / strata),
+        ]);
+        let stratum = permute(index % strata, strata, key as u32);
+
+        let cell = if dimension & 1 == 0 {
+            stratum % n
+        } else {
+            stratum / n
+        };
+        let jitter = to_unit_float(hash(&[key, u64::from(index), u64::from(dimension)]) as u32);
+
+        ((cell as f32 + jitter) / n as f32).min(ONE_MINUS_EPSILON)
+    }
+}
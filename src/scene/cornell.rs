@@ -0,0 +1,119 @@
+use cgmath::prelude::*;
+use cgmath::Vector3;
+
+use std::sync::Arc;
+
+use camera::CameraSettings;
+use hittable_list::HittableList;
+use material::Material;
+use mesh::Mesh;
+use scene::{Background, Scene};
+
+/// The Cornell box, a closed white room with a red and a green wall, lit only by a square
+/// light in the ceiling, with a tall and a short box standing inside. Units are
+/// millimeters, matching the measurements of the real box
+pub fn cornell_box() -> Scene {
+    let red = Material::new_lambertian(0.65, 0.05, 0.05);
+    let white = Material::new_lambertian(0.73, 0.73, 0.73);
+    let green = Material::new_lambertian(0.12, 0.45, 0.15);
+    let light = Material::new_emissive(15.0, 15.0, 15.0);
+
+    let corner = |x: f32, y: f32, z: f32| Vector3::new(x, y, z);
+
+    let mut world = HittableList::new();
+
+    // Red on the left and green on the right, seen from the camera
+    world.insert(Box::new(Mesh::quad(
+        corner(555.0, 0.0, 0.0),
+        corner(555.0, 555.0, 0.0),
+        corner(555.0, 555.0, 555.0),
+        corner(555.0, 0.0, 555.0),
+        red,
+    )));
+    world.insert(Box::new(Mesh::quad(
+        corner(0.0, 0.0, 0.0),
+        corner(0.0, 0.0, 555.0),
+        corner(0.0, 555.0, 555.0),
+        corner(0.0, 555.0, 0.0),
+        green,
+    )));
+    // Floor, ceiling and back wall
+    world.insert(Box::new(Mesh::quad(
+        corner(0.0, 0.0, 0.0),
+        corner(555.0, 0.0, 0.0),
+        corner(555.0, 0.0, 555.0),
+        corner(0.0, 0.0, 555.0),
+        white,
+    )));
+    world.insert(Box::new(Mesh::quad(
+        corner(0.0, 555.0, 0.0),
+        corner(0.0, 555.0, 555.0),
+        corner(555.0, 555.0, 555.0),
+        corner(555.0, 555.0, 0.0),
+        white,
+    )));
+    world.insert(Box::new(Mesh::quad(
+        corner(0.0, 0.0, 555.0),
+        corner(555.0, 0.0, 555.0),
+        corner(555.0, 555.0, 555.0),
+        corner(0.0, 555.0, 555.0),
+        white,
+    )));
+
+    // Just below the ceiling, facing down
+    let ceiling_light = Arc::new(Mesh::quad(
+        corner(213.0, 554.0, 227.0),
+        corner(343.0, 554.0, 227.0),
+        corner(343.0, 554.0, 332.0),
+        corner(213.0, 554.0, 332.0),
+        light,
+    ));
+    world.insert(Box::new(ceiling_light.clone()));
+
+    world.insert(Box::new(rotated_box(
+        Vector3::new(165.0, 330.0, 165.0),
+        15.0,
+        Vector3::new(265.0, 0.0, 295.0),
+        white,
+    )));
+    world.insert(Box::new(rotated_box(
+        Vector3::new(165.0, 165.0, 165.0),
+        -18.0,
+        Vector3::new(130.0, 0.0, 65.0),
+        white,
+    )));
+
+    Scene {
+        world: Box::new(world.into_bvh()),
+        camera: CameraSettings {
+            position: Vector3::new(278.0, 278.0, -800.0),
+            target: Vector3::new(278.0, 278.0, 0.0),
+            up: Vector3::unit_y(),
+            vertical_fov: 40.0,
+        },
+        background: Background::Uniform(Vector3::zero()),
+        lights: vec![ceiling_light],
+    }
+}
+
+// Box of the given size with a corner at the origin, rotated around the y-axis by
+// `degrees` and then moved by `offset`
+fn rotated_box(size: Vector3<f32>, degrees: f32, offset: Vector3<f32>, material: Material) -> Mesh {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let place =
+        |x: f32, y: f32, z: f32| Vector3::new(cos * x + sin * z, y, -sin * x + cos * z) + offset;
+
+    Mesh::cuboid(
+        [
+            place(0.0, 0.0, 0.0),
+            place(0.0, 0.0, size.z),
+            place(size.x, 0.0, size.z),
+            place(size.x, 0.0, 0.0),
+            place(0.0, size.y, 0.0),
+            place(0.0, size.y, size.z),
+            place(size.x, size.y, size.z),
+            place(size.x, size.y, 0.0),
+        ],
+        material,
+    )
+}
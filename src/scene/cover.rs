@@ -0,0 +1,91 @@
+use cgmath::prelude::*;
+use cgmath::Vector3;
+
+use camera::CameraSettings;
+use hittable_list::HittableList;
+use material::Material;
+use scene::{random_material, Background, Rng, Scene};
+use sphere::Sphere;
+
+// Rows and columns of small spheres in the original scene
+const CLASSIC_GRID: i32 = 22;
+
+/// The scene on the cover of Ray Tracing in One Weekend. A grid of small spheres with
+/// random materials scattered around three big ones, all on a huge ground sphere. Without
+/// a count the grid is the original 22 by 22, otherwise it grows to fit `count` spheres
+pub fn cover_scene(seed: u64, count: Option<usize>) -> Scene {
+    // A few places next to the big metal sphere are left empty, an extra row and column
+    // makes up for them
+    let grid = match count {
+        Some(count) => (count as f64).sqrt().ceil() as i32 + 1,
+        None => CLASSIC_GRID,
+    };
+    let count = count.unwrap_or(usize::MAX);
+
+    // Big enough that the ground stays under every sphere however far the grid reaches
+    let ground_radius = (grid as f32).max(1000.0);
+    let ground_height =
+        |x: f32, z: f32| (ground_radius * ground_radius - x * x - z * z).sqrt() - ground_radius;
+
+    let mut rng = Rng::new(seed);
+    let mut world = HittableList::new();
+
+    world.insert(Box::new(Sphere::new(
+        Vector3::new(0.0, -ground_radius, 0.0),
+        ground_radius,
+        Material::new_lambertian(0.5, 0.5, 0.5),
+    )));
+
+    let mut placed = 0;
+    'grid: for a in -grid / 2..grid - grid / 2 {
+        for b in -grid / 2..grid - grid / 2 {
+            if placed == count {
+                break 'grid;
+            }
+
+            let x = a as f32 + 0.9 * rng.next_f32();
+            let z = b as f32 + 0.9 * rng.next_f32();
+            let center = Vector3::new(x, ground_height(x, z) + 0.2, z);
+
+            // Keep clear of the big metal sphere
+            if (center - Vector3::new(4.0, 0.2, 0.0)).magnitude() <= 0.9 {
+                continue;
+            }
+
+            world.insert(Box::new(Sphere::new(
+                center,
+                0.2,
+                random_material(&mut rng),
+            )));
+            placed += 1;
+        }
+    }
+
+    world.insert(Box::new(Sphere::new(
+        Vector3::new(0.0, 1.0, 0.0),
+        1.0,
+        Material::new_dielectric(1.5),
+    )));
+    world.insert(Box::new(Sphere::new(
+        Vector3::new(-4.0, 1.0, 0.0),
+        1.0,
+        Material::new_lambertian(0.4, 0.2, 0.1),
+    )));
+    world.insert(Box::new(Sphere::new(
+        Vector3::new(4.0, 1.0, 0.0),
+        1.0,
+        Material::new_metallic(0.7, 0.6, 0.5, 0.0),
+    )));
+
+    Scene {
+        world: Box::new(world.into_bvh()),
+        camera: CameraSettings {
+            position: Vector3::new(13.0, 2.0, 3.0),
+            target: Vector3::zero(),
+            up: Vector3::unit_y(),
+            vertical_fov: 20.0,
+        },
+        background: Background::Sky,
+        lights: Vec::new(),
+    }
+}
@@ -0,0 +1,74 @@
+use cgmath::Vector3;
+
+use camera::CameraSettings;
+use csg::Csg;
+use cuboid::Cuboid;
+use hittable_list::HittableList;
+use material::Material;
+use scene::{Background, Scene};
+use sphere::Sphere;
+
+/// Shapes built with constructive solid geometry. A glass lens made from the overlap of
+/// two spheres, a thin walled glass ball, a block with a ball carved out of it and two
+/// glass balls merged into one piece with no surface left between them
+pub fn csg_scene() -> Scene {
+    let glass = Material::new_dielectric(1.5);
+    let mut world = HittableList::new();
+
+    world.insert(Box::new(Sphere::new(
+        Vector3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Material::new_lambertian(0.5, 0.5, 0.5),
+    )));
+
+    // Standing on its edge, facing the camera
+    let lens_center = Vector3::new(-2.6, 1.1, 0.0);
+    world.insert(Box::new(Csg::intersection(
+        Box::new(Sphere::new(
+            lens_center + Vector3::new(0.0, 0.0, 1.7),
+            2.0,
+            glass,
+        )),
+        Box::new(Sphere::new(
+            lens_center - Vector3::new(0.0, 0.0, 1.7),
+            2.0,
+            glass,
+        )),
+    )));
+
+    let ball_center = Vector3::new(0.0, 1.0, 0.0);
+    world.insert(Box::new(Csg::difference(
+        Box::new(Sphere::new(ball_center, 1.0, glass)),
+        Box::new(Sphere::new(ball_center, 0.9, glass)),
+    )));
+
+    world.insert(Box::new(Csg::difference(
+        Box::new(Cuboid::new(
+            Vector3::new(1.6, 0.0, -0.9),
+            Vector3::new(3.4, 1.8, 0.9),
+            Material::new_lambertian(0.8, 0.3, 0.2),
+        )),
+        Box::new(Sphere::new(
+            Vector3::new(2.5, 1.8, 0.9),
+            1.1,
+            Material::new_metallic(0.9, 0.9, 0.9, 0.05),
+        )),
+    )));
+
+    world.insert(Box::new(Csg::union(
+        Box::new(Sphere::new(Vector3::new(-1.8, 0.6, -3.0), 0.6, glass)),
+        Box::new(Sphere::new(Vector3::new(-1.0, 0.6, -3.0), 0.6, glass)),
+    )));
+
+    Scene {
+        world: Box::new(world.into_bvh()),
+        camera: CameraSettings {
+            position: Vector3::new(0.0, 3.0, 7.5),
+            target: Vector3::new(0.0, 0.9, 0.0),
+            up: Vector3::unit_y(),
+            vertical_fov: 40.0,
+        },
+        background: Background::Sky,
+        lights: Vec::new(),
+    }
+}
@@ -0,0 +1,68 @@
+use cgmath::prelude::*;
+use cgmath::Vector3;
+
+use camera::CameraSettings;
+use hittable_list::HittableList;
+use material::Material;
+use scene::{Background, Scene};
+use sdf::{Sdf, SdfNode};
+use sphere::Sphere;
+
+/// Shapes modelled with signed distance fields. A sphere blended into a ring, a glass
+/// block with a ball scooped out of it, a rippled ball and a colonnade of capsules
+pub fn distance_field_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    world.insert(Box::new(Sphere::new(
+        Vector3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Material::new_lambertian(0.5, 0.5, 0.5),
+    )));
+
+    let blob = SdfNode::torus(0.8, 0.2)
+        .smooth_union(
+            SdfNode::sphere(0.45).translate(Vector3::new(0.0, 0.45, 0.0)),
+            0.4,
+        )
+        .translate(Vector3::new(-2.3, 0.25, 0.0));
+    world.insert(Box::new(Sdf::new(
+        blob,
+        Material::new_metallic(0.9, 0.7, 0.3, 0.1),
+    )));
+
+    let scooped = SdfNode::cuboid(Vector3::new(0.7, 0.7, 0.7))
+        .smooth_subtract(
+            SdfNode::sphere(0.8).translate(Vector3::new(0.0, 0.7, 0.0)),
+            0.15,
+        )
+        .translate(Vector3::new(0.0, 0.7, 0.0));
+    world.insert(Box::new(Sdf::new(scooped, Material::new_dielectric(1.5))));
+
+    let rippled = SdfNode::sphere(0.7)
+        .displace(0.04, 12.0)
+        .translate(Vector3::new(2.3, 0.75, 0.0));
+    world.insert(Box::new(Sdf::new(
+        rippled,
+        Material::new_oren_nayar(0.3, 0.5, 0.8, 0.5),
+    )));
+
+    let columns = SdfNode::capsule(Vector3::zero(), Vector3::new(0.0, 1.6, 0.0), 0.15)
+        .repeat(Vector3::new(0.9, 0.0, 0.0), [4, 0, 0])
+        .translate(Vector3::new(0.0, 0.15, -2.5));
+    world.insert(Box::new(Sdf::new(
+        columns,
+        Material::new_lambertian(0.8, 0.8, 0.75),
+    )));
+
+    Scene {
+        world: Box::new(world.into_bvh()),
+        camera: CameraSettings {
+            position: Vector3::new(0.0, 2.2, 6.5),
+            target: Vector3::new(0.0, 0.6, 0.0),
+            up: Vector3::unit_y(),
+            vertical_fov: 40.0,
+        },
+        background: Background::Sky,
+        lights: Vec::new(),
+    }
+}
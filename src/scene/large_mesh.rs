@@ -0,0 +1,58 @@
+use cgmath::Vector3;
+
+use std::f32;
+
+use camera::CameraSettings;
+use hittable_list::HittableList;
+use material::Material;
+use mesh::Mesh;
+use scene::{Background, Scene};
+use sphere::Sphere;
+
+// Quads around and from pole to pole of the blob, for about a quarter million triangles
+const COLUMNS: u32 = 512;
+const ROWS: u32 = 256;
+
+/// A lumpy blob made of a quarter million triangles, standing on the ground under the sky
+pub fn large_mesh_scene() -> Scene {
+    let center = Vector3::new(0.0, 1.0, 0.0);
+
+    let blob = Mesh::from_grid(
+        COLUMNS,
+        ROWS,
+        Material::new_oren_nayar(0.7, 0.35, 0.2, 0.3),
+        |u, v| {
+            let phi = 2.0 * f32::consts::PI * u;
+            let theta = f32::consts::PI * v;
+            let radius = 1.0 + 0.08 * (12.0 * phi).sin() * (9.0 * theta).sin();
+
+            center
+                + radius
+                    * Vector3::new(
+                        theta.sin() * phi.cos(),
+                        -theta.cos(),
+                        theta.sin() * phi.sin(),
+                    )
+        },
+    );
+
+    let mut world = HittableList::new();
+    world.insert(Box::new(blob));
+    world.insert(Box::new(Sphere::new(
+        Vector3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Material::new_lambertian(0.5, 0.5, 0.5),
+    )));
+
+    Scene {
+        world: Box::new(world.into_bvh()),
+        camera: CameraSettings {
+            position: Vector3::new(0.0, 1.6, 4.5),
+            target: center,
+            up: Vector3::unit_y(),
+            vertical_fov: 40.0,
+        },
+        background: Background::Sky,
+        lights: Vec::new(),
+    }
+}
@@ -0,0 +1,175 @@
+use cgmath::prelude::*;
+use cgmath::Vector3;
+
+use std::sync::Arc;
+
+use camera::CameraSettings;
+use hit::Hittable;
+use light::Light;
+use material::Material;
+use ray::Ray;
+
+mod cornell;
+mod cover;
+mod csg;
+mod distance_fields;
+mod large_mesh;
+mod oren_nayar;
+mod quadrics;
+mod sphere_cloud;
+mod spheres;
+mod terrain;
+mod validation;
+mod volumes;
+
+pub use self::cornell::cornell_box;
+pub use self::cover::cover_scene;
+pub use self::csg::csg_scene;
+pub use self::distance_fields::distance_field_scene;
+pub use self::large_mesh::large_mesh_scene;
+pub use self::oren_nayar::oren_nayar_scene;
+pub use self::quadrics::quadrics_scene;
+pub use self::sphere_cloud::sphere_cloud;
+pub use self::spheres::load_scene;
+pub use self::terrain::terrain_scene;
+pub use self::validation::{furnace, glass_caustic, veach_mis};
+pub use self::volumes::volume_scene;
+
+/// Names of every scene that can be created with `create_scene`
+pub const SCENE_NAMES: &[&str] = &[
+    "spheres",
+    "volumes",
+    "cover",
+    "sphere-cloud",
+    "cornell",
+    "mesh",
+    "furnace",
+    "caustic",
+    "veach-mis",
+    "csg",
+    "sdf",
+    "quadrics",
+    "terrain",
+    "oren-nayar",
+];
+
+/// Names of the scenes that are generated from `SceneOptions`
+pub const GENERATED_SCENES: &[&str] = &["cover", "sphere-cloud"];
+
+/// Parameters of the generated scenes. The command line rejects them for any other
+/// scene, `create_scene` leaves them unused
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SceneOptions {
+    /// The same seed always generates the same scene
+    pub seed: u64,
+    /// Number of objects to generate, each scene has its own default
+    pub count: Option<usize>,
+}
+
+/// Everything that gets rendered: the objects, where they're seen from and the light
+/// arriving from behind them
+pub struct Scene {
+    pub world: Box<dyn Hittable + Sync>,
+    pub camera: CameraSettings,
+    pub background: Background,
+    /// Objects of the world that give off light and can be sampled directly
+    pub lights: Vec<Arc<dyn Light>>,
+}
+
+/// Light arriving along rays that don't hit anything
+#[derive(Debug, Clone, Copy)]
+pub enum Background {
+    /// Gradient from white at the horizon to light blue straight up
+    Sky,
+    /// The same light from every direction
+    Uniform(Vector3<f32>),
+}
+
+impl Background {
+    pub fn radiance(&self, ray: Ray) -> Vector3<f32> {
+        match *self {
+            Background::Sky => {
+                // Create a background gradient by lerping white and blue over the height
+
+                // Normalize ray height to -1.0 to 1.0
+                let height = ray.direction().normalize().y;
+                // Scale ray to range 0.0 to 1.0 to get lerp factor
+                let t = 0.5 * (height + 1.0);
+                // Lerp height to get color
+                // Blended Value = (1 - t) * start_value + t * end_value where t is the lerp factor
+                (1.0 - t) * Vector3::new(1.0, 1.0, 1.0) + t * Vector3::new(0.5, 0.7, 1.0)
+            }
+            Background::Uniform(color) => color,
+        }
+    }
+}
+
+/// Create a built-in scene by name. Returns None if no scene has that name
+pub fn create_scene(name: &str, options: SceneOptions) -> Option<Scene> {
+    let scene = match name {
+        "spheres" => load_scene(),
+        "volumes" => volume_scene(None),
+        "cover" => cover_scene(options.seed, options.count),
+        "sphere-cloud" => sphere_cloud(options.seed, options.count),
+        "cornell" => cornell_box(),
+        "mesh" => large_mesh_scene(),
+        "furnace" => furnace(),
+        "caustic" => glass_caustic(),
+        "veach-mis" => veach_mis(),
+        "csg" => csg_scene(),
+        "sdf" => distance_field_scene(),
+        "quadrics" => quadrics_scene(),
+        "terrain" => terrain_scene(None),
+        "oren-nayar" => oren_nayar_scene(),
+        _ => return None,
+    };
+
+    Some(scene)
+}
+
+// Random numbers for placing objects in generated scenes, the same seed always gives
+// the same scene
+struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    // Number in the range 0.0 to 1.0, excluding 1.0
+    fn next_f32(&mut self) -> f32 {
+        // SplitMix64
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+
+        // Only the top 24 bits fit in an f32
+        (z >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+// Material of a small generated sphere, mostly diffuse with some metal and glass
+fn random_material(rng: &mut Rng) -> Material {
+    let choice = rng.next_f32();
+
+    if choice < 0.8 {
+        Material::new_lambertian(
+            rng.next_f32() * rng.next_f32(),
+            rng.next_f32() * rng.next_f32(),
+            rng.next_f32() * rng.next_f32(),
+        )
+    } else if choice < 0.95 {
+        Material::new_metallic(
+            0.5 * (1.0 + rng.next_f32()),
+            0.5 * (1.0 + rng.next_f32()),
+            0.5 * (1.0 + rng.next_f32()),
+            0.5 * rng.next_f32(),
+        )
+    } else {
+        Material::new_dielectric(1.5)
+    }
+}
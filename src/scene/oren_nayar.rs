@@ -0,0 +1,45 @@
+use cgmath::Vector3;
+
+use camera::CameraSettings;
+use hittable_list::HittableList;
+use material::Material;
+use scene::{Background, Scene};
+use sphere::Sphere;
+
+/// A row of clay colored spheres going from smooth Lambertian on the left to very rough
+/// Oren-Nayar on the right. Rougher spheres look flatter and darker, since light caught
+/// between the bumps of a rough surface is lost after its first bounce
+pub fn oren_nayar_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    world.insert(Box::new(Sphere::new(
+        Vector3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Material::new_lambertian(0.5, 0.5, 0.5),
+    )));
+
+    world.insert(Box::new(Sphere::new(
+        Vector3::new(-3.3, 1.0, 0.0),
+        1.0,
+        Material::new_lambertian(0.8, 0.5, 0.3),
+    )));
+    for (index, &roughness) in [0.3, 0.6, 1.0].iter().enumerate() {
+        world.insert(Box::new(Sphere::new(
+            Vector3::new(-1.1 + 2.2 * index as f32, 1.0, 0.0),
+            1.0,
+            Material::new_oren_nayar(0.8, 0.5, 0.3, roughness),
+        )));
+    }
+
+    Scene {
+        world: Box::new(world.into_bvh()),
+        camera: CameraSettings {
+            position: Vector3::new(0.0, 2.5, 9.0),
+            target: Vector3::new(0.0, 0.9, 0.0),
+            up: Vector3::unit_y(),
+            vertical_fov: 35.0,
+        },
+        background: Background::Sky,
+        lights: Vec::new(),
+    }
+}
@@ -0,0 +1,64 @@
+use cgmath::Vector3;
+
+use camera::CameraSettings;
+use hittable_list::HittableList;
+use material::Material;
+use quadric::{Cone, Cylinder, Paraboloid};
+use scene::{Background, Scene};
+use sphere::Sphere;
+use torus::Torus;
+
+/// A row of cylinders, cones, tori and paraboloids, whole at the front and with a piece
+/// cut out of their sweep at the back so the inside shows
+pub fn quadrics_scene() -> Scene {
+    let mut world = HittableList::new();
+
+    world.insert(Box::new(Sphere::new(
+        Vector3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Material::new_lambertian(0.5, 0.5, 0.5),
+    )));
+
+    for &(z, phi_max) in [(1.0, 360.0), (-1.5, 270.0)].iter() {
+        world.insert(Box::new(Cylinder::new(
+            Vector3::new(-3.0, 0.0, z),
+            0.6,
+            1.2,
+            phi_max,
+            Material::new_lambertian(0.8, 0.3, 0.3),
+        )));
+        world.insert(Box::new(Cone::new(
+            Vector3::new(-1.0, 0.0, z),
+            0.7,
+            1.4,
+            phi_max,
+            Material::new_metallic(0.9, 0.7, 0.3, 0.15),
+        )));
+        world.insert(Box::new(Torus::new(
+            Vector3::new(1.0, 0.25, z),
+            0.55,
+            0.25,
+            phi_max,
+            Material::new_lambertian(0.3, 0.7, 0.4),
+        )));
+        world.insert(Box::new(Paraboloid::new(
+            Vector3::new(3.0, 0.0, z),
+            0.7,
+            1.2,
+            phi_max,
+            Material::new_oren_nayar(0.3, 0.5, 0.8, 0.5),
+        )));
+    }
+
+    Scene {
+        world: Box::new(world.into_bvh()),
+        camera: CameraSettings {
+            position: Vector3::new(0.0, 3.0, 7.5),
+            target: Vector3::new(0.0, 0.5, -0.3),
+            up: Vector3::unit_y(),
+            vertical_fov: 40.0,
+        },
+        background: Background::Sky,
+        lights: Vec::new(),
+    }
+}
@@ -0,0 +1,49 @@
+use cgmath::prelude::*;
+use cgmath::Vector3;
+
+use camera::CameraSettings;
+use hittable_list::HittableList;
+use scene::{random_material, Background, Rng, Scene};
+use sphere::Sphere;
+
+const DEFAULT_COUNT: usize = 10_000;
+
+/// Spheres of random sizes and materials floating in a cube under the sky. The cube grows
+/// with the number of spheres so they're always about as crowded, which makes it a good
+/// scene for seeing how tracing scales up to millions of objects
+pub fn sphere_cloud(seed: u64, count: Option<usize>) -> Scene {
+    let count = count.unwrap_or(DEFAULT_COUNT);
+    // One sphere for every unit of volume
+    let side = (count as f32).cbrt().max(1.0);
+
+    let mut rng = Rng::new(seed);
+    let mut world = HittableList::new();
+
+    for _ in 0..count {
+        let position = Vector3::new(
+            rng.next_f32() - 0.5,
+            rng.next_f32() - 0.5,
+            rng.next_f32() - 0.5,
+        ) * side;
+        let radius = 0.1 + 0.2 * rng.next_f32();
+
+        world.insert(Box::new(Sphere::new(
+            position,
+            radius,
+            random_material(&mut rng),
+        )));
+    }
+
+    Scene {
+        world: Box::new(world.into_bvh()),
+        camera: CameraSettings {
+            // Far enough back to see the whole cube
+            position: Vector3::new(1.0, 0.7, 1.6).normalize() * 2.2 * side,
+            target: Vector3::zero(),
+            up: Vector3::unit_y(),
+            vertical_fov: 40.0,
+        },
+        background: Background::Sky,
+        lights: Vec::new(),
+    }
+}
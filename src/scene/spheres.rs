@@ -1,10 +1,23 @@
 use cgmath::Vector3;
-use sphere;
+
+use camera::CameraSettings;
+use hittable_list::HittableList;
 use material::Material;
-use hit::Hittable;
-use hittable_list;
+use scene::{Background, Scene};
+use sphere;
+
+/// Builds the default scene, four spheres under the sky
+pub fn load_scene() -> Scene {
+    Scene {
+        world: Box::new(four_spheres().into_bvh()),
+        camera: CameraSettings::default(),
+        background: Background::Sky,
+        lights: Vec::new(),
+    }
+}
 
-pub fn load_scene() -> hittable_list::HittableList {
+/// Blue, metal and glass spheres in a row on a yellow ground
+pub fn four_spheres() -> HittableList {
     let lambertian_blue = Material::new_lambertian(0.1, 0.2, 0.5);
     let lambertian_yellow = Material::new_lambertian(0.8, 0.8, 0.0);
     let metallic = Material::new_metallic(0.8, 0.6, 0.2, 1.0);
@@ -15,7 +28,7 @@ pub fn load_scene() -> hittable_list::HittableList {
     let sphere_two = sphere::Sphere::new(Vector3::new(-1.0, 0.0, -1.0), 0.5, dielectric);
     let big_sphere = sphere::Sphere::new(Vector3::new(0.0, -100.5, -1.0), 100.0, lambertian_yellow);
 
-    let mut world = hittable_list::HittableList::new();
+    let mut world = HittableList::new();
     world.insert(Box::new(sphere_zero));
     world.insert(Box::new(sphere_one));
     world.insert(Box::new(sphere_two));
@@ -0,0 +1,60 @@
+use cgmath::Vector3;
+
+use std::f32;
+
+use camera::CameraSettings;
+use heightfield::{HeightMap, Heightfield};
+use hittable_list::HittableList;
+use material::Material;
+use mesh::Mesh;
+use scene::{Background, Scene};
+
+/// A valley between hills seen from above, with a lake at the bottom. If a height map is
+/// given it replaces the built-in hills
+pub fn terrain_scene(heights: Option<HeightMap>) -> Scene {
+    let heights = heights.unwrap_or_else(|| HeightMap::from_fn(512, 512, hills));
+
+    let mut world = HittableList::new();
+    world.insert(Box::new(Heightfield::new(
+        heights,
+        Vector3::new(-10.0, 0.0, -10.0),
+        Vector3::new(20.0, 4.0, 20.0),
+        Material::new_oren_nayar(0.45, 0.5, 0.3, 0.6),
+    )));
+
+    // Lake filling the valley up to a quarter of the way
+    world.insert(Box::new(Mesh::quad(
+        Vector3::new(-10.0, 1.0, -10.0),
+        Vector3::new(-10.0, 1.0, 10.0),
+        Vector3::new(10.0, 1.0, 10.0),
+        Vector3::new(10.0, 1.0, -10.0),
+        Material::new_metallic(0.3, 0.45, 0.6, 0.05),
+    )));
+
+    Scene {
+        world: Box::new(world.into_bvh()),
+        camera: CameraSettings {
+            position: Vector3::new(0.0, 7.0, 13.0),
+            target: Vector3::new(0.0, 0.5, -2.0),
+            up: Vector3::unit_y(),
+            vertical_fov: 45.0,
+        },
+        background: Background::Sky,
+        lights: Vec::new(),
+    }
+}
+
+// Heights from 0.0 to 1.0, low along a winding valley in the middle and rising into
+// ridges on both sides. Positions range from 0.0 to 1.0
+fn hills(u: f32, v: f32) -> f32 {
+    let tau = 2.0 * f32::consts::PI;
+
+    let river = 0.5 + 0.12 * (v * tau * 1.5).sin();
+    let valley = ((u - river).abs() * 2.2).min(1.0);
+
+    let ridges = 0.5 * (u * tau * 3.0).sin() * (v * tau * 2.0).cos()
+        + 0.25 * (u * tau * 7.0 + 1.0).sin() * (v * tau * 5.0 + 2.0).sin()
+        + 0.12 * (u * tau * 17.0).cos() * (v * tau * 13.0 + 0.5).sin();
+
+    (0.15 + 0.55 * valley * valley + 0.15 * valley * ridges).clamp(0.0, 1.0)
+}
@@ -0,0 +1,180 @@
+use cgmath::prelude::*;
+use cgmath::Vector3;
+
+use std::sync::Arc;
+
+use camera::CameraSettings;
+use hittable_list::HittableList;
+use light::Light;
+use material::Material;
+use mesh::Mesh;
+use scene::{Background, Scene};
+use sphere::Sphere;
+
+// Albedo of the sphere in the furnace
+const FURNACE_ALBEDO: f32 = 0.5;
+
+/// A diffuse sphere in a furnace, lit the same from every direction. Light bouncing off a
+/// convex object never hits it again, so every pixel of the sphere should come out as
+/// exactly half the background. Shading, dark edges or a visible outline mean the
+/// material or the integrator loses or creates energy
+pub fn furnace() -> Scene {
+    let mut world = HittableList::new();
+    world.insert(Box::new(Sphere::new(
+        Vector3::zero(),
+        1.0,
+        Material::new_lambertian(FURNACE_ALBEDO, FURNACE_ALBEDO, FURNACE_ALBEDO),
+    )));
+
+    Scene {
+        world: Box::new(world.into_bvh()),
+        camera: CameraSettings {
+            position: Vector3::new(0.0, 0.0, 3.0),
+            target: Vector3::zero(),
+            up: Vector3::unit_y(),
+            vertical_fov: 60.0,
+        },
+        background: Background::Uniform(Vector3::new(1.0, 1.0, 1.0)),
+        lights: Vec::new(),
+    }
+}
+
+/// A glass ball on a white floor under a small bright light. The ball works as a lens and
+/// focuses the light into a bright spot inside its shadow. Glass with a refractive index
+/// of 1.5 focuses one and a half radii behind the center of the ball, a little before the
+/// light reaches the floor, so the spot is slightly blurred.
+/// Caustics like this can only be found by paths that go through the glass and then hit
+/// the light, so they converge slowly without light sampling
+pub fn glass_caustic() -> Scene {
+    let mut world = HittableList::new();
+
+    world.insert(Box::new(Mesh::quad(
+        Vector3::new(-10.0, 0.0, -10.0),
+        Vector3::new(-10.0, 0.0, 10.0),
+        Vector3::new(10.0, 0.0, 10.0),
+        Vector3::new(10.0, 0.0, -10.0),
+        Material::new_lambertian(0.8, 0.8, 0.8),
+    )));
+    world.insert(Box::new(Sphere::new(
+        Vector3::new(0.0, 1.0, 0.0),
+        1.0,
+        Material::new_dielectric(1.5),
+    )));
+    // Low and off to the side so the caustic lands next to the ball instead of under it
+    let light = Arc::new(Sphere::new(
+        Vector3::new(-5.0, 4.0, -2.0),
+        0.5,
+        Material::new_emissive(200.0, 200.0, 200.0),
+    ));
+    world.insert(Box::new(light.clone()));
+
+    Scene {
+        world: Box::new(world.into_bvh()),
+        camera: CameraSettings {
+            position: Vector3::new(0.0, 3.5, 7.0),
+            target: Vector3::new(0.5, 0.6, 0.0),
+            up: Vector3::unit_y(),
+            vertical_fov: 40.0,
+        },
+        background: Background::Uniform(Vector3::zero()),
+        lights: vec![light],
+    }
+}
+
+/// The multiple importance sampling test scene from Eric Veach's thesis. Four spherical
+/// lights of very different sizes, each giving off the same power, reflect in four glossy
+/// plates going from nearly a mirror at the front to rough at the back.
+///
+/// Sampling the material finds the small lights in the smooth plates with little noise,
+/// while sampling the lights finds the big lights in the rough plates. Rendered with the
+/// direct lighting integrator, which combines both, every reflection should come out
+/// clean
+pub fn veach_mis() -> Scene {
+    let camera = Vector3::new(0.0, 2.0, 15.0);
+    // The lights are spread along the x-axis
+    let lights = [
+        (-3.75, 0.0333, 901.803),
+        (-1.25, 0.1, 100.0),
+        (1.25, 0.3, 11.1111),
+        (3.75, 0.9, 1.23457),
+    ];
+    // Center of each plate and how fuzzy its reflection is
+    let plates = [
+        (Vector3::new(0.0, -3.4, 4.4), 0.02),
+        (Vector3::new(0.0, -2.9, 3.1), 0.06),
+        (Vector3::new(0.0, -2.35, 1.85), 0.15),
+        (Vector3::new(0.0, -1.7, 0.65), 0.35),
+    ];
+
+    let mut world = HittableList::new();
+
+    for &(center, fuzziness) in plates.iter() {
+        // Tilted so the camera sees the middle of the row of lights in the middle of the
+        // plate
+        let to_camera = (camera - center).normalize();
+        let to_lights = (Vector3::zero() - center).normalize();
+        let normal = (to_camera + to_lights).normalize();
+
+        let across = Vector3::new(4.0, 0.0, 0.0);
+        let along = normal.cross(Vector3::unit_x()).normalize() * 0.5;
+
+        world.insert(Box::new(Mesh::quad(
+            center - across - along,
+            center + across - along,
+            center + across + along,
+            center - across + along,
+            Material::new_metallic(0.35, 0.35, 0.35, fuzziness),
+        )));
+    }
+
+    let mut spheres = Vec::new();
+    for &(x, radius, radiance) in lights.iter() {
+        spheres.push(Arc::new(Sphere::new(
+            Vector3::new(x, 0.0, 0.0),
+            radius,
+            Material::new_emissive(radiance, radiance, radiance),
+        )));
+    }
+
+    // Dim light from above so the rest of the scene isn't black
+    spheres.push(Arc::new(Sphere::new(
+        Vector3::new(10.0, 10.0, 4.0),
+        0.5,
+        Material::new_emissive(800.0, 800.0, 800.0),
+    )));
+
+    for sphere in &spheres {
+        world.insert(Box::new(sphere.clone()));
+    }
+
+    // Floor and back wall
+    world.insert(Box::new(Mesh::quad(
+        Vector3::new(-20.0, -4.1, -5.0),
+        Vector3::new(-20.0, -4.1, 20.0),
+        Vector3::new(20.0, -4.1, 20.0),
+        Vector3::new(20.0, -4.1, -5.0),
+        Material::new_lambertian(0.4, 0.4, 0.4),
+    )));
+    world.insert(Box::new(Mesh::quad(
+        Vector3::new(-20.0, -4.1, -5.0),
+        Vector3::new(20.0, -4.1, -5.0),
+        Vector3::new(20.0, 20.0, -5.0),
+        Vector3::new(-20.0, 20.0, -5.0),
+        Material::new_lambertian(0.4, 0.4, 0.4),
+    )));
+
+    Scene {
+        world: Box::new(world.into_bvh()),
+        camera: CameraSettings {
+            position: camera,
+            target: Vector3::new(0.0, -2.0, 2.5),
+            up: Vector3::unit_y(),
+            vertical_fov: 28.0,
+        },
+        background: Background::Uniform(Vector3::zero()),
+        lights: spheres
+            .into_iter()
+            .map(|sphere| sphere as Arc<dyn Light>)
+            .collect(),
+    }
+}
@@ -0,0 +1,71 @@
+use cgmath::prelude::*;
+use cgmath::Vector3;
+
+use aabb::Aabb;
+use camera::CameraSettings;
+use cuboid::Cuboid;
+use material::Material;
+use medium::{GridMedium, HenyeyGreenstein, HomogeneousMedium};
+use scene::spheres::four_spheres;
+use scene::{Background, Scene};
+use sphere::Sphere;
+use volume::Volume;
+use voxel::VoxelGrid;
+
+/// The spheres of the default scene with a puff of smoke in front of the spheres and an explosion in the
+/// sky. If a density grid is given it replaces the built-in explosion
+pub fn volume_scene(cloud_density: Option<VoxelGrid>) -> Scene {
+    let dielectric = Material::new_dielectric(1.5);
+
+    // Explosion hanging in the sky, with a glowing core
+    let cloud_bounds = Aabb::new(Vector3::new(-1.9, 0.5, -2.4), Vector3::new(-0.7, 1.7, -1.2));
+    let cloud_boundary = Cuboid::new(cloud_bounds.min, cloud_bounds.max, dielectric);
+    let cloud_phase = HenyeyGreenstein::new(0.6);
+    let cloud = match cloud_density {
+        Some(density) => GridMedium::new(cloud_bounds, density, 0.5, 6.0, cloud_phase),
+        None => {
+            let density = VoxelGrid::from_fn([32, 32, 32], explosion_density);
+            let heat = VoxelGrid::from_fn([32, 32, 32], explosion_heat);
+
+            GridMedium::new(cloud_bounds, density, 0.5, 6.0, cloud_phase)
+                .with_emission(heat, Vector3::new(8.0, 3.0, 0.5))
+        }
+    };
+    let explosion = Volume::new(Box::new(cloud_boundary), cloud);
+
+    // Forward scattering, so the smoke looks brightest with the sky behind it
+    let smoke = HomogeneousMedium::new(
+        Vector3::new(0.5, 0.5, 0.5),
+        Vector3::new(4.0, 4.0, 4.0),
+        HenyeyGreenstein::new(0.3),
+    );
+    // The boundary is invisible so its material is never used
+    let smoke_boundary = Sphere::new(Vector3::new(0.5, -0.3, -0.6), 0.2, dielectric);
+
+    let mut world = four_spheres();
+    world.insert(Box::new(Volume::new(Box::new(smoke_boundary), smoke)));
+    world.insert(Box::new(explosion));
+
+    Scene {
+        world: Box::new(world.into_bvh()),
+        camera: CameraSettings::default(),
+        background: Background::Sky,
+        lights: Vec::new(),
+    }
+}
+
+// Lumpy ball of smoke filling the grid, positions range from 0.0 to 1.0
+fn explosion_density(position: Vector3<f32>) -> f32 {
+    let offset = position - Vector3::new(0.5, 0.5, 0.5);
+    let lumps = (offset.x * 17.0).sin() * (offset.y * 13.0).sin() * (offset.z * 19.0).sin();
+    let radius = 0.4 + 0.08 * lumps;
+
+    (1.0 - offset.magnitude() / radius).max(0.0)
+}
+
+// The center of the explosion is the hottest part
+fn explosion_heat(position: Vector3<f32>) -> f32 {
+    let distance = (position - Vector3::new(0.5, 0.5, 0.5)).magnitude();
+
+    (1.0 - distance / 0.25).max(0.0).powi(2)
+}
@@ -0,0 +1,560 @@
+use cgmath::prelude::*;
+use cgmath::{Vector2, Vector3};
+
+use aabb::Aabb;
+use hit::{HitRecord, Hittable};
+use material::Material;
+use ray::Ray;
+use stats::{self, Counter};
+
+// Distance from the surface at which a ray counts as having hit it
+const HIT_DISTANCE: f32 = 1e-4;
+
+// Smallest step along a ray, so rays grazing a surface still get anywhere
+const MIN_STEP: f32 = 1e-4;
+
+// Steps before a ray gives up and counts as a miss
+const MAX_STEPS: u32 = 1024;
+
+// Offset used for the central differences of the gradient
+const GRADIENT_STEP: f32 = 1e-4;
+
+// Halvings of the last step once a ray has stepped through the surface
+const REFINE_STEPS: u32 = 16;
+
+/// Tree of shapes described by their signed distance field, the distance from any point
+/// to the closest surface, negative inside. Primitives are centered on the origin and
+/// placed with `translate`
+#[derive(Debug, Clone)]
+pub enum SdfNode {
+    Sphere {
+        radius: f32,
+    },
+    Box {
+        half_size: Vector3<f32>,
+    },
+    /// Ring lying flat in the xz-plane
+    Torus {
+        major_radius: f32,
+        minor_radius: f32,
+    },
+    /// Line segment between two points with a radius around it
+    Capsule {
+        a: Vector3<f32>,
+        b: Vector3<f32>,
+        radius: f32,
+    },
+    Translate {
+        offset: Vector3<f32>,
+        child: Box<SdfNode>,
+    },
+    /// Union that blends the shapes together within `smoothness` of where they meet
+    SmoothUnion {
+        a: Box<SdfNode>,
+        b: Box<SdfNode>,
+        smoothness: f32,
+    },
+    /// Cuts `b` out of `a`, rounding the edges of the cut within `smoothness`
+    SmoothSubtraction {
+        a: Box<SdfNode>,
+        b: Box<SdfNode>,
+        smoothness: f32,
+    },
+    /// Copies of the child `spacing` apart, `count` more in both directions along each
+    /// axis. The child has to fit between its neighbours
+    Repeat {
+        spacing: Vector3<f32>,
+        count: [u32; 3],
+        child: Box<SdfNode>,
+    },
+    /// Ripples over the surface of the child, `amplitude` high with `frequency` waves
+    /// per unit of length
+    Displace {
+        amplitude: f32,
+        frequency: f32,
+        child: Box<SdfNode>,
+    },
+}
+
+impl SdfNode {
+    pub fn sphere(radius: f32) -> SdfNode {
+        SdfNode::Sphere { radius }
+    }
+
+    pub fn cuboid(half_size: Vector3<f32>) -> SdfNode {
+        SdfNode::Box { half_size }
+    }
+
+    pub fn torus(major_radius: f32, minor_radius: f32) -> SdfNode {
+        SdfNode::Torus {
+            major_radius,
+            minor_radius,
+        }
+    }
+
+    pub fn capsule(a: Vector3<f32>, b: Vector3<f32>, radius: f32) -> SdfNode {
+        SdfNode::Capsule { a, b, radius }
+    }
+
+    pub fn translate(self, offset: Vector3<f32>) -> SdfNode {
+        SdfNode::Translate {
+            offset,
+            child: Box::new(self),
+        }
+    }
+
+    pub fn smooth_union(self, other: SdfNode, smoothness: f32) -> SdfNode {
+        SdfNode::SmoothUnion {
+            a: Box::new(self),
+            b: Box::new(other),
+            smoothness,
+        }
+    }
+
+    pub fn smooth_subtract(self, other: SdfNode, smoothness: f32) -> SdfNode {
+        SdfNode::SmoothSubtraction {
+            a: Box::new(self),
+            b: Box::new(other),
+            smoothness,
+        }
+    }
+
+    pub fn repeat(self, spacing: Vector3<f32>, count: [u32; 3]) -> SdfNode {
+        SdfNode::Repeat {
+            spacing,
+            count,
+            child: Box::new(self),
+        }
+    }
+
+    pub fn displace(self, amplitude: f32, frequency: f32) -> SdfNode {
+        SdfNode::Displace {
+            amplitude,
+            frequency,
+            child: Box::new(self),
+        }
+    }
+
+    /// Signed distance from the point to the surface. Blends and displacements can make
+    /// it an overestimate, see `lipschitz`
+    pub fn distance(&self, point: Vector3<f32>) -> f32 {
+        match *self {
+            SdfNode::Sphere { radius } => point.magnitude() - radius,
+            SdfNode::Box { half_size } => {
+                let q = point.map(f32::abs) - half_size;
+                let outside = q.map(|x| x.max(0.0)).magnitude();
+                let inside = q.x.max(q.y).max(q.z).min(0.0);
+
+                outside + inside
+            }
+            SdfNode::Torus {
+                major_radius,
+                minor_radius,
+            } => {
+                let q = Vector2::new(
+                    Vector2::new(point.x, point.z).magnitude() - major_radius,
+                    point.y,
+                );
+
+                q.magnitude() - minor_radius
+            }
+            SdfNode::Capsule { a, b, radius } => {
+                let pa = point - a;
+                let ba = b - a;
+                let h = (pa.dot(ba) / ba.magnitude2()).clamp(0.0, 1.0);
+
+                (pa - ba * h).magnitude() - radius
+            }
+            SdfNode::Translate { offset, ref child } => child.distance(point - offset),
+            SdfNode::SmoothUnion {
+                ref a,
+                ref b,
+                smoothness,
+            } => smooth_min(a.distance(point), b.distance(point), smoothness),
+            SdfNode::SmoothSubtraction {
+                ref a,
+                ref b,
+                smoothness,
+            } => -smooth_min(-a.distance(point), b.distance(point), smoothness),
+            SdfNode::Repeat {
+                spacing,
+                count,
+                ref child,
+            } => {
+                // Move the point into the copy closest to it
+                let mut local = point;
+                for axis in 0..3 {
+                    if count[axis] > 0 {
+                        let limit = count[axis] as f32;
+                        let copy = (point[axis] / spacing[axis]).round().clamp(-limit, limit);
+                        local[axis] -= spacing[axis] * copy;
+                    }
+                }
+
+                child.distance(local)
+            }
+            SdfNode::Displace {
+                amplitude,
+                frequency,
+                ref child,
+            } => {
+                let p = point * frequency;
+                child.distance(point) + amplitude * p.x.sin() * p.y.sin() * p.z.sin()
+            }
+        }
+    }
+
+    /// Box around the surface
+    pub fn bounds(&self) -> Aabb {
+        match *self {
+            SdfNode::Sphere { radius } => cube(radius),
+            SdfNode::Box { half_size } => Aabb::new(-half_size, half_size),
+            SdfNode::Torus {
+                major_radius,
+                minor_radius,
+            } => {
+                let extent = major_radius + minor_radius;
+                Aabb::new(
+                    Vector3::new(-extent, -minor_radius, -extent),
+                    Vector3::new(extent, minor_radius, extent),
+                )
+            }
+            SdfNode::Capsule { a, b, radius } => {
+                cube(radius).offset(a).union(&cube(radius).offset(b))
+            }
+            SdfNode::Translate { offset, ref child } => child.bounds().offset(offset),
+            // Blending only ever adds a quarter of the smoothness to the shapes
+            SdfNode::SmoothUnion {
+                ref a,
+                ref b,
+                smoothness,
+            } => a.bounds().union(&b.bounds()).expand(smoothness / 4.0),
+            SdfNode::SmoothSubtraction {
+                ref a, smoothness, ..
+            } => a.bounds().expand(smoothness / 4.0),
+            SdfNode::Repeat {
+                spacing,
+                count,
+                ref child,
+            } => {
+                let child = child.bounds();
+                let reach = Vector3::new(
+                    spacing.x * count[0] as f32,
+                    spacing.y * count[1] as f32,
+                    spacing.z * count[2] as f32,
+                );
+
+                Aabb::new(child.min - reach, child.max + reach)
+            }
+            SdfNode::Displace {
+                amplitude,
+                ref child,
+                ..
+            } => child.bounds().expand(amplitude),
+        }
+    }
+
+    /// Upper bound on how fast the distance changes along a line. Exact distance fields
+    /// have a bound of 1.0, displacements make the distance change faster than the
+    /// actual distance so rays have to take smaller steps
+    pub fn lipschitz(&self) -> f32 {
+        match *self {
+            SdfNode::Sphere { .. }
+            | SdfNode::Box { .. }
+            | SdfNode::Torus { .. }
+            | SdfNode::Capsule { .. } => 1.0,
+            SdfNode::Translate { ref child, .. } | SdfNode::Repeat { ref child, .. } => {
+                child.lipschitz()
+            }
+            SdfNode::SmoothUnion { ref a, ref b, .. }
+            | SdfNode::SmoothSubtraction { ref a, ref b, .. } => a.lipschitz().max(b.lipschitz()),
+            SdfNode::Displace {
+                amplitude,
+                frequency,
+                ref child,
+            } => child.lipschitz() + amplitude * frequency * 3f32.sqrt(),
+        }
+    }
+}
+
+/// A distance field tree made of a single material, found by sphere tracing. Rays step
+/// forward by the distance to the closest surface, which can never take them through it
+pub struct Sdf {
+    root: SdfNode,
+    material: Material,
+    bounds: Aabb,
+    lipschitz: f32,
+}
+
+impl Sdf {
+    pub fn new(root: SdfNode, material: Material) -> Sdf {
+        Sdf {
+            bounds: root.bounds(),
+            lipschitz: root.lipschitz(),
+            root,
+            material,
+        }
+    }
+
+    // Points along the gradient of the distance, out of the shape
+    fn normal(&self, point: Vector3<f32>) -> Vector3<f32> {
+        let gradient = |axis: Vector3<f32>| {
+            self.root.distance(point + axis * GRADIENT_STEP)
+                - self.root.distance(point - axis * GRADIENT_STEP)
+        };
+
+        let normal = Vector3::new(
+            gradient(Vector3::unit_x()),
+            gradient(Vector3::unit_y()),
+            gradient(Vector3::unit_z()),
+        );
+
+        if normal.magnitude2() > 0.0 {
+            normal.normalize()
+        } else {
+            Vector3::unit_y()
+        }
+    }
+}
+
+impl Hittable for Sdf {
+    // Rays can start on either side of the surface, so distances are measured on the side
+    // the ray starts on. A ray that just left the surface has to get away from it before
+    // it can hit it again
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        stats::count(Counter::IntersectionTests);
+
+        let (t_enter, t_exit) = self.bounds.intersect(ray, t_min, t_max)?;
+
+        // Distances are along the ray in world units, t is in multiples of the direction
+        let speed = ray.direction().magnitude();
+        let mut t = t_enter.max(t_min);
+        let side = if self.root.distance(ray.point_at_distance(t)) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+
+        let mut previous_t = t;
+        let mut left_surface = false;
+        let mut converged = false;
+        let mut near_surface = false;
+
+        for _ in 0..MAX_STEPS {
+            let distance = side * self.root.distance(ray.point_at_distance(t));
+            near_surface = distance < HIT_DISTANCE;
+
+            if distance < 0.0 {
+                // Stepped through the surface, find where it was by bisection
+                let (mut near, mut far) = (previous_t, t);
+                for _ in 0..REFINE_STEPS {
+                    let middle = 0.5 * (near + far);
+                    if side * self.root.distance(ray.point_at_distance(middle)) < 0.0 {
+                        far = middle;
+                    } else {
+                        near = middle;
+                    }
+                }
+
+                t = far;
+                converged = true;
+                break;
+            }
+
+            if distance < HIT_DISTANCE {
+                if left_surface {
+                    converged = true;
+                    break;
+                }
+            } else {
+                left_surface = true;
+            }
+
+            previous_t = t;
+            t += (distance / self.lipschitz).max(MIN_STEP) / speed;
+            if t > t_exit {
+                return None;
+            }
+        }
+
+        // Rays skimming along the surface take the smallest step every time and run out
+        // of steps while still touching it. Letting them miss would let refracted rays
+        // escape through the side of glass, so they hit where they last touched it
+        if !converged && near_surface {
+            t = previous_t;
+            converged = true;
+        }
+
+        if !converged || t <= t_min || t >= t_max {
+            return None;
+        }
+
+        let position = ray.point_at_distance(t);
+
+        Some(HitRecord {
+            t,
+            position,
+            normal: self.normal(position),
+            // Distance fields have no natural parameterization
+            uv: Vector2::new(0.0, 0.0),
+            material: self.material,
+            object_id: 0,
+            medium: None,
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bounds
+    }
+}
+
+// Polynomial smooth minimum, equal to the minimum when a and b are more than k apart
+fn smooth_min(a: f32, b: f32, k: f32) -> f32 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+
+    let h = (k - (a - b).abs()).max(0.0) / k;
+    a.min(b) - h * h * k / 4.0
+}
+
+// Box around a sphere of the given radius at the origin
+fn cube(radius: f32) -> Aabb {
+    let extent = Vector3::new(radius, radius, radius);
+    Aabb::new(-extent, extent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn primitive_distances() {
+        let point = Vector3::new(2.0, 0.0, 0.0);
+
+        assert_near(SdfNode::sphere(1.0).distance(point), 1.0);
+        assert_near(
+            SdfNode::cuboid(Vector3::new(1.0, 1.0, 1.0)).distance(point),
+            1.0,
+        );
+        assert_near(SdfNode::torus(1.5, 0.25).distance(point), 0.25);
+        assert_near(
+            SdfNode::capsule(
+                Vector3::new(0.0, -1.0, 0.0),
+                Vector3::new(0.0, 1.0, 0.0),
+                0.5,
+            )
+            .distance(point),
+            1.5,
+        );
+
+        // Inside is negative
+        assert_near(
+            SdfNode::cuboid(Vector3::new(1.0, 1.0, 1.0)).distance(Vector3::zero()),
+            -1.0,
+        );
+    }
+
+    #[test]
+    fn operations() {
+        let sphere = SdfNode::sphere(1.0).translate(Vector3::new(3.0, 0.0, 0.0));
+        assert_near(sphere.distance(Vector3::zero()), 2.0);
+
+        // Far from where they meet, a smooth union is an ordinary union
+        let union = SdfNode::sphere(1.0).smooth_union(sphere.clone(), 0.5);
+        assert_near(union.distance(Vector3::new(-2.0, 0.0, 0.0)), 1.0);
+
+        let cut = SdfNode::sphere(1.0).smooth_subtract(SdfNode::sphere(0.5), 0.0);
+        assert_near(cut.distance(Vector3::zero()), 0.5);
+
+        let row = SdfNode::sphere(0.5).repeat(Vector3::new(2.0, 0.0, 0.0), [2, 0, 0]);
+        assert_near(row.distance(Vector3::new(4.0, 0.0, 0.0)), -0.5);
+        assert_near(row.distance(Vector3::new(8.0, 0.0, 0.0)), 3.5);
+    }
+
+    #[test]
+    fn bounds_contain_the_surface() {
+        let shape = SdfNode::torus(1.0, 0.3)
+            .smooth_union(
+                SdfNode::sphere(0.5).translate(Vector3::new(0.0, 1.0, 0.0)),
+                0.4,
+            )
+            .displace(0.05, 10.0);
+        let bounds = shape.bounds();
+
+        // Every point just outside the bounds is outside the shape
+        for i in 0..64 {
+            let angle = i as f32 / 64.0 * ::std::f32::consts::PI * 2.0;
+            for &y in &[bounds.min.y - 0.01, bounds.max.y + 0.01] {
+                let point = Vector3::new(angle.cos(), y, angle.sin());
+                assert!(shape.distance(point) > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn sphere_tracing_matches_analytic_sphere() {
+        let sdf = Sdf::new(
+            SdfNode::sphere(1.0),
+            Material::new_lambertian(0.5, 0.5, 0.5),
+        );
+        let ray = Ray::new(Vector3::new(0.3, 0.2, 5.0), Vector3::new(0.0, 0.0, -2.0));
+
+        let record = sdf.hit(ray, 0.001, f32::MAX).unwrap();
+        let expected_z = (1.0f32 - 0.3 * 0.3 - 0.2 * 0.2).sqrt();
+        assert_near(record.position.z, expected_z);
+        assert!((record.normal - record.position.normalize()).magnitude() < 1e-3);
+    }
+
+    #[test]
+    fn rays_inside_hit_the_far_side() {
+        let sdf = Sdf::new(SdfNode::sphere(1.0), Material::new_dielectric(1.5));
+
+        // Leaving the surface on the inside, like a refracted ray
+        let ray = Ray::new(Vector3::new(0.0, 0.0, 1.0), Vector3::new(0.0, 0.0, -1.0));
+        let record = sdf.hit(ray, 0.001, f32::MAX).unwrap();
+
+        assert_near(record.t, 2.0);
+        assert!((record.normal - Vector3::new(0.0, 0.0, -1.0)).magnitude() < 1e-3);
+    }
+
+    #[test]
+    fn rays_leaving_the_surface_miss_it() {
+        let sdf = Sdf::new(
+            SdfNode::sphere(1.0),
+            Material::new_lambertian(0.5, 0.5, 0.5),
+        );
+        let ray = Ray::new(Vector3::new(0.0, 0.0, 1.0), Vector3::new(0.0, 1.0, 1.0));
+
+        assert!(sdf.hit(ray, 0.001, f32::MAX).is_none());
+    }
+
+    #[test]
+    fn rays_running_out_of_steps_hit_the_surface_they_skim() {
+        let sdf = Sdf::new(
+            SdfNode::cuboid(Vector3::new(1.0, 1.0, 1.0)),
+            Material::new_dielectric(1.5),
+        );
+
+        // Skims along just under the top face, closer to it than a hit, so every step is
+        // the smallest one and the march gives up long before reaching the far side
+        let ray = Ray::new(
+            Vector3::new(-0.5, 1.0 - 5e-5, 0.0),
+            Vector3::new(1.0, 0.0, 0.0),
+        );
+        assert!(MAX_STEPS as f32 * MIN_STEP < 1.5);
+
+        let record = sdf.hit(ray, 0.001, f32::MAX).unwrap();
+        assert!(record.position.x < 1.0);
+        assert!((record.normal - Vector3::unit_y()).magnitude() < 1e-3);
+    }
+}
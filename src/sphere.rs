@@ -1,9 +1,16 @@
 use cgmath::prelude::*;
-use cgmath::Vector3;
+use cgmath::{Vector2, Vector3};
 
-use hit::{HitRecord, Hittable};
+use std::f32;
+
+use aabb::Aabb;
+use hit::{HitRecord, Hittable, Solid, Span};
+use light::Light;
 use material::Material;
+use onb::Onb;
 use ray::Ray;
+use sampler::Sampler;
+use stats::{self, Counter};
 
 /// The sphere is a position in space, an origin and a material.
 /// It implemements the Hittable trait which means that rays can interact
@@ -22,10 +29,12 @@ impl Sphere {
             material,
         }
     }
-}
 
-impl Hittable for Sphere {
-    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    // Distances along the ray's line to where it enters and leaves the sphere, if it
+    // touches it at all
+    fn roots(&self, ray: Ray) -> Option<(f32, f32)> {
+        stats::count(Counter::IntersectionTests);
+
         // Calculate a vector from the ray origin to the sphere origin
         let oc = ray.origin() - self.center;
 
@@ -35,46 +44,116 @@ impl Hittable for Sphere {
         let c = oc.dot(oc) - (self.radius * self.radius);
         let discriminant = (b * b) - (a * c);
 
-        // It hit something
+        // The hit function is a quadratic so there are two roots where it hits, since it
+        // is a sphere and therefore a circle from the perspective of a ray
         if discriminant > 0.0 {
-            // The hit function is a quadratic so we must find both roots since
-            // it is a sphere and therefore a circle from the perspective of a ray
-
-            // Only calculate precise roots after we know it hit as a optimization
-            // probably premature but it's not a big deal in this case
-            let tmp_t = (-b - discriminant.sqrt()) / a;
-
-            // Check float bounds because of floating point errors
-            if tmp_t < t_max && tmp_t > t_min {
-                let position = ray.point_at_distance(tmp_t);
-
-                let record = HitRecord {
-                    t: tmp_t,
-                    position,
-                    normal: (position - self.center).normalize(),
-                    material: self.material,
-                };
-
-                return Some(record);
-            };
-
-            // Check second square root
-            let tmp_t = (-b + discriminant.sqrt()) / a;
-            if tmp_t < t_max && tmp_t > t_min {
-                let position = ray.point_at_distance(tmp_t);
-
-                let record = HitRecord {
-                    t: tmp_t,
-                    position,
-                    normal: (position - self.center).normalize(),
-                    material: self.material,
-                };
-
-                return Some(record);
-            };
+            Some((
+                (-b - discriminant.sqrt()) / a,
+                (-b + discriminant.sqrt()) / a,
+            ))
+        } else {
+            None
+        }
+    }
+
+    fn record(&self, ray: Ray, t: f32) -> HitRecord<'_> {
+        let position = ray.point_at_distance(t);
+        let normal = (position - self.center).normalize();
+
+        HitRecord {
+            t,
+            position,
+            normal,
+            uv: sphere_uv(normal),
+            material: self.material,
+            object_id: 0,
+            medium: None,
+        }
+    }
+
+    // One minus the cosine of the angle between the center and the edge of the sphere as
+    // seen from `origin`. From inside the sphere every direction hits it
+    fn cone_height(&self, origin: Vector3<f32>) -> f32 {
+        let sin2 = self.radius * self.radius / (self.center - origin).magnitude2();
+        if sin2 >= 1.0 {
+            return 2.0;
+        }
+
+        // Same as 1 - sqrt(1 - sin2), without losing precision for small far away lights
+        sin2 / (1.0 + (1.0 - sin2).sqrt())
+    }
+}
+
+impl Hittable for Sphere {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (near, far) = self.roots(ray)?;
+
+        // Check float bounds because of floating point errors
+        if near < t_max && near > t_min {
+            Some(self.record(ray, near))
+        } else if far < t_max && far > t_min {
+            Some(self.record(ray, far))
+        } else {
+            None
         }
+    }
 
-        // It didn't hit anything so return None
-        None
+    fn bounding_box(&self) -> Aabb {
+        let extent = Vector3::new(self.radius, self.radius, self.radius);
+        Aabb::new(self.center - extent, self.center + extent)
     }
 }
+
+impl Solid for Sphere {
+    fn spans(&self, ray: Ray) -> Vec<Span<'_>> {
+        match self.roots(ray) {
+            Some((near, far)) => vec![Span {
+                enter: self.record(ray, near),
+                exit: self.record(ray, far),
+            }],
+            None => Vec::new(),
+        }
+    }
+}
+
+// Lights are sampled over the cone of directions they cover as seen from the origin, so
+// every sampled direction hits the light
+impl Light for Sphere {
+    fn sample_direction(&self, origin: Vector3<f32>, sampler: &mut dyn Sampler) -> Vector3<f32> {
+        let (u, v) = sampler.next_2d();
+        let cos_theta = 1.0 - u * self.cone_height(origin);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * f32::consts::PI * v;
+
+        Onb::from_w(self.center - origin).to_world(Vector3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ))
+    }
+
+    fn pdf(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        let height = self.cone_height(origin);
+        let cosine = direction
+            .normalize()
+            .dot((self.center - origin).normalize());
+
+        if cosine >= 1.0 - height {
+            1.0 / (2.0 * f32::consts::PI * height)
+        } else {
+            0.0
+        }
+    }
+}
+
+// Latitude/longitude coordinates of a point on the unit sphere. u wraps around the y axis
+// and v goes from the bottom pole to the top pole
+fn sphere_uv(normal: Vector3<f32>) -> Vector2<f32> {
+    let phi = normal.z.atan2(normal.x);
+    let theta = normal.y.clamp(-1.0, 1.0).asin();
+
+    Vector2::new(
+        1.0 - (phi + f32::consts::PI) / (2.0 * f32::consts::PI),
+        (theta + f32::consts::FRAC_PI_2) / f32::consts::PI,
+    )
+}
@@ -0,0 +1,176 @@
+use std::cell::Cell;
+use std::fs;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Things counted while rendering
+#[derive(Debug, Clone, Copy)]
+pub enum Counter {
+    /// Rays leaving the camera
+    CameraRays,
+    /// Rays followed along a path, including the camera ray
+    PathRays,
+    /// Rays only checking whether something is blocked
+    ShadowRays,
+    /// Ray tests against a single primitive
+    IntersectionTests,
+    /// Nodes of acceleration structures the rays went through
+    NodesVisited,
+}
+
+const NUM_COUNTERS: usize = 5;
+
+// Counters of the current thread, added to the global ones by `flush` so threads don't
+// fight over the same atomics for every ray
+thread_local! {
+    static LOCAL_COUNTERS: [Cell<u64>; NUM_COUNTERS] = Default::default();
+}
+
+static GLOBAL_COUNTERS: [AtomicU64; NUM_COUNTERS] = [
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+];
+
+/// Add one to a counter of the current thread
+pub fn count(counter: Counter) {
+    LOCAL_COUNTERS.with(|counters| {
+        let cell = &counters[counter as usize];
+        cell.set(cell.get() + 1);
+    });
+}
+
+/// Add the counters of the current thread to the totals and reset them
+pub fn flush() {
+    LOCAL_COUNTERS.with(|counters| {
+        for (local, global) in counters.iter().zip(&GLOBAL_COUNTERS) {
+            global.fetch_add(local.replace(0), Ordering::Relaxed);
+        }
+    });
+}
+
+/// Set every total back to zero, so the next render is counted on its own. Counts of
+/// other threads that haven't been flushed yet are kept
+pub fn reset() {
+    for global in &GLOBAL_COUNTERS {
+        global.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Total of a counter over every thread, as of the last time each thread flushed
+pub fn total(counter: Counter) -> u64 {
+    GLOBAL_COUNTERS[counter as usize].load(Ordering::Relaxed)
+}
+
+/// Summary of the work done in a render
+#[derive(Debug, Clone)]
+pub struct RenderStats {
+    pub primary_rays: u64,
+    pub secondary_rays: u64,
+    pub shadow_rays: u64,
+    pub intersection_tests: u64,
+    pub nodes_visited: u64,
+    pub seconds: f64,
+    /// Largest amount of memory the process held at once, if the OS reports it
+    pub peak_memory_bytes: Option<u64>,
+}
+
+impl RenderStats {
+    /// Collect the totals of every counter, for a render that took `elapsed`
+    pub fn collect(elapsed: Duration) -> RenderStats {
+        let primary_rays = total(Counter::CameraRays);
+
+        RenderStats {
+            primary_rays,
+            secondary_rays: total(Counter::PathRays).saturating_sub(primary_rays),
+            shadow_rays: total(Counter::ShadowRays),
+            intersection_tests: total(Counter::IntersectionTests),
+            nodes_visited: total(Counter::NodesVisited),
+            seconds: elapsed.as_secs_f64(),
+            peak_memory_bytes: peak_memory_bytes(),
+        }
+    }
+
+    pub fn total_rays(&self) -> u64 {
+        self.primary_rays + self.secondary_rays + self.shadow_rays
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        if self.seconds > 0.0 {
+            self.total_rays() as f64 / self.seconds
+        } else {
+            0.0
+        }
+    }
+
+    /// Average number of rays followed along each path, not counting shadow rays
+    pub fn average_path_length(&self) -> f64 {
+        if self.primary_rays > 0 {
+            (self.primary_rays + self.secondary_rays) as f64 / self.primary_rays as f64
+        } else {
+            0.0
+        }
+    }
+
+    /// Human readable report, one statistic per line
+    pub fn report(&self) -> String {
+        let peak_memory = match self.peak_memory_bytes {
+            Some(bytes) => format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0)),
+            None => "unknown".to_string(),
+        };
+
+        format!(
+            "Primary rays:        {}\n\
+             Secondary rays:      {}\n\
+             Shadow rays:         {}\n\
+             Rays per second:     {:.0}\n\
+             Average path length: {:.2}\n\
+             Intersection tests:  {}\n\
+             BVH nodes visited:   {}\n\
+             Peak memory:         {}",
+            self.primary_rays,
+            self.secondary_rays,
+            self.shadow_rays,
+            self.rays_per_second(),
+            self.average_path_length(),
+            self.intersection_tests,
+            self.nodes_visited,
+            peak_memory
+        )
+    }
+
+    /// The same statistics as a JSON object
+    pub fn to_json(&self) -> String {
+        let peak_memory = match self.peak_memory_bytes {
+            Some(bytes) => bytes.to_string(),
+            None => "null".to_string(),
+        };
+
+        format!(
+            "{{\n  \"primary_rays\": {},\n  \"secondary_rays\": {},\n  \"shadow_rays\": {},\n  \
+             \"seconds\": {},\n  \"rays_per_second\": {},\n  \"average_path_length\": {},\n  \
+             \"intersection_tests\": {},\n  \"bvh_nodes_visited\": {},\n  \
+             \"peak_memory_bytes\": {}\n}}\n",
+            self.primary_rays,
+            self.secondary_rays,
+            self.shadow_rays,
+            self.seconds,
+            self.rays_per_second(),
+            self.average_path_length(),
+            self.intersection_tests,
+            self.nodes_visited,
+            peak_memory
+        )
+    }
+}
+
+// High water mark of the resident set size, only available on Linux
+fn peak_memory_bytes() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+
+    Some(kilobytes * 1024)
+}
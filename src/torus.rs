@@ -0,0 +1,343 @@
+use cgmath::prelude::*;
+use cgmath::{Vector2, Vector3};
+
+use std::{f32, f64};
+
+use aabb::Aabb;
+use hit::{HitRecord, Hittable, Solid, Span};
+use material::Material;
+use quadric::{azimuth, sweep, Crossings};
+use ray::Ray;
+use stats::{self, Counter};
+
+/// Ring around `center` lying flat in the xz-plane. `major_radius` is the distance from
+/// the center to the middle of the tube and `minor_radius` the thickness of the tube.
+/// With a `phi_max` below 360 degrees only that much of the ring is swept around the
+/// y-axis, starting from the x-axis towards the z-axis. Like a `Cylinder`, only a whole
+/// sweep is closed
+pub struct Torus {
+    center: Vector3<f32>,
+    major_radius: f32,
+    minor_radius: f32,
+    phi_max: f32,
+    material: Material,
+}
+
+impl Torus {
+    pub fn new(
+        center: Vector3<f32>,
+        major_radius: f32,
+        minor_radius: f32,
+        phi_max: f32,
+        material: Material,
+    ) -> Torus {
+        Torus {
+            center,
+            major_radius,
+            minor_radius,
+            phi_max: sweep(phi_max),
+            material,
+        }
+    }
+
+    // Every place the ray's line crosses the surface, including the ones behind the
+    // origin of the ray
+    fn crossings(&self, ray: Ray) -> Crossings {
+        stats::count(Counter::IntersectionTests);
+
+        let mut crossings = Crossings::new();
+
+        // Solved in double precision along a unit direction, which keeps the coefficients
+        // of the quartic in a sensible range
+        let length = f64::from(ray.direction().magnitude());
+        if length == 0.0 {
+            return crossings;
+        }
+        let direction = ray.direction().cast::<f64>().unwrap() / length;
+        let mut origin = (ray.origin() - self.center).cast::<f64>().unwrap();
+
+        let major = f64::from(self.major_radius);
+        let minor = f64::from(self.minor_radius);
+
+        // Far away the terms of the quartic are huge and cancel each other out. Start
+        // from the closest point to the center instead, any hit is within the bounding
+        // sphere from there
+        let shift = -origin.dot(direction);
+        origin += direction * shift;
+        if origin.magnitude2() > (major + minor) * (major + minor) {
+            return crossings;
+        }
+
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (p.x^2 + p.z^2) with p along the ray
+        let e = origin.magnitude2() + major * major - minor * minor;
+        let f = origin.dot(direction);
+        let four_r2 = 4.0 * major * major;
+
+        let roots = solve_quartic(
+            4.0 * f,
+            4.0 * f * f + 2.0 * e
+                - four_r2 * (direction.x * direction.x + direction.z * direction.z),
+            4.0 * e * f - 2.0 * four_r2 * (origin.x * direction.x + origin.z * direction.z),
+            e * e - four_r2 * (origin.x * origin.x + origin.z * origin.z),
+        );
+
+        for root in roots {
+            let t = ((root + shift) / length) as f32;
+
+            let local = ray.point_at_distance(t) - self.center;
+            let phi = azimuth(local.x, local.z);
+            if phi > self.phi_max {
+                continue;
+            }
+
+            // The normal points away from the closest point on the circle through the
+            // middle of the tube
+            let ring = Vector2::new(local.x, local.z).magnitude();
+            let middle = if ring > 0.0 {
+                Vector3::new(local.x, 0.0, local.z) * (self.major_radius / ring)
+            } else {
+                Vector3::new(self.major_radius, 0.0, 0.0)
+            };
+            let normal = (local - middle).normalize();
+
+            // Around the tube, starting from the outside of the ring and going up
+            let theta = azimuth(ring - self.major_radius, local.y);
+
+            crossings.add(
+                t,
+                normal,
+                Vector2::new(phi / self.phi_max, theta / (2.0 * f32::consts::PI)),
+            );
+        }
+
+        crossings
+    }
+}
+
+impl Hittable for Torus {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.crossings(ray).first(ray, t_min, t_max, self.material)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let extent = self.major_radius + self.minor_radius;
+
+        Aabb::new(
+            self.center + Vector3::new(-extent, -self.minor_radius, -extent),
+            self.center + Vector3::new(extent, self.minor_radius, extent),
+        )
+    }
+}
+
+impl Solid for Torus {
+    fn spans(&self, ray: Ray) -> Vec<Span<'_>> {
+        self.crossings(ray).spans(ray, self.material)
+    }
+}
+
+/// Real roots of x^4 + a x^3 + b x^2 + c x + d in increasing order.
+///
+/// Found with Ferrari's method, which splits the quartic into two quadratics using a root
+/// of a cubic. Every root is then polished with a few Newton steps on the original
+/// quartic, since the closed form loses precision when roots are close together
+fn solve_quartic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    // Substituting x = y - a / 4 leaves y^4 + p y^2 + q y + r
+    let a2 = a * a;
+    let p = b - 3.0 / 8.0 * a2;
+    let q = c - a * b / 2.0 + a2 * a / 8.0;
+    let r = d - a * c / 4.0 + a2 * b / 16.0 - 3.0 / 256.0 * a2 * a2;
+
+    let mut roots = Vec::with_capacity(4);
+
+    if q.abs() < 1e-12 {
+        // Quadratic in y^2
+        for z in solve_quadratic(1.0, p, r) {
+            if z >= 0.0 {
+                roots.push(z.sqrt());
+                roots.push(-z.sqrt());
+            }
+        }
+    } else {
+        // A positive m for which (y^2 + p / 2 + m)^2 = 2 m (y - q / (4 m))^2. The cubic is
+        // -q^2 at zero and grows without bound, so there always is one
+        let m = largest_cubic_root(p, p * p / 4.0 - r, -q * q / 8.0);
+        if m <= 0.0 {
+            return Vec::new();
+        }
+
+        let s = (2.0 * m).sqrt();
+        roots.extend(solve_quadratic(1.0, s, p / 2.0 + m - q / (2.0 * s)));
+        roots.extend(solve_quadratic(1.0, -s, p / 2.0 + m + q / (2.0 * s)));
+    }
+
+    let mut roots: Vec<f64> = roots
+        .into_iter()
+        .map(|y| polish(y - a / 4.0, a, b, c, d))
+        .collect();
+    roots.sort_by(|x, y| x.total_cmp(y));
+    roots
+}
+
+// Newton's method on the quartic, keeping the starting point if it goes nowhere useful
+fn polish(mut x: f64, a: f64, b: f64, c: f64, d: f64) -> f64 {
+    for _ in 0..4 {
+        let value = (((x + a) * x + b) * x + c) * x + d;
+        let slope = ((4.0 * x + 3.0 * a) * x + 2.0 * b) * x + c;
+        if slope == 0.0 {
+            break;
+        }
+
+        let next = x - value / slope;
+        if !next.is_finite() {
+            break;
+        }
+        x = next;
+    }
+
+    x
+}
+
+// Largest real root of x^3 + a x^2 + b x + c
+fn largest_cubic_root(a: f64, b: f64, c: f64) -> f64 {
+    let q = (a * a - 3.0 * b) / 9.0;
+    let r = (2.0 * a * a * a - 9.0 * a * b + 27.0 * c) / 54.0;
+    let q3 = q * q * q;
+
+    if r * r < q3 {
+        // Three real roots
+        let theta = (r / q3.sqrt()).clamp(-1.0, 1.0).acos();
+        let scale = -2.0 * q.sqrt();
+        let third = 2.0 * f64::consts::PI / 3.0;
+
+        [theta / 3.0, theta / 3.0 + third, theta / 3.0 - third]
+            .iter()
+            .map(|angle| scale * angle.cos() - a / 3.0)
+            .fold(f64::MIN, f64::max)
+    } else {
+        let s = -r.signum() * (r.abs() + (r * r - q3).sqrt()).cbrt();
+        let t = if s == 0.0 { 0.0 } else { q / s };
+        s + t - a / 3.0
+    }
+}
+
+// Real roots of a x^2 + b x + c
+fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return Vec::new();
+    }
+
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    if q == 0.0 {
+        vec![0.0, 0.0]
+    } else {
+        vec![q / a, c / q]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_roots(actual: Vec<f64>, expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len(), "{:?}", actual);
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert!((actual - expected).abs() < 1e-9, "{:?}", actual);
+        }
+    }
+
+    #[test]
+    fn quartic_with_four_roots() {
+        // (x + 3)(x + 1)(x - 2)(x - 5)
+        assert_roots(
+            solve_quartic(-3.0, -15.0, 19.0, 30.0),
+            &[-3.0, -1.0, 2.0, 5.0],
+        );
+    }
+
+    #[test]
+    fn quartic_with_two_roots() {
+        // (x^2 + 1)(x - 1)(x - 4)
+        assert_roots(solve_quartic(-5.0, 5.0, -5.0, 4.0), &[1.0, 4.0]);
+    }
+
+    #[test]
+    fn biquadratic() {
+        // (x^2 - 1)(x^2 - 9)
+        assert_roots(solve_quartic(0.0, -10.0, 0.0, 9.0), &[-3.0, -1.0, 1.0, 3.0]);
+    }
+
+    #[test]
+    fn ray_through_the_ring() {
+        let torus = Torus::new(
+            Vector3::zero(),
+            2.0,
+            0.5,
+            360.0,
+            Material::new_lambertian(0.5, 0.5, 0.5),
+        );
+
+        // Along the x-axis the ray crosses the tube twice on each side
+        let ray = Ray::new(Vector3::new(-10.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        let record = torus.hit(ray, 0.001, f32::MAX).unwrap();
+        assert!((record.t - 7.5).abs() < 1e-4);
+        assert!((record.normal - Vector3::new(-1.0, 0.0, 0.0)).magnitude() < 1e-4);
+
+        // Starting in the hole it hits the inside of the tube
+        let ray = Ray::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0));
+        let record = torus.hit(ray, 0.001, f32::MAX).unwrap();
+        assert!((record.t - 1.5).abs() < 1e-4);
+        assert!((record.normal - Vector3::new(0.0, 0.0, -1.0)).magnitude() < 1e-4);
+
+        // Straight down through the hole misses
+        let ray = Ray::new(Vector3::new(0.0, 5.0, 0.0), Vector3::new(0.0, -1.0, 0.0));
+        assert!(torus.hit(ray, 0.001, f32::MAX).is_none());
+    }
+
+    #[test]
+    fn spans_through_both_sides_of_the_tube() {
+        let torus = Torus::new(
+            Vector3::zero(),
+            2.0,
+            0.5,
+            360.0,
+            Material::new_lambertian(0.5, 0.5, 0.5),
+        );
+
+        let ray = Ray::new(Vector3::new(-10.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        let spans = torus.spans(ray);
+        let ends: Vec<(f32, f32)> = spans
+            .iter()
+            .map(|span| (span.enter.t, span.exit.t))
+            .collect();
+
+        assert_eq!(ends.len(), 2, "{:?}", ends);
+        for (actual, expected) in ends.iter().zip(&[(7.5, 8.5), (11.5, 12.5)]) {
+            assert!(
+                (actual.0 - expected.0).abs() < 1e-4 && (actual.1 - expected.1).abs() < 1e-4,
+                "{:?} != {:?}",
+                actual,
+                expected
+            );
+        }
+
+        // Both ends of a span face out of the tube
+        assert!((spans[0].enter.normal - Vector3::new(-1.0, 0.0, 0.0)).magnitude() < 1e-4);
+        assert!((spans[0].exit.normal - Vector3::new(1.0, 0.0, 0.0)).magnitude() < 1e-4);
+    }
+
+    #[test]
+    fn ray_from_far_away() {
+        let torus = Torus::new(
+            Vector3::new(0.0, 0.0, -1000.0),
+            1.0,
+            0.25,
+            360.0,
+            Material::new_lambertian(0.5, 0.5, 0.5),
+        );
+
+        let ray = Ray::new(Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -2.0));
+        let record = torus.hit(ray, 0.001, f32::MAX).unwrap();
+        assert!((record.position.z - -999.25).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,32 @@
+use aabb::Aabb;
+use hit::{HitRecord, Hittable};
+use medium::Medium;
+use ray::Ray;
+
+/// A participating medium filling the inside of a closed shape. The surface of the
+/// shape is invisible and only marks where rays enter and leave the medium
+pub struct Volume<M: Medium> {
+    boundary: Box<dyn Hittable + Sync>,
+    medium: M,
+}
+
+impl<M: Medium> Volume<M> {
+    pub fn new(boundary: Box<dyn Hittable + Sync>, medium: M) -> Self {
+        Volume { boundary, medium }
+    }
+}
+
+impl<M: Medium> Hittable for Volume<M> {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.boundary
+            .hit(ray, t_min, t_max)
+            .map(|record| HitRecord {
+                medium: Some(&self.medium),
+                ..record
+            })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.boundary.bounding_box()
+    }
+}
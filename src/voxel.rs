@@ -0,0 +1,237 @@
+use cgmath::Vector3;
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// A 3D grid of values sampled at the center of each voxel
+#[derive(Debug, Clone)]
+pub struct VoxelGrid {
+    resolution: [usize; 3],
+    // Stored with x changing fastest, then y, then z
+    values: Vec<f32>,
+}
+
+impl VoxelGrid {
+    pub fn new(resolution: [usize; 3], values: Vec<f32>) -> VoxelGrid {
+        assert!(resolution.iter().all(|&n| n > 0));
+        assert_eq!(values.len(), resolution[0] * resolution[1] * resolution[2]);
+
+        VoxelGrid { resolution, values }
+    }
+
+    /// Fill a grid by evaluating `f` at the center of each voxel, with coordinates
+    /// ranging from 0.0 to 1.0 across the grid
+    pub fn from_fn<F>(resolution: [usize; 3], f: F) -> VoxelGrid
+    where
+        F: Fn(Vector3<f32>) -> f32,
+    {
+        let mut values = Vec::with_capacity(resolution[0] * resolution[1] * resolution[2]);
+
+        for z in 0..resolution[2] {
+            for y in 0..resolution[1] {
+                for x in 0..resolution[0] {
+                    values.push(f(Vector3::new(
+                        (x as f32 + 0.5) / resolution[0] as f32,
+                        (y as f32 + 0.5) / resolution[1] as f32,
+                        (z as f32 + 0.5) / resolution[2] as f32,
+                    )));
+                }
+            }
+        }
+
+        VoxelGrid::new(resolution, values)
+    }
+
+    /// Load a grid from a file. Files ending in .raw are read as binary, anything else as text
+    ///
+    /// The text format is the three dimensions of the grid followed by every value, all
+    /// separated by whitespace. Lines starting with # are ignored.
+    ///
+    /// The raw format is the three dimensions as little endian u32s followed by every value
+    /// as a little endian f32.
+    ///
+    /// In both formats values are ordered with x changing fastest, then y, then z
+    pub fn load(path: &Path) -> io::Result<VoxelGrid> {
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("raw") => VoxelGrid::from_raw(&bytes),
+            _ => match String::from_utf8(bytes) {
+                Ok(text) => VoxelGrid::from_text(&text),
+                Err(_) => Err(invalid_data("text voxel grid is not valid UTF-8")),
+            },
+        }
+    }
+
+    /// Parse a grid in the text format described in `load`
+    pub fn from_text(text: &str) -> io::Result<VoxelGrid> {
+        let mut tokens = text
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .flat_map(|line| line.split_whitespace());
+
+        let mut resolution = [0; 3];
+        for n in &mut resolution {
+            *n = match tokens.next().map(|token| token.parse()) {
+                Some(Ok(n)) if n > 0 => n,
+                _ => return Err(invalid_data("voxel grid dimensions are missing or invalid")),
+            };
+        }
+
+        let values = tokens
+            .map(|token| token.parse())
+            .collect::<Result<Vec<f32>, _>>()
+            .map_err(|_| invalid_data("voxel grid contains a value that is not a number"))?;
+
+        check_values(resolution, &values)?;
+
+        Ok(VoxelGrid::new(resolution, values))
+    }
+
+    /// Parse a grid in the raw format described in `load`
+    pub fn from_raw(bytes: &[u8]) -> io::Result<VoxelGrid> {
+        let mut words = bytes.chunks(4).map(|chunk| {
+            if chunk.len() == 4 {
+                Ok([chunk[0], chunk[1], chunk[2], chunk[3]])
+            } else {
+                Err(invalid_data(
+                    "raw voxel grid length is not a multiple of 4 bytes",
+                ))
+            }
+        });
+
+        let mut resolution = [0; 3];
+        for n in &mut resolution {
+            *n = match words.next() {
+                Some(word) => u32::from_le_bytes(word?) as usize,
+                None => return Err(invalid_data("raw voxel grid header is truncated")),
+            };
+
+            if *n == 0 {
+                return Err(invalid_data("voxel grid dimensions must be positive"));
+            }
+        }
+
+        let values = words
+            .map(|word| word.map(f32::from_le_bytes))
+            .collect::<io::Result<Vec<f32>>>()?;
+
+        check_values(resolution, &values)?;
+
+        Ok(VoxelGrid::new(resolution, values))
+    }
+
+    /// Largest value in the grid
+    pub fn max_value(&self) -> f32 {
+        self.values.iter().cloned().fold(0.0, f32::max)
+    }
+
+    /// Trilinearly interpolated value at a position ranging from 0.0 to 1.0 across the grid.
+    /// Positions outside of the grid are zero
+    pub fn lookup(&self, position: Vector3<f32>) -> f32 {
+        if (0..3).any(|axis| position[axis] < 0.0 || position[axis] > 1.0) {
+            return 0.0;
+        }
+
+        // Shift by half a voxel since values are stored at voxel centers
+        let mut base = [0usize; 3];
+        let mut fraction = [0.0f32; 3];
+        for axis in 0..3 {
+            let n = self.resolution[axis];
+            let coordinate = (position[axis] * n as f32 - 0.5)
+                .max(0.0)
+                .min((n - 1) as f32);
+
+            base[axis] = (coordinate.floor() as usize).min(n - 1);
+            fraction[axis] = coordinate - base[axis] as f32;
+        }
+
+        let mut value = 0.0;
+        for corner in 0..8 {
+            let mut weight = 1.0;
+            let mut index = [0usize; 3];
+
+            for axis in 0..3 {
+                let upper = (corner >> axis) & 1 == 1;
+                index[axis] = (base[axis] + upper as usize).min(self.resolution[axis] - 1);
+                weight *= if upper {
+                    fraction[axis]
+                } else {
+                    1.0 - fraction[axis]
+                };
+            }
+
+            value += weight * self.voxel(index);
+        }
+
+        value
+    }
+
+    fn voxel(&self, index: [usize; 3]) -> f32 {
+        let [nx, ny, _] = self.resolution;
+
+        self.values[index[0] + nx * (index[1] + ny * index[2])]
+    }
+}
+
+// Grids hold densities and emission strengths, so values must also be finite and not negative
+fn check_values(resolution: [usize; 3], values: &[f32]) -> io::Result<()> {
+    let length = resolution[0]
+        .checked_mul(resolution[1])
+        .and_then(|length| length.checked_mul(resolution[2]));
+    if length != Some(values.len()) {
+        return Err(invalid_data(
+            "voxel grid has the wrong number of values for its dimensions",
+        ));
+    }
+
+    if values
+        .iter()
+        .all(|&value| value >= 0.0 && value.is_finite())
+    {
+        Ok(())
+    } else {
+        Err(invalid_data(
+            "voxel grid contains a value that is negative or not finite",
+        ))
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn text_grid() {
+        let grid = VoxelGrid::from_text("# density\n2 1 1\n0.25 0.75\n").unwrap();
+        assert_eq!(grid.resolution, [2, 1, 1]);
+        assert_eq!(grid.max_value(), 0.75);
+
+        assert!(VoxelGrid::from_text("2 1 1\n0.25\n").is_err());
+    }
+
+    #[test]
+    fn raw_dimensions_that_overflow_are_rejected() {
+        let bytes = raw(&[u32::MAX, u32::MAX, u32::MAX, 0]);
+        assert!(VoxelGrid::from_raw(&bytes).is_err());
+    }
+
+    #[test]
+    fn negative_and_nan_values_are_rejected() {
+        assert!(VoxelGrid::from_text("1 1 1\n-0.5\n").is_err());
+        assert!(VoxelGrid::from_text("1 1 1\nNaN\n").is_err());
+
+        let bytes = raw(&[1, 1, 1, f32::NAN.to_bits()]);
+        assert!(VoxelGrid::from_raw(&bytes).is_err());
+    }
+}
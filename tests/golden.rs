@@ -0,0 +1,243 @@
+//! Golden image tests. Each test renders a small image at a fixed seed and compares it to
+//! a reference image in `tests/golden`, so changes to materials, primitives or sampling
+//! can't change the output without anyone noticing.
+//!
+//! Renders that don't match are kept, along with an image of the difference, in the
+//! `golden` directory under cargo's temporary directory for integration tests. After a
+//! change that is meant to alter the output, regenerate the references with
+//! `UPDATE_GOLDEN=1 cargo test --test golden` and look over the new images before
+//! committing them.
+
+extern crate image;
+
+use image::RgbaImage;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const WIDTH: u32 = 48;
+const HEIGHT: u32 = 32;
+
+// Renders are deterministic, so on the machine that made the references they match
+// exactly. These leave room for small differences in floating point functions between
+// platforms, but not for anything visible
+const MAX_RMSE: f64 = 0.5;
+const MIN_SSIM: f64 = 0.995;
+
+#[test]
+fn path() {
+    check_golden("path", &["--integrator", "path", "--samples", "16"]);
+}
+
+#[test]
+fn path_stratified_mitchell() {
+    check_golden(
+        "path-stratified-mitchell",
+        &[
+            "--integrator",
+            "path",
+            "--samples",
+            "16",
+            "--sampler",
+            "stratified",
+            "--filter",
+            "mitchell",
+        ],
+    );
+}
+
+#[test]
+fn direct() {
+    check_golden("direct", &["--integrator", "direct", "--samples", "16"]);
+}
+
+#[test]
+fn ambient_occlusion() {
+    check_golden("ao", &["--integrator", "ao", "--samples", "16"]);
+}
+
+#[test]
+fn normals() {
+    check_golden("normals", &["--integrator", "normals", "--samples", "4"]);
+}
+
+#[test]
+fn depth() {
+    check_golden("depth", &["--integrator", "depth", "--samples", "4"]);
+}
+
+// The scenes above are all spheres, these cover the other kinds of primitives
+
+#[test]
+fn cornell() {
+    check_golden(
+        "cornell",
+        &[
+            "--scene",
+            "cornell",
+            "--integrator",
+            "direct",
+            "--samples",
+            "16",
+        ],
+    );
+}
+
+#[test]
+fn csg() {
+    check_golden(
+        "csg",
+        &["--scene", "csg", "--integrator", "path", "--samples", "16"],
+    );
+}
+
+#[test]
+fn sdf() {
+    check_golden(
+        "sdf",
+        &["--scene", "sdf", "--integrator", "path", "--samples", "16"],
+    );
+}
+
+#[test]
+fn quadrics() {
+    check_golden(
+        "quadrics",
+        &[
+            "--scene",
+            "quadrics",
+            "--integrator",
+            "path",
+            "--samples",
+            "16",
+        ],
+    );
+}
+
+#[test]
+fn terrain() {
+    check_golden(
+        "terrain",
+        &[
+            "--scene",
+            "terrain",
+            "--integrator",
+            "path",
+            "--samples",
+            "16",
+        ],
+    );
+}
+
+// Render an image with the given arguments and compare it to the reference with the
+// same name
+fn check_golden(name: &str, args: &[&str]) {
+    let output_directory = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    fs::create_dir_all(&output_directory).unwrap();
+
+    let output_path = output_directory.join(format!("{}.png", name));
+    render(&output_path, args);
+
+    let reference_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{}.png", name));
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::copy(&output_path, &reference_path).unwrap();
+        return;
+    }
+
+    let reference = match image::open(&reference_path) {
+        Ok(image) => image.to_rgba(),
+        Err(e) => panic!(
+            "could not open reference image {}: {}, run with UPDATE_GOLDEN=1 to create it",
+            reference_path.display(),
+            e
+        ),
+    };
+    let output = image::open(&output_path).unwrap().to_rgba();
+
+    assert_eq!(
+        reference.dimensions(),
+        output.dimensions(),
+        "{} has a different size than the reference",
+        name
+    );
+
+    let diff_path = output_directory.join(format!("{}.diff.png", name));
+    let rmse = rmse(&reference, &output);
+    let ssim = ssim(&reference_path, &output_path, &diff_path);
+
+    if rmse > MAX_RMSE || ssim < MIN_SSIM {
+        panic!(
+            "{} doesn't match the reference, RMSE is {:.3} (at most {}) and SSIM is {:.4} \
+             (at least {})\nreference: {}\nrender: {}\ndifference: {}",
+            name,
+            rmse,
+            MAX_RMSE,
+            ssim,
+            MIN_SSIM,
+            reference_path.display(),
+            output_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+fn render(output_path: &PathBuf, args: &[&str]) {
+    let width = WIDTH.to_string();
+    let height = HEIGHT.to_string();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ray-tracer"))
+        .args(["--resx", &width, "--resy", &height, "--seed", "0"])
+        .args(args)
+        .arg("--output")
+        .arg(output_path)
+        .output()
+        .unwrap();
+
+    assert!(
+        output.status.success(),
+        "render failed: {}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+}
+
+// Root mean squared error over the color channels, in 0..255
+fn rmse(a: &RgbaImage, b: &RgbaImage) -> f64 {
+    let mut sum = 0.0;
+    for (a, b) in a.pixels().zip(b.pixels()) {
+        for channel in 0..3 {
+            let difference = f64::from(a.data[channel]) - f64::from(b.data[channel]);
+            sum += difference * difference;
+        }
+    }
+
+    (sum / (3 * a.width() * a.height()) as f64).sqrt()
+}
+
+// SSIM as reported by the compare subcommand, which also writes a false color image of
+// the difference. The ray tracer is a binary without a library to link against, so this
+// runs it rather than keeping a second copy of SSIM here
+fn ssim(reference_path: &Path, output_path: &Path, diff_path: &Path) -> f64 {
+    let output = Command::new(env!("CARGO_BIN_EXE_ray-tracer"))
+        .arg("compare")
+        .arg(reference_path)
+        .arg(output_path)
+        .arg("--output")
+        .arg(diff_path)
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "compare failed: {}", stdout);
+
+    stdout
+        .lines()
+        .find(|line| line.starts_with("SSIM:"))
+        .and_then(|line| line["SSIM:".len()..].trim().parse().ok())
+        .unwrap_or_else(|| panic!("compare didn't report SSIM: {}", stdout))
+}